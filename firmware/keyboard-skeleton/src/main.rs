@@ -6,12 +6,9 @@ extern crate alloc;
 #[cfg(feature = "std")]
 use std::println;
 
-#[cfg(not(feature = "std"))]
-use alloc::vec;
-
 use proto::{
-    derive_nonce, seal_framed, validate_packet, DummyAead, Packet, PacketFlags, PacketHeader,
-    PacketKind, Payload, SessionKeys, SESSION_SALT_BYTES,
+    decode_header, BatteryConfig, BatteryMonitor, DummyAead, KeyReport, Session, SessionKeys,
+    HEADER_LEN, SESSION_SALT_BYTES,
 };
 
 #[cfg(feature = "std")]
@@ -28,38 +25,32 @@ fn main() {}
 pub fn keyboard_task() {
     let cfg = proto::demo_config();
     let session_id = 0xFE_ED_F0_0D;
-    let mut session = SessionKeys::new(session_id, [0x99; SESSION_SALT_BYTES]);
+    let mut session = Session::new(
+        cfg,
+        SessionKeys::new(session_id, [0x99; SESSION_SALT_BYTES]),
+        DummyAead,
+    );
 
     // Assume persisted counter from previous uptime.
-    session.resume_from(7);
-    let counter = session.next_counter().expect("counter not exhausted");
+    session.keys_mut().resume_from(7);
 
-    let key_report = Packet {
-        header: PacketHeader {
-            session_id,
-            counter,
-            kind: PacketKind::KeyReport,
-            flags: PacketFlags {
-                encrypted: true,
-                needs_ack: true,
-                retransmit: false,
-            },
-        },
-        payload: Payload::KeyReport { keys: vec![0x04] },
-        mac: vec![0x55; cfg.security.mac_len],
-    };
-
-    // Validate locally before transmit (mirrors firmware-side sanity checks).
-    validate_packet(&key_report, &cfg, Some(session_id), Some(counter - 1)).unwrap();
-
-    let nonce = derive_nonce(&session.salt, counter);
-    let aead = DummyAead;
-    let frame = seal_framed(&key_report, &cfg, &aead, &nonce).unwrap();
+    // Session fills in header, counter, nonce and tag; replay checks belong to the receiver.
+    let report = KeyReport::boot(0, &[0x04]).unwrap(); // 'a'
+    let frame = session.send(report.to_payload()).unwrap();
 
     #[cfg(feature = "std")]
     println!(
         "firmware skeleton: seq={} len={} (dummy-aead)",
-        counter,
+        decode_header(&frame[..HEADER_LEN]).unwrap().counter,
         frame.len()
     );
+
+    // Battery level piggybacks on the wake: sample the cell, send only when due.
+    let mut battery = BatteryMonitor::new(BatteryConfig::lipo());
+    let percent = battery.sample(3_950); // ADC reading in millivolts
+    if let Some(level) = battery.poll(0) {
+        session.send(level).unwrap();
+        #[cfg(feature = "std")]
+        println!("firmware skeleton: battery {}% sent", percent);
+    }
 }
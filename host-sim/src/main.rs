@@ -1,26 +1,33 @@
 use clap::Parser;
 use proto::{
-    associated_data, demo_config, derive_nonce, encode_header, encode_payload, sample_packets,
-    seal_framed, sim::MockRf, simulate_wake_sequence, validate_packet, DummyAead, RealAead,
-    SessionKeys, SimEvent, ValidationError, KEY_BYTES, MAX_RETRANSMIT_ATTEMPTS, SESSION_SALT_BYTES,
+    associated_data, decode_header, demo_config, encode_header, encode_payload, noise_public_key,
+    sample_packets, seal_framed, sim::MockRf, simulate_wake_sequence, validate_packet, Batcher,
+    CipherSuite, CoverTraffic, DummyAead, EstablishedSession, HidReport, KeyEventQueue, KeyReport,
+    NoiseInitiator, NoiseResponder, PacketKind, Payload, ProtocolConfig, ReplayWindow, Role,
+    Session, SessionKeys, SimEvent, SuiteAead, ValidationError, CAP_PADDING, CONSUMER_VOLUME_UP,
+    HEADER_LEN, KEY_BYTES, MAX_RETRANSMIT_ATTEMPTS, MODIFIER_LEFT_SHIFT, NONCE_BYTES,
+    SESSION_SALT_BYTES,
 };
 use serde::Serialize;
+use std::time::Duration;
 
 fn main() {
     let args = Args::parse();
-    let cfg = demo_config();
+    let mut cfg = demo_config();
+    cfg.security.cipher_suite = args.cipher_suite;
     let frames = simulate_wake_sequence(&cfg);
     let packets = sample_packets(&cfg);
     let use_real_aead = args.real_aead;
-    let aead_key = args.aead_key.unwrap_or([0x42; KEY_BYTES]);
-    let demo_salt = args.session_salt.unwrap_or([0xA5; SESSION_SALT_BYTES]);
+    let (established, init_len, response_len) = run_demo_handshake(&cfg);
+    let aead_key = args.aead_key.unwrap_or(established.key);
+    let demo_salt = args.session_salt.unwrap_or(established.session.salt);
     let mut rf = if args.mock_rf {
         Some(MockRf::new(args.drop_first, args.reorder, args.jitter_ms))
     } else {
         None
     };
     let aead: Box<dyn proto::Aead> = if use_real_aead {
-        Box::new(RealAead::new(aead_key))
+        Box::new(SuiteAead::new(cfg.security.cipher_suite, aead_key))
     } else {
         Box::new(DummyAead)
     };
@@ -40,6 +47,19 @@ fn main() {
         cfg.latency.target.as_millis(),
         cfg.latency.max.as_millis()
     );
+    println!(
+        "handshake (Noise NK): init={} bytes, response={} bytes, protocol v{}, features=0x{:x}, session=0x{:08x}{}",
+        init_len,
+        response_len,
+        established.version,
+        established.config.features,
+        established.session.session_id,
+        if args.aead_key.is_some() || args.session_salt.is_some() {
+            " (overridden by --aead-key/--session-salt)"
+        } else {
+            ""
+        }
+    );
     println!();
 
     for frame in frames {
@@ -60,7 +80,7 @@ fn main() {
     }
 
     println!("\nSample packet flow (header/payload/MAC expectations):");
-    let mut last_counter = None;
+    let mut window = ReplayWindow::new();
     let session_id = 0x88_77_66_55;
     for pkt in &packets {
         let header = &pkt.header;
@@ -74,7 +94,7 @@ fn main() {
             header.flags.retransmit,
         );
 
-        let verdict = validate_packet(&pkt, &cfg, Some(header.session_id), last_counter);
+        let verdict = validate_packet(pkt, &cfg, Some(header.session_id), Some(&window));
         match verdict {
             Ok(()) => println!(" -> ok"),
             Err(ValidationError::PayloadTooLarge) => println!(" -> payload too large"),
@@ -82,12 +102,17 @@ fn main() {
             Err(ValidationError::ReplayDetected) => println!(" -> replay"),
             Err(ValidationError::CounterJump) => println!(" -> counter jump"),
             Err(ValidationError::SessionMismatch) => println!(" -> session mismatch"),
+            Err(ValidationError::Unencrypted) => println!(" -> unencrypted data frame"),
+            Err(ValidationError::NotForwardSecure) => println!(" -> psk handshake not allowed"),
+            Err(ValidationError::NoReplayWindow) => println!(" -> no replay window"),
         }
 
-        last_counter = Some(header.counter);
+        window.record(header.counter);
     }
 
     let mut delivered_frames = Vec::new();
+    let uplink = SessionKeys::new(session_id, demo_salt);
+    let downlink = SessionKeys::for_role(session_id, demo_salt, Role::Dongle);
 
     println!("\nByte layout preview (header / payload / AAD / framed):");
     for pkt in packets {
@@ -107,13 +132,17 @@ fn main() {
         let hex: String = framed.iter().map(|b| format!("{:02x}", b)).collect();
         println!("  hex (plaintext framing): {}", hex);
 
-        let nonce = derive_nonce(&demo_salt, pkt.header.counter);
+        // Acks travel dongle -> keyboard and use the downlink nonce space.
+        let nonce = match pkt.header.kind {
+            PacketKind::Ack => downlink.nonce_for(pkt.header.counter),
+            _ => uplink.nonce_for(pkt.header.counter),
+        };
         let sealed = seal_framed(&pkt, &cfg, aead.as_ref(), &nonce).expect("seal");
         let sealed_hex: String = sealed.iter().map(|b| format!("{:02x}", b)).collect();
         println!(
             "  {} frame: {}",
             if use_real_aead {
-                "xchacha20-poly1305"
+                suite_name(cfg.security.cipher_suite)
             } else {
                 "dummy-aead"
             },
@@ -172,19 +201,147 @@ fn main() {
     }
 
     println!("\nWarm wake (cached session, no handshake):");
-    let mut warm_session = SessionKeys::new(session_id, [0xA5; SESSION_SALT_BYTES]);
+    let mut warm_session = Session::new(
+        cfg,
+        SessionKeys::new(session_id, [0xA5; SESSION_SALT_BYTES]),
+        aead.as_ref(),
+    );
     if let Some(resume) = args.resume_counter {
-        warm_session.resume_from(resume);
-    } else if let Some(next) = last_counter.map(|c| c + 1) {
-        warm_session.resume_from(next);
+        warm_session.keys_mut().resume_from(resume);
+    } else if let Some(next) = window.highest().map(|c| c + 1) {
+        warm_session.keys_mut().resume_from(next);
     }
-    let warm_counter = warm_session.next_counter().expect("counter not exhausted");
-    let nonce = warm_session.nonce_for(warm_counter);
+    let report = KeyReport::boot(MODIFIER_LEFT_SHIFT, &[0x04]).expect("boot report"); // 'A'
+    let warm_frame = warm_session
+        .send(report.to_payload())
+        .expect("warm-wake send");
+    let warm_counter = decode_header(&warm_frame[..HEADER_LEN])
+        .expect("header")
+        .counter;
+    let nonce = warm_session.keys().nonce_for(warm_counter);
     println!(
-        "- next seq={} uses deterministic nonce (salt || counter) prefix={:02x?}",
+        "- next seq={} frame_len={} uses deterministic nonce (salt || counter || direction || epoch) prefix={:02x?}",
         warm_counter,
+        warm_frame.len(),
         &nonce[..SESSION_SALT_BYTES]
     );
+    println!(
+        "- key report encoded={:02x?} hid boot report={:02x?}",
+        encode_payload(&report.to_payload()),
+        report.to_boot_report()
+    );
+    let volume = Payload::ConsumerControl {
+        usage: CONSUMER_VOLUME_UP,
+    };
+    println!(
+        "- volume up encoded={:02x?} hid consumer report={:02x?}",
+        encode_payload(&volume),
+        HidReport::from_payload(&volume)
+            .expect("consumer report")
+            .as_bytes()
+    );
+
+    // Shift+a tap as press/release events in one frame vs. one snapshot per state change.
+    let mut events = KeyEventQueue::new();
+    let mut snapshot = KeyReport::boot(0, &[]).expect("empty report");
+    let mut snapshot_bytes = 0;
+    for (usage, pressed) in [(0xE1, true), (0x04, true), (0x04, false), (0xE1, false)] {
+        events.push(usage, pressed).expect("queue has room");
+        if pressed {
+            snapshot.press(usage).expect("boot report has room");
+        } else {
+            snapshot.release(usage);
+        }
+        snapshot_bytes += warm_session
+            .send(snapshot.to_payload())
+            .expect("snapshot send")
+            .len();
+    }
+    let event_frame = warm_session
+        .send(events.batch(&cfg).to_payload())
+        .expect("event send");
+    println!(
+        "- shift+a tap: 1 event frame {} B (~{} us airtime) vs 4 snapshot frames {} B (~{} us)",
+        event_frame.len(),
+        event_frame.len() * 8,
+        snapshot_bytes,
+        snapshot_bytes * 8
+    );
+
+    // Key press and a knob detent within the batching delay: one frame instead of two.
+    let knob = Payload::Encoder {
+        encoder: 0,
+        detents: 1,
+    };
+    let mut batcher = Batcher::new(&cfg, cfg.latency.target);
+    let mut separate_bytes = 0;
+    for payload in [report.to_payload(), knob] {
+        batcher.push(&payload, 0).expect("batchable report");
+        separate_bytes += warm_session.send(payload).expect("report send").len();
+    }
+    let batch_frame = warm_session
+        .send(batcher.flush().expect("held reports"))
+        .expect("batch send");
+    println!(
+        "- key+knob: 1 batched frame {} B vs 2 frames {} B, held up to {} ms",
+        batch_frame.len(),
+        separate_bytes,
+        cfg.latency.target.as_millis()
+    );
+
+    // Privacy mode: every data frame padded to one size, a cover frame in each idle slot.
+    let mut private_cfg = cfg;
+    private_cfg.features |= CAP_PADDING;
+    let mut private_session = Session::new(
+        private_cfg,
+        SessionKeys::new(session_id, [0xA5; SESSION_SALT_BYTES]),
+        aead.as_ref(),
+    );
+    let plain_len = warm_session
+        .send(report.to_payload())
+        .expect("report send")
+        .len();
+    let padded_len = private_session
+        .send(report.to_payload())
+        .expect("padded send")
+        .len();
+    let interval = args
+        .cover_interval_ms
+        .map_or(cfg.latency.target, Duration::from_millis);
+    let mut cover = CoverTraffic::new(&private_cfg, interval, 0);
+    let mut batcher = Batcher::new(&private_cfg, private_cfg.latency.target);
+    let released = KeyReport::boot(0, &[]).expect("empty report").to_payload();
+    let (mut frames, mut cover_frames, mut reports) = (0, 0, 0);
+    // One second of typing at five keys per second, each key held for 80 ms.
+    for t in 0..1000u64 {
+        let change = match t % 200 {
+            0 => Some(report.to_payload()),
+            80 => Some(released.clone()),
+            _ => None,
+        };
+        if let Some(payload) = change {
+            reports += 1;
+            let full = batcher.push(&payload, t).expect("batchable report");
+            assert!(full.is_none(), "two reports fit one frame");
+        }
+        if let Some(payload) = cover.poll(t, || batcher.flush()) {
+            frames += 1;
+            if payload == Payload::KeepAlive {
+                cover_frames += 1;
+            }
+        }
+    }
+    println!(
+        "- privacy mode: {} B padded frames vs {} B; 1 s typing 5 keys/s sends {} frames ({} cover, {} ms slots) ~{:.0} uJ vs {} frames ~{:.0} uJ",
+        padded_len,
+        plain_len,
+        frames,
+        cover_frames,
+        cover.interval_ms(),
+        frames as f64 * frame_energy_uj(padded_len),
+        reports,
+        reports as f64 * frame_energy_uj(plain_len)
+    );
 
     if let Some(rf) = rf {
         let stats = rf.stats();
@@ -227,15 +384,19 @@ fn main() {
 
 #[derive(Parser, Debug)]
 struct Args {
-    /// Use real XChaCha20-Poly1305 AEAD instead of dummy tagger.
+    /// Use the cipher suite's real AEAD instead of dummy tagger.
     #[arg(long, default_value_t = false)]
     real_aead: bool,
 
-    /// 32-byte AEAD key as hex (64 chars). Defaults to 0x42 * 32.
+    /// Cipher suite: xchacha20-poly1305, chacha20-poly1305 or aes-128-ccm.
+    #[arg(long, value_parser = parse_suite, default_value = "xchacha20-poly1305")]
+    cipher_suite: CipherSuite,
+
+    /// 32-byte AEAD key as hex (64 chars). Defaults to the key from the demo handshake.
     #[arg(long, value_parser = parse_key)]
     aead_key: Option<[u8; KEY_BYTES]>,
 
-    /// 16-byte session salt as hex (32 chars). Defaults to 0xA5 * 16.
+    /// 16-byte session salt as hex (32 chars). Defaults to the salt from the demo handshake.
     #[arg(long, value_parser = parse_salt)]
     session_salt: Option<[u8; SESSION_SALT_BYTES]>,
 
@@ -259,11 +420,39 @@ struct Args {
     #[arg(long)]
     resume_counter: Option<u32>,
 
+    /// Cover traffic slot length in ms for the privacy-mode estimate (default: latency target).
+    #[arg(long)]
+    cover_interval_ms: Option<u64>,
+
     /// Path to write mock RF metrics CSV (optional).
     #[arg(long)]
     metrics_csv: Option<String>,
 }
 
+/// TX at 15 mA and 3.0 V for 8 us per byte (1 Mbps), plus ~10 uJ to receive the Ack
+/// (docs/energy.md).
+fn frame_energy_uj(frame_len: usize) -> f64 {
+    frame_len as f64 * 8.0 * 15e-3 * 3.0 + 10.0
+}
+
+/// Run the Noise NK handshake between fixed demo identities.
+/// Returns the keyboard's session plus the init and response frame lengths.
+fn run_demo_handshake(cfg: &ProtocolConfig) -> (EstablishedSession, usize, usize) {
+    let dongle_static = [0x5D; KEY_BYTES];
+    let mut keyboard = NoiseInitiator::new(
+        cfg,
+        noise_public_key(&dongle_static),
+        [0x3E; KEY_BYTES],
+        [0x4B; NONCE_BYTES],
+    );
+    let mut dongle = NoiseResponder::new(cfg, dongle_static, [0x6F; KEY_BYTES], 0x88_77_66_55);
+
+    let init = keyboard.start().expect("handshake init");
+    let (response, _) = dongle.respond(&init).expect("handshake response");
+    let established = keyboard.finish(&response).expect("handshake finish");
+    (established, init.len(), response.len())
+}
+
 #[derive(Serialize)]
 struct Metrics {
     attempts: Option<u32>,
@@ -277,6 +466,27 @@ struct Metrics {
     real_aead: bool,
 }
 
+const CIPHER_SUITES: [CipherSuite; 3] = [
+    CipherSuite::XChaCha20Poly1305,
+    CipherSuite::ChaCha20Poly1305,
+    CipherSuite::Aes128Ccm,
+];
+
+fn suite_name(suite: CipherSuite) -> &'static str {
+    match suite {
+        CipherSuite::XChaCha20Poly1305 => "xchacha20-poly1305",
+        CipherSuite::ChaCha20Poly1305 => "chacha20-poly1305",
+        CipherSuite::Aes128Ccm => "aes-128-ccm",
+    }
+}
+
+fn parse_suite(s: &str) -> Result<CipherSuite, String> {
+    CIPHER_SUITES
+        .into_iter()
+        .find(|suite| suite_name(*suite) == s)
+        .ok_or_else(|| format!("unknown cipher suite {s}"))
+}
+
 fn parse_key(s: &str) -> Result<[u8; KEY_BYTES], String> {
     let bytes = parse_hex_bytes(s, KEY_BYTES)?;
     Ok(bytes.try_into().unwrap())
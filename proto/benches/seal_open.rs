@@ -0,0 +1,134 @@
+//! Allocating `seal_framed`/`open_framed` vs the in-place `seal_framed_into`/`open_framed_in_place`.
+//!
+//! Run with `cargo bench -p proto --bench seal_open`. A counting global allocator reports heap
+//! allocations per packet; wall time per packet stands in for cycles on the host.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use proto::{
+    open_framed, open_framed_in_place, seal_framed, seal_framed_into, Aead, DummyAead, Packet,
+    PacketFlags, PacketHeader, PacketKind, Payload, RealAead, SessionKeys, HEADER_LEN, KEY_BYTES,
+    MAX_MAC_BYTES, SESSION_SALT_BYTES,
+};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const ITERATIONS: u32 = 200_000;
+
+struct Measurement {
+    ns_per_op: f64,
+    allocs_per_op: f64,
+}
+
+fn measure(mut op: impl FnMut()) -> Measurement {
+    // Warm up caches and any lazy initialisation outside the counted region.
+    for _ in 0..1_000 {
+        op();
+    }
+    let allocs_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        op();
+    }
+    let elapsed = start.elapsed();
+    let allocs = ALLOCATIONS.load(Ordering::Relaxed) - allocs_before;
+    Measurement {
+        ns_per_op: elapsed.as_nanos() as f64 / ITERATIONS as f64,
+        allocs_per_op: allocs as f64 / ITERATIONS as f64,
+    }
+}
+
+fn report(label: &str, path: &str, m: &Measurement) {
+    println!(
+        "{label:<12} {path:<10} {:>9.1} ns/packet {:>6.2} allocs/packet",
+        m.ns_per_op, m.allocs_per_op
+    );
+}
+
+fn bench_suite(label: &str, aead: &dyn Aead) {
+    let cfg = proto::demo_config();
+    let session = SessionKeys::new(0x0B_E4_C4_00, [0x5A; SESSION_SALT_BYTES]);
+    let counter = 42;
+    let nonce = session.nonce_for(counter);
+    let packet = Packet {
+        header: PacketHeader {
+            session_id: session.session_id,
+            counter,
+            kind: PacketKind::KeyReport,
+            flags: PacketFlags {
+                encrypted: true,
+                needs_ack: true,
+                retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
+            },
+        },
+        payload: Payload::KeyReport {
+            keys: vec![0x00, 0x00, 0x04, 0x05, 0x06, 0x00, 0x00, 0x00],
+        },
+        mac: vec![0; cfg.security.mac_len],
+    };
+    let frame = seal_framed(&packet, &cfg, aead, &nonce).expect("seal");
+
+    let alloc_path = measure(|| {
+        let sealed = seal_framed(black_box(&packet), &cfg, aead, &nonce).expect("seal");
+        let opened = open_framed(black_box(&sealed), &cfg, aead, &nonce).expect("open");
+        black_box(opened);
+    });
+
+    let mut buf = [0u8; HEADER_LEN + 2 + 32 + MAX_MAC_BYTES];
+    let in_place = measure(|| {
+        let len = seal_framed_into(black_box(&packet), &cfg, aead, &nonce, &mut buf).expect("seal");
+        let opened = open_framed_in_place(&mut buf[..len], &cfg, aead, &nonce).expect("open");
+        black_box(opened);
+    });
+
+    let mut out = [0u8; HEADER_LEN + 2 + 32 + MAX_MAC_BYTES];
+    let len = seal_framed_into(&packet, &cfg, aead, &nonce, &mut out).expect("seal");
+    assert_eq!(
+        &out[..len],
+        frame.as_slice(),
+        "both paths emit the same frame"
+    );
+    assert_eq!(
+        in_place.allocs_per_op, 0.0,
+        "in-place path must not allocate"
+    );
+
+    report(label, "alloc", &alloc_path);
+    report(label, "in-place", &in_place);
+    println!(
+        "{label:<12} speedup    {:>9.2}x",
+        alloc_path.ns_per_op / in_place.ns_per_op
+    );
+}
+
+fn main() {
+    println!("seal+open round trip, 8-byte key report, {ITERATIONS} iterations");
+    bench_suite("dummy-aead", &DummyAead);
+    bench_suite("xchacha20", &RealAead::new([0x42; KEY_BYTES]));
+}
@@ -1,12 +1,34 @@
+#[cfg(any(feature = "std", feature = "alloc"))]
 use crate::Vec;
+use crate::MAX_MAC_BYTES;
 #[cfg(feature = "crypto")]
-use crate::{MAX_MAC_BYTES, NONCE_BYTES};
+use crate::{CipherSuite, MAC_LENGTHS, NONCE_BYTES};
 #[cfg(feature = "crypto")]
-use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use aes::Aes128;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::vec;
 #[cfg(feature = "crypto")]
-use chacha20poly1305::{Key, Tag, XChaCha20Poly1305, XNonce};
+use ccm::Ccm;
+#[cfg(feature = "crypto")]
+use chacha20poly1305::aead::consts::{U12, U13, U16, U8};
+#[cfg(feature = "crypto")]
+use chacha20poly1305::aead::generic_array::{ArrayLength, GenericArray};
+#[cfg(feature = "crypto")]
+use chacha20poly1305::aead::{AeadInPlace, KeyInit, Nonce as AeadNonce};
+#[cfg(feature = "crypto")]
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, Tag, XChaCha20Poly1305, XNonce};
+#[cfg(feature = "crypto")]
+use hkdf::Hkdf;
+#[cfg(feature = "crypto")]
+use sha2::Sha256;
 use subtle::ConstantTimeEq;
 
+#[cfg(feature = "crypto")]
+const AES_128_KEY_BYTES: usize = 16;
+/// HKDF info for the AES-128-CCM key, so it shares no bits with the session key itself.
+#[cfg(feature = "crypto")]
+const AES_CCM_KEY_INFO: &[u8] = b"keyboard-project aes-128-ccm";
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum CryptoError {
     /// Authentication failed (MAC mismatch or decryption failure).
@@ -18,23 +40,115 @@ pub enum CryptoError {
     Serialize(crate::SerializationError),
 }
 
-/// Minimal AEAD interface for plugging in real XChaCha20-Poly1305 or dummy.
+/// Minimal AEAD interface for plugging in a `CipherSuite`'s cipher (`SuiteAead`) or the dummy.
+///
+/// The allocating `seal`/`open` need `std` or `alloc` and default to copying into the in-place
+/// detached methods, which are always available.
 pub trait Aead {
+    #[cfg(any(feature = "std", feature = "alloc"))]
     fn seal(
         &self,
         nonce: &[u8],
         aad: &[u8],
         plaintext: &[u8],
         mac_len: usize,
-    ) -> Result<(Vec<u8>, Vec<u8>), CryptoError>;
+    ) -> Result<(Vec<u8>, Vec<u8>), CryptoError> {
+        if mac_len > MAX_MAC_BYTES {
+            return Err(CryptoError::Serialize(
+                crate::SerializationError::MacLengthMismatch,
+            ));
+        }
+        let mut buf = plaintext.to_vec();
+        let mut mac = vec![0u8; mac_len];
+        self.seal_in_place_detached(nonce, aad, &mut buf, &mut mac)?;
+        Ok((buf, mac))
+    }
 
+    #[cfg(any(feature = "std", feature = "alloc"))]
     fn open(
         &self,
         nonce: &[u8],
         aad: &[u8],
         ciphertext: &[u8],
         mac: &[u8],
-    ) -> Result<Vec<u8>, CryptoError>;
+    ) -> Result<Vec<u8>, CryptoError> {
+        let mut buf = ciphertext.to_vec();
+        self.open_in_place_detached(nonce, aad, &mut buf, mac)?;
+        Ok(buf)
+    }
+
+    /// Encrypt `buf` in place and write the tag into `tag` (its length is the MAC length).
+    /// Allocation-free; backs `seal_framed_into`.
+    fn seal_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &mut [u8],
+    ) -> Result<(), CryptoError>;
+
+    /// Verify `tag` and decrypt `buf` in place. `buf` contents are unspecified on failure.
+    fn open_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), CryptoError>;
+
+    /// The same cipher keyed with `key`, for in-band rekeying (`Session::enable_rekey`).
+    /// `None`, the default, when the AEAD cannot be rebuilt from a session key.
+    fn with_key(&self, _key: &[u8; crate::KEY_BYTES]) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+/// Lets callers that pick the AEAD at runtime (`&dyn Aead`) hand it to generic code like `Session`.
+impl<T: Aead + ?Sized> Aead for &T {
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    fn seal(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+        mac_len: usize,
+    ) -> Result<(Vec<u8>, Vec<u8>), CryptoError> {
+        (**self).seal(nonce, aad, plaintext, mac_len)
+    }
+
+    #[cfg(any(feature = "std", feature = "alloc"))]
+    fn open(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        ciphertext: &[u8],
+        mac: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        (**self).open(nonce, aad, ciphertext, mac)
+    }
+
+    fn seal_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        (**self).seal_in_place_detached(nonce, aad, buf, tag)
+    }
+
+    fn open_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), CryptoError> {
+        (**self).open_in_place_detached(nonce, aad, buf, tag)
+    }
 }
 
 /// Deterministic, non-cryptographic AEAD for simulations and tests.
@@ -57,12 +171,14 @@ pub trait Aead {
 )]
 pub struct DummyAead;
 
+#[allow(deprecated)] // the warning is for users of DummyAead, not its own impls
 impl Default for DummyAead {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[allow(deprecated)]
 impl DummyAead {
     /// Create a new DummyAead instance.
     ///
@@ -90,7 +206,9 @@ impl DummyAead {
     }
 }
 
+#[allow(deprecated)]
 impl Aead for DummyAead {
+    #[cfg(any(feature = "std", feature = "alloc"))]
     fn seal(
         &self,
         nonce: &[u8],
@@ -102,6 +220,7 @@ impl Aead for DummyAead {
         Ok((plaintext.to_vec(), mac))
     }
 
+    #[cfg(any(feature = "std", feature = "alloc"))]
     fn open(
         &self,
         nonce: &[u8],
@@ -119,9 +238,51 @@ impl Aead for DummyAead {
             })
         }
     }
+
+    fn seal_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        simple_tag_into(aad, buf, nonce, tag);
+        Ok(())
+    }
+
+    fn open_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), CryptoError> {
+        let mut expected = [0u8; MAX_MAC_BYTES];
+        let expected = expected
+            .get_mut(..tag.len())
+            .ok_or(CryptoError::Parse(crate::ParseError::MacLengthMismatch))?;
+        simple_tag_into(aad, buf, nonce, expected);
+        // Use constant-time comparison to prevent timing attacks
+        if bool::from(expected.ct_eq(tag)) {
+            Ok(())
+        } else {
+            Err(CryptoError::AuthFailed {
+                context: "DummyAead::open_in_place_detached",
+            })
+        }
+    }
+
+    /// Keyless; epochs still differ through the nonce.
+    fn with_key(&self, _key: &[u8; crate::KEY_BYTES]) -> Option<Self> {
+        Some(DummyAead)
+    }
 }
 
 /// Real XChaCha20-Poly1305 AEAD.
+///
+/// Tags may be truncated to any of `MAC_LENGTHS`: the sender transmits a prefix of the full
+/// Poly1305 tag and the receiver recomputes the full tag and compares the prefix in constant
+/// time.
 #[cfg(feature = "crypto")]
 pub struct RealAead {
     cipher: XChaCha20Poly1305,
@@ -131,86 +292,380 @@ pub struct RealAead {
 impl RealAead {
     pub fn new(key: [u8; crate::KEY_BYTES]) -> Self {
         Self {
-            cipher: XChaCha20Poly1305::new(&Key::from_slice(&key)),
+            cipher: XChaCha20Poly1305::new(Key::from_slice(&key)),
         }
     }
 }
 
 #[cfg(feature = "crypto")]
 impl Aead for RealAead {
-    fn seal(
+    fn seal_in_place_detached(
         &self,
         nonce: &[u8],
         aad: &[u8],
-        plaintext: &[u8],
-        mac_len: usize,
-    ) -> Result<(Vec<u8>, Vec<u8>), CryptoError> {
-        if nonce.len() != NONCE_BYTES {
-            return Err(CryptoError::Parse(crate::ParseError::UnexpectedLength));
-        }
-        if mac_len != MAX_MAC_BYTES {
-            return Err(CryptoError::Serialize(
-                crate::SerializationError::MacLengthMismatch,
-            ));
-        }
+        buf: &mut [u8],
+        tag: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        let nonce = cipher_nonce::<NONCE_BYTES>(CipherSuite::XChaCha20Poly1305, nonce)?;
+        poly1305_seal(
+            &self.cipher,
+            XNonce::from_slice(&nonce),
+            aad,
+            buf,
+            tag,
+            "RealAead::seal",
+        )
+    }
 
-        let mut buf = plaintext.to_vec();
-        let tag = self
-            .cipher
-            .encrypt_in_place_detached(XNonce::from_slice(nonce), aad, &mut buf)
-            .map_err(|_| CryptoError::AuthFailed {
-                context: "RealAead::seal",
-            })?;
-        let mut mac = tag.to_vec();
-        if mac.len() != mac_len {
-            mac.truncate(mac_len.min(mac.len()));
+    fn open_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), CryptoError> {
+        let nonce = cipher_nonce::<NONCE_BYTES>(CipherSuite::XChaCha20Poly1305, nonce)?;
+        poly1305_open(
+            &self.cipher,
+            XNonce::from_slice(&nonce),
+            aad,
+            buf,
+            tag,
+            "RealAead::open",
+        )
+    }
+
+    fn with_key(&self, key: &[u8; crate::KEY_BYTES]) -> Option<Self> {
+        Some(Self::new(*key))
+    }
+}
+
+/// IETF ChaCha20-Poly1305 (RFC 8439), `CipherSuite::ChaCha20Poly1305`.
+///
+/// Same cipher as `RealAead` without the HChaCha20 subkey step; the 12-byte nonce comes from
+/// `CipherSuite::cipher_nonce`. Truncated tags are prefixes of the full tag, as for `RealAead`.
+#[cfg(feature = "crypto")]
+pub struct ChaCha20Aead {
+    cipher: ChaCha20Poly1305,
+}
+
+#[cfg(feature = "crypto")]
+impl ChaCha20Aead {
+    pub fn new(key: [u8; crate::KEY_BYTES]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
         }
+    }
+}
 
-        Ok((buf, mac))
+#[cfg(feature = "crypto")]
+impl Aead for ChaCha20Aead {
+    fn seal_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        let nonce = cipher_nonce::<12>(CipherSuite::ChaCha20Poly1305, nonce)?;
+        poly1305_seal(
+            &self.cipher,
+            Nonce::from_slice(&nonce),
+            aad,
+            buf,
+            tag,
+            "ChaCha20Aead::seal",
+        )
     }
 
-    fn open(
+    fn open_in_place_detached(
         &self,
         nonce: &[u8],
         aad: &[u8],
-        ciphertext: &[u8],
-        mac: &[u8],
-    ) -> Result<Vec<u8>, CryptoError> {
-        if nonce.len() != NONCE_BYTES {
-            return Err(CryptoError::Parse(crate::ParseError::UnexpectedLength));
+        buf: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), CryptoError> {
+        let nonce = cipher_nonce::<12>(CipherSuite::ChaCha20Poly1305, nonce)?;
+        poly1305_open(
+            &self.cipher,
+            Nonce::from_slice(&nonce),
+            aad,
+            buf,
+            tag,
+            "ChaCha20Aead::open",
+        )
+    }
+
+    fn with_key(&self, key: &[u8; crate::KEY_BYTES]) -> Option<Self> {
+        Some(Self::new(*key))
+    }
+}
+
+/// AES-128-CCM, `CipherSuite::Aes128Ccm`.
+///
+/// Standard CCM (RFC 3610, NIST SP 800-38C) with a 13-byte nonce and a 2-byte length field.
+///
+/// Deliberately *not* bit-compatible with the nRF52840 CCM peripheral, although the suite was
+/// requested that way. The peripheral only runs BLE link-layer CCM: a 4-byte MIC (below every
+/// `MAC_LENGTHS` entry), a single masked header byte as AAD (our AAD is the whole frame header),
+/// and a 39-bit packet counter in its nonce. Frames in that mode could not carry this protocol's
+/// header or tag, so matching it would mean a second, weaker wire format. Firmware that wants
+/// hardware AES runs CCM on the ECB peripheral instead, which yields exactly these bytes. CCM
+/// authenticates each tag length separately (`M` is part of the MAC input): an 8- or 12-byte tag
+/// is not a prefix of the 16-byte one. The key schedule is expanded per frame.
+#[cfg(feature = "crypto")]
+pub struct AesCcmAead {
+    key: [u8; AES_128_KEY_BYTES],
+}
+
+#[cfg(feature = "crypto")]
+impl AesCcmAead {
+    /// Derive the AES-128 key from the 32-byte session key:
+    /// `HKDF-SHA256(ikm = key, info = "keyboard-project aes-128-ccm")`.
+    pub fn new(key: [u8; crate::KEY_BYTES]) -> Self {
+        let mut aes_key = [0u8; AES_128_KEY_BYTES];
+        Hkdf::<Sha256>::new(None, &key)
+            .expand(AES_CCM_KEY_INFO, &mut aes_key)
+            .expect("16 bytes is a valid HKDF-SHA256 length");
+        Self::from_aes_key(aes_key)
+    }
+
+    /// Use an already-derived AES-128 key as is.
+    pub fn from_aes_key(key: [u8; AES_128_KEY_BYTES]) -> Self {
+        Self { key }
+    }
+
+    fn ccm<M: ArrayLength<u8> + ccm::TagSize>(&self) -> Ccm<Aes128, M, U13> {
+        Ccm::new(GenericArray::from_slice(&self.key))
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl Aead for AesCcmAead {
+    fn seal_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        let nonce = cipher_nonce::<13>(CipherSuite::Aes128Ccm, nonce)?;
+        let nonce = GenericArray::from_slice(&nonce);
+        let sealed = match tag.len() {
+            8 => self
+                .ccm::<U8>()
+                .encrypt_in_place_detached(nonce, aad, buf)
+                .map(|full| tag.copy_from_slice(&full)),
+            12 => self
+                .ccm::<U12>()
+                .encrypt_in_place_detached(nonce, aad, buf)
+                .map(|full| tag.copy_from_slice(&full)),
+            MAX_MAC_BYTES => self
+                .ccm::<U16>()
+                .encrypt_in_place_detached(nonce, aad, buf)
+                .map(|full| tag.copy_from_slice(&full)),
+            _ => {
+                return Err(CryptoError::Serialize(
+                    crate::SerializationError::MacLengthMismatch,
+                ))
+            }
+        };
+        sealed.map_err(|_| CryptoError::AuthFailed {
+            context: "AesCcmAead::seal",
+        })
+    }
+
+    fn open_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), CryptoError> {
+        let nonce = cipher_nonce::<13>(CipherSuite::Aes128Ccm, nonce)?;
+        let nonce = GenericArray::from_slice(&nonce);
+        let opened = match tag.len() {
+            8 => self.ccm::<U8>().decrypt_in_place_detached(
+                nonce,
+                aad,
+                buf,
+                GenericArray::from_slice(tag),
+            ),
+            12 => self.ccm::<U12>().decrypt_in_place_detached(
+                nonce,
+                aad,
+                buf,
+                GenericArray::from_slice(tag),
+            ),
+            MAX_MAC_BYTES => self.ccm::<U16>().decrypt_in_place_detached(
+                nonce,
+                aad,
+                buf,
+                GenericArray::from_slice(tag),
+            ),
+            _ => return Err(CryptoError::Parse(crate::ParseError::MacLengthMismatch)),
+        };
+        opened.map_err(|_| CryptoError::AuthFailed {
+            context: "AesCcmAead::open",
+        })
+    }
+
+    fn with_key(&self, key: &[u8; crate::KEY_BYTES]) -> Option<Self> {
+        Some(Self::new(*key))
+    }
+}
+
+/// The `Aead` for `SecurityConfig::cipher_suite`, chosen at runtime.
+///
+/// Usable directly as `Session`'s AEAD, or boxed where callers hold a `Box<dyn Aead>`.
+#[cfg(feature = "crypto")]
+pub enum SuiteAead {
+    XChaCha20Poly1305(RealAead),
+    ChaCha20Poly1305(ChaCha20Aead),
+    Aes128Ccm(AesCcmAead),
+}
+
+#[cfg(feature = "crypto")]
+impl SuiteAead {
+    pub fn new(suite: CipherSuite, key: [u8; crate::KEY_BYTES]) -> Self {
+        match suite {
+            CipherSuite::XChaCha20Poly1305 => Self::XChaCha20Poly1305(RealAead::new(key)),
+            CipherSuite::ChaCha20Poly1305 => Self::ChaCha20Poly1305(ChaCha20Aead::new(key)),
+            CipherSuite::Aes128Ccm => Self::Aes128Ccm(AesCcmAead::new(key)),
         }
-        if mac.len() != MAX_MAC_BYTES {
-            return Err(CryptoError::Parse(crate::ParseError::MacLengthMismatch));
+    }
+
+    pub fn suite(&self) -> CipherSuite {
+        match self {
+            Self::XChaCha20Poly1305(_) => CipherSuite::XChaCha20Poly1305,
+            Self::ChaCha20Poly1305(_) => CipherSuite::ChaCha20Poly1305,
+            Self::Aes128Ccm(_) => CipherSuite::Aes128Ccm,
         }
+    }
 
-        let mut buf = ciphertext.to_vec();
-        self.cipher
-            .decrypt_in_place_detached(
-                XNonce::from_slice(nonce),
-                aad,
-                &mut buf,
-                Tag::from_slice(mac),
-            )
-            .map_err(|_| CryptoError::AuthFailed {
-                context: "RealAead::open",
-            })?;
-        Ok(buf)
+    fn inner(&self) -> &dyn Aead {
+        match self {
+            Self::XChaCha20Poly1305(aead) => aead,
+            Self::ChaCha20Poly1305(aead) => aead,
+            Self::Aes128Ccm(aead) => aead,
+        }
     }
 }
 
+#[cfg(feature = "crypto")]
+impl Aead for SuiteAead {
+    fn seal_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        self.inner().seal_in_place_detached(nonce, aad, buf, tag)
+    }
+
+    fn open_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), CryptoError> {
+        self.inner().open_in_place_detached(nonce, aad, buf, tag)
+    }
+
+    fn with_key(&self, key: &[u8; crate::KEY_BYTES]) -> Option<Self> {
+        Some(Self::new(self.suite(), *key))
+    }
+}
+
+/// `suite`'s `N`-byte nonce (`CipherSuite::cipher_nonce`) for the protocol nonce `nonce`.
+#[cfg(feature = "crypto")]
+fn cipher_nonce<const N: usize>(suite: CipherSuite, nonce: &[u8]) -> Result<[u8; N], CryptoError> {
+    debug_assert_eq!(suite.nonce_len(), N);
+    let nonce: &[u8; NONCE_BYTES] = nonce
+        .try_into()
+        .map_err(|_| CryptoError::Parse(crate::ParseError::UnexpectedLength))?;
+    let mut out = [0u8; N];
+    out.copy_from_slice(&suite.cipher_nonce(nonce)[..N]);
+    Ok(out)
+}
+
+/// Seal with a Poly1305 AEAD; a truncated `tag` gets a prefix of the full tag.
+#[cfg(feature = "crypto")]
+fn poly1305_seal<C: AeadInPlace<TagSize = U16>>(
+    cipher: &C,
+    nonce: &AeadNonce<C>,
+    aad: &[u8],
+    buf: &mut [u8],
+    tag: &mut [u8],
+    context: &'static str,
+) -> Result<(), CryptoError> {
+    if !MAC_LENGTHS.contains(&tag.len()) {
+        return Err(CryptoError::Serialize(
+            crate::SerializationError::MacLengthMismatch,
+        ));
+    }
+    let full = cipher
+        .encrypt_in_place_detached(nonce, aad, buf)
+        .map_err(|_| CryptoError::AuthFailed { context })?;
+    tag.copy_from_slice(&full[..tag.len()]);
+    Ok(())
+}
+
+/// Open with a Poly1305 AEAD, accepting a tag truncated to any of `MAC_LENGTHS`.
+#[cfg(feature = "crypto")]
+fn poly1305_open<C: AeadInPlace<TagSize = U16>>(
+    cipher: &C,
+    nonce: &AeadNonce<C>,
+    aad: &[u8],
+    buf: &mut [u8],
+    tag: &[u8],
+    context: &'static str,
+) -> Result<(), CryptoError> {
+    if !MAC_LENGTHS.contains(&tag.len()) {
+        return Err(CryptoError::Parse(crate::ParseError::MacLengthMismatch));
+    }
+    let failed = || CryptoError::AuthFailed { context };
+    if tag.len() == MAX_MAC_BYTES {
+        return cipher
+            .decrypt_in_place_detached(nonce, aad, buf, Tag::from_slice(tag))
+            .map_err(|_| failed());
+    }
+
+    // Truncated tag: the AEAD only verifies full tags, so recompute the sender's. Encryption
+    // XORs the keystream, so sealing the ciphertext yields the plaintext, and sealing that
+    // plaintext restores the ciphertext along with the full tag it was sent with. Plaintext
+    // is only left in `buf` once the tag matched.
+    let encrypt = |buf: &mut [u8]| {
+        cipher
+            .encrypt_in_place_detached(nonce, aad, buf)
+            .map_err(|_| failed())
+    };
+    encrypt(buf)?;
+    let full = encrypt(buf)?;
+    if !bool::from(full[..tag.len()].ct_eq(tag)) {
+        return Err(failed());
+    }
+    encrypt(buf)?;
+    Ok(())
+}
+
+#[cfg(any(feature = "std", feature = "alloc"))]
 fn simple_tag(aad: &[u8], payload: &[u8], nonce: &[u8], mac_len: usize) -> Vec<u8> {
+    let mut out = vec![0u8; mac_len];
+    simple_tag_into(aad, payload, nonce, &mut out);
+    out
+}
+
+fn simple_tag_into(aad: &[u8], payload: &[u8], nonce: &[u8], out: &mut [u8]) {
     let mut state: u32 = 0xA5A5_5A5A;
     for b in aad.iter().chain(payload).chain(nonce) {
         state = state.rotate_left(5) ^ (*b as u32);
         state = state.wrapping_mul(0x45d9f3b);
     }
-    let mut out = Vec::with_capacity(mac_len);
-    let mut bytes = state.to_le_bytes();
-    while out.len() < mac_len {
-        out.extend_from_slice(&bytes);
+    for chunk in out.chunks_mut(4) {
+        chunk.copy_from_slice(&state.to_le_bytes()[..chunk.len()]);
         state = state.rotate_left(7) ^ 0xA5A5_A5A5;
-        bytes = state.to_le_bytes();
     }
-    out.truncate(mac_len);
-    out
 }
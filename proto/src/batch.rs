@@ -0,0 +1,179 @@
+//! Frame batching: several report payloads under one header and one MAC.
+//!
+//! Every frame spends `HEADER_LEN`, the length field and the MAC (28 bytes at the default tag)
+//! before its payload, more than most reports take themselves. During a typing burst, or a key
+//! press next to an encoder turn, the keyboard's `Batcher` holds reports for a bounded delay and
+//! sends the ones that pile up as a single `Batch` frame. No report waits longer than
+//! `max_delay`, itself capped at `LatencyBudget::target`, and a report still alone when the
+//! delay runs out goes out as its ordinary payload.
+//!
+//! The batch is one frame on the link: one counter, acked and retransmitted as a whole.
+//!
+//! Wire encoding, `Payload::Batch` under `PacketKind::Batch`: one or more records
+//! `kind || len || payload`, where `kind` is the header kind byte of a batchable payload
+//! (`PacketKind::batchable`) and `payload` its usual encoding. The dongle walks them in order
+//! with `batch_records`.
+
+use crate::{
+    data_payload_limit, decode_payload_ref, encode_payload_into, kind_from_byte, payload_bytes,
+    payload_len, ParseError, Payload, PayloadRef, ProtocolConfig, MAX_PAYLOAD_BYTES,
+};
+#[cfg(not(feature = "std"))]
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+/// Kind and length bytes in front of each record.
+pub const BATCH_RECORD_HEADER_BYTES: usize = 2;
+
+/// Smallest record (a `SystemControl` usage); less room than this and the batch is full.
+const MIN_RECORD_BYTES: usize = BATCH_RECORD_HEADER_BYTES + 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BatchError {
+    /// Handshake, Ack, KeepAlive and Batch payloads go out on their own.
+    NotBatchable,
+    /// Payload plus its record header exceeds the frame; send it on its own.
+    TooLarge,
+}
+
+/// Typed view of `Payload::Batch` records, in the order they were pushed.
+pub fn batch_records(records: &[u8]) -> BatchRecords<'_> {
+    BatchRecords { rest: records }
+}
+
+pub struct BatchRecords<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> BatchRecords<'a> {
+    fn split_record(&mut self) -> Result<PayloadRef<'a>, ParseError> {
+        let (&[code, len], rest) = self
+            .rest
+            .split_first_chunk::<BATCH_RECORD_HEADER_BYTES>()
+            .ok_or(ParseError::UnexpectedLength)?;
+        let kind = kind_from_byte(code)?;
+        if !kind.batchable() {
+            return Err(ParseError::NotBatchable(code));
+        }
+        if rest.len() < usize::from(len) {
+            return Err(ParseError::UnexpectedLength);
+        }
+        let (payload, rest) = rest.split_at(usize::from(len));
+        self.rest = rest;
+        decode_payload_ref(kind, payload)
+    }
+}
+
+impl<'a> Iterator for BatchRecords<'a> {
+    type Item = Result<PayloadRef<'a>, ParseError>;
+
+    /// Stops after the first malformed record.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let record = self.split_record();
+        if record.is_err() {
+            self.rest = &[];
+        }
+        Some(record)
+    }
+}
+
+/// Keyboard side: holds reports until the frame is full or the oldest has waited `max_delay`.
+pub struct Batcher {
+    limit: usize,
+    max_delay_ms: u64,
+    records: heapless::Vec<u8, MAX_PAYLOAD_BYTES>,
+    count: usize,
+    held_since_ms: u64,
+}
+
+impl Batcher {
+    /// `max_delay` is how long a report may wait for others to share its frame; capped at
+    /// `cfg.latency.target`. Zero sends every report on the next `poll`.
+    pub fn new(cfg: &ProtocolConfig, max_delay: Duration) -> Self {
+        Self {
+            limit: data_payload_limit(cfg).min(MAX_PAYLOAD_BYTES),
+            max_delay_ms: max_delay.min(cfg.latency.target).as_millis() as u64,
+            records: heapless::Vec::new(),
+            count: 0,
+            held_since_ms: 0,
+        }
+    }
+
+    /// Hold `payload`. Returns a payload to send now when the held reports fill the frame, or
+    /// when `payload` did not fit next to them (it then starts the next batch).
+    pub fn push(&mut self, payload: &Payload, now_ms: u64) -> Result<Option<Payload>, BatchError> {
+        if !payload.kind().batchable() {
+            return Err(BatchError::NotBatchable);
+        }
+        let len = payload_len(payload);
+        if BATCH_RECORD_HEADER_BYTES + len > self.limit {
+            return Err(BatchError::TooLarge);
+        }
+        let ready = if self.records.len() + BATCH_RECORD_HEADER_BYTES + len > self.limit {
+            self.flush()
+        } else {
+            None
+        };
+
+        if self.records.is_empty() {
+            self.held_since_ms = now_ms;
+        }
+        let start = self.records.len() + BATCH_RECORD_HEADER_BYTES;
+        self.records
+            .resize(start + len, 0)
+            .expect("checked against the frame limit");
+        self.records[start - 2] = payload.kind() as u8;
+        self.records[start - 1] = len as u8;
+        encode_payload_into(payload, &mut self.records[start..]).expect("sized by payload_len");
+        self.count += 1;
+
+        if ready.is_some() {
+            return Ok(ready);
+        }
+        if self.limit - self.records.len() < MIN_RECORD_BYTES {
+            return Ok(self.flush());
+        }
+        Ok(None)
+    }
+
+    /// The held reports, once the oldest has waited `max_delay`.
+    pub fn poll(&mut self, now_ms: u64) -> Option<Payload> {
+        match self.deadline_ms() {
+            Some(deadline) if now_ms >= deadline => self.flush(),
+            _ => None,
+        }
+    }
+
+    /// When `poll` will next return a payload; `None` while nothing is held.
+    pub fn deadline_ms(&self) -> Option<u64> {
+        (!self.records.is_empty()).then_some(self.held_since_ms + self.max_delay_ms)
+    }
+
+    /// Everything held, now: the report itself when only one is held, otherwise a `Batch`.
+    pub fn flush(&mut self) -> Option<Payload> {
+        if self.records.is_empty() {
+            return None;
+        }
+        let payload = if self.count == 1 {
+            let kind = kind_from_byte(self.records[0]).expect("written by push");
+            decode_payload_ref(kind, &self.records[BATCH_RECORD_HEADER_BYTES..])
+                .expect("encoded by push")
+                .to_owned()
+        } else {
+            Payload::Batch {
+                records: payload_bytes(&self.records).expect("within MAX_PAYLOAD_BYTES"),
+            }
+        };
+        self.records.clear();
+        self.count = 0;
+        Some(payload)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
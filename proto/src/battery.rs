@@ -0,0 +1,155 @@
+//! Battery state: raw cell voltage to percentage, reported over `ControlMessage::BatteryLevel`.
+//!
+//! A LiPo cell's voltage is far from linear in remaining charge (flat around 3.8 V, steep at both
+//! ends), so the percentage comes from a discharge curve: `(millivolts, percent)` points,
+//! interpolated linearly in between. Radio bursts sag the cell by a few tens of millivolts, so the
+//! reported value only moves once the reading differs by the configured hysteresis; otherwise the
+//! host's battery indicator would flicker with every keypress.
+
+use core::time::Duration;
+
+use crate::{ControlMessage, Payload};
+
+/// One point of a discharge curve.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CurvePoint {
+    pub millivolts: u16,
+    pub percent: u8,
+}
+
+const fn point(millivolts: u16, percent: u8) -> CurvePoint {
+    CurvePoint {
+        millivolts,
+        percent,
+    }
+}
+
+/// Typical single-cell LiPo under light load, highest voltage first.
+pub const LIPO_DISCHARGE_CURVE: &[CurvePoint] = &[
+    point(4200, 100),
+    point(4150, 95),
+    point(4110, 90),
+    point(4080, 85),
+    point(4020, 80),
+    point(3980, 70),
+    point(3950, 60),
+    point(3910, 50),
+    point(3870, 40),
+    point(3850, 30),
+    point(3830, 20),
+    point(3790, 10),
+    point(3700, 5),
+    point(3500, 0),
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct BatteryConfig {
+    /// Discharge curve sorted by descending voltage (and percent).
+    pub curve: &'static [CurvePoint],
+    /// Smallest change in percent that replaces the reported value.
+    pub hysteresis_pct: u8,
+    /// How often `BatteryMonitor::poll` re-sends an unchanged level.
+    pub report_interval: Duration,
+}
+
+impl BatteryConfig {
+    /// `LIPO_DISCHARGE_CURVE`, 2 % hysteresis, one report a minute.
+    pub fn lipo() -> Self {
+        Self {
+            curve: LIPO_DISCHARGE_CURVE,
+            hysteresis_pct: 2,
+            report_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Percentage for a cell voltage on `curve`; clamps outside the curve, 0 for an empty curve.
+pub fn millivolts_to_percent(curve: &[CurvePoint], millivolts: u16) -> u8 {
+    let (Some(top), Some(bottom)) = (curve.first(), curve.last()) else {
+        return 0;
+    };
+    if millivolts >= top.millivolts {
+        return top.percent;
+    }
+    if millivolts <= bottom.millivolts {
+        return bottom.percent;
+    }
+    let upper_index = curve
+        .iter()
+        .rposition(|p| p.millivolts >= millivolts)
+        .expect("top point is above the sample");
+    let upper = curve[upper_index];
+    let lower = curve[upper_index + 1];
+    if upper.millivolts == millivolts {
+        return upper.percent;
+    }
+    let span_mv = u32::from(upper.millivolts - lower.millivolts);
+    let span_pct = u32::from(upper.percent.saturating_sub(lower.percent));
+    let above_lower = u32::from(millivolts - lower.millivolts);
+    // Round to nearest.
+    lower.percent + ((above_lower * span_pct + span_mv / 2) / span_mv) as u8
+}
+
+/// Keyboard side: turns ADC samples into a steady percentage and schedules its reports.
+#[derive(Clone, Debug)]
+pub struct BatteryMonitor {
+    cfg: BatteryConfig,
+    percent: Option<u8>,
+    millivolts: u16,
+    dirty: bool,
+    last_report_ms: Option<u64>,
+}
+
+impl BatteryMonitor {
+    pub fn new(cfg: BatteryConfig) -> Self {
+        Self {
+            cfg,
+            percent: None,
+            millivolts: 0,
+            dirty: false,
+            last_report_ms: None,
+        }
+    }
+
+    /// Feed a cell voltage sample; returns the (hysteresis-filtered) percentage.
+    pub fn sample(&mut self, millivolts: u16) -> u8 {
+        let percent = millivolts_to_percent(self.cfg.curve, millivolts);
+        self.millivolts = millivolts;
+        match self.percent {
+            Some(reported) if reported.abs_diff(percent) < self.cfg.hysteresis_pct => reported,
+            _ => {
+                self.dirty |= self.percent != Some(percent);
+                self.percent = Some(percent);
+                percent
+            }
+        }
+    }
+
+    /// Current percentage, `None` before the first sample.
+    pub fn percent(&self) -> Option<u8> {
+        self.percent
+    }
+
+    /// A `BatteryLevel` Control payload when the level changed or `report_interval` has passed
+    /// since the last one.
+    pub fn poll(&mut self, now_ms: u64) -> Option<Payload> {
+        let percent = self.percent?;
+        let interval_ms = self.cfg.report_interval.as_millis() as u64;
+        let due = match self.last_report_ms {
+            None => true,
+            Some(last) => self.dirty || now_ms.saturating_sub(last) >= interval_ms,
+        };
+        if !due {
+            return None;
+        }
+        self.dirty = false;
+        self.last_report_ms = Some(now_ms);
+        Some(
+            ControlMessage::BatteryLevel {
+                percent,
+                millivolts: self.millivolts,
+            }
+            .to_payload(),
+        )
+    }
+}
@@ -0,0 +1,254 @@
+//! Registry of `PacketKind::Control` messages: stable codes with typed encode/decode.
+//!
+//! Every command a feature needs gets a code here instead of a private magic number. Codes
+//! `0x01..=0x0F` are device and link management, `0x10..=0x1F` session maintenance (rekey,
+//! resync). A code this build does not know decodes as `ControlMessage::Unknown` and encodes
+//! back to the same bytes, so an older dongle can log or relay commands from newer firmware.
+//!
+//! Data layouts (multi-byte fields LE):
+//! - `0x01 BatteryLevel`: `percent || millivolts u16`
+//! - `0x02 LedState`: `leds` (HID LED bitmap)
+//! - `0x03 Unpair`, `0x04 GetStats`, `0x07 CapabilityQuery`: empty
+//! - `0x05 Stats`: `frames_sent u32 || retransmits u32 || rejected u32`
+//! - `0x06 SetConfig`: `key || value u32`
+//! - `0x08 Capabilities`: `features u32` (`CAP_*` bits)
+//! - `0x10 RekeyRequest`, `0x11 RekeyConfirm`: `epoch u16`
+//! - `0x12 ResyncRequest`, `0x13 ResyncResponse`: `challenge u32`
+
+use crate::{
+    payload_bytes, Payload, PayloadBytes, RekeyMessage, ResyncMessage, CONTROL_REKEY_CONFIRM,
+    CONTROL_REKEY_REQUEST, CONTROL_RESYNC_REQUEST, CONTROL_RESYNC_RESPONSE,
+};
+
+pub const CONTROL_BATTERY_LEVEL: u8 = 0x01;
+pub const CONTROL_LED_STATE: u8 = 0x02;
+pub const CONTROL_UNPAIR: u8 = 0x03;
+pub const CONTROL_GET_STATS: u8 = 0x04;
+pub const CONTROL_STATS: u8 = 0x05;
+pub const CONTROL_SET_CONFIG: u8 = 0x06;
+pub const CONTROL_CAPABILITY_QUERY: u8 = 0x07;
+pub const CONTROL_CAPABILITIES: u8 = 0x08;
+
+/// Key reports may use the NKRO bitmap format.
+pub const CAP_NKRO: u32 = 1 << 0;
+/// Key reports may use the press/release event format.
+pub const CAP_KEY_EVENTS: u32 = 1 << 1;
+/// `ConsumerControl` and `SystemControl` payloads.
+pub const CAP_CONSUMER_CONTROL: u32 = 1 << 2;
+/// `Pointer` payloads.
+pub const CAP_POINTER: u32 = 1 << 3;
+/// `Encoder` payloads.
+pub const CAP_ENCODER: u32 = 1 << 4;
+/// In-band rekey over Control.
+pub const CAP_REKEY: u32 = 1 << 5;
+/// Control messages larger than one frame, split with the fragment header flags.
+pub const CAP_FRAGMENTS: u32 = 1 << 6;
+/// Data frames padded to a constant size (`Padded` payloads).
+pub const CAP_PADDING: u32 = 1 << 7;
+
+/// Largest data field of a known message (`Stats`).
+const CONTROL_DATA_MAX_BYTES: usize = 12;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ControlError {
+    /// Payload is not `Payload::Control`.
+    NotControl,
+    /// Known code with data of the wrong length.
+    UnexpectedLength,
+}
+
+/// Link counters reported in answer to `GetStats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub frames_sent: u32,
+    pub retransmits: u32,
+    /// Received frames dropped for failing authentication or validation.
+    pub rejected: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ControlMessage {
+    BatteryLevel {
+        percent: u8,
+        millivolts: u16,
+    },
+    /// Host keyboard LED state, forwarded by the dongle.
+    LedState {
+        leds: u8,
+    },
+    /// Forget the pairing and return to the pairing state.
+    Unpair,
+    GetStats,
+    Stats(LinkStats),
+    /// Set tunable `key` to `value`; keys are defined by the receiving firmware.
+    SetConfig {
+        key: u8,
+        value: u32,
+    },
+    CapabilityQuery,
+    Capabilities {
+        features: u32,
+    },
+    Rekey(RekeyMessage),
+    Resync(ResyncMessage),
+    /// Code this build does not know, kept verbatim.
+    Unknown {
+        code: u8,
+        data: PayloadBytes,
+    },
+}
+
+impl ControlMessage {
+    pub fn code(&self) -> u8 {
+        match self {
+            ControlMessage::BatteryLevel { .. } => CONTROL_BATTERY_LEVEL,
+            ControlMessage::LedState { .. } => CONTROL_LED_STATE,
+            ControlMessage::Unpair => CONTROL_UNPAIR,
+            ControlMessage::GetStats => CONTROL_GET_STATS,
+            ControlMessage::Stats(_) => CONTROL_STATS,
+            ControlMessage::SetConfig { .. } => CONTROL_SET_CONFIG,
+            ControlMessage::CapabilityQuery => CONTROL_CAPABILITY_QUERY,
+            ControlMessage::Capabilities { .. } => CONTROL_CAPABILITIES,
+            ControlMessage::Rekey(RekeyMessage::Request { .. }) => CONTROL_REKEY_REQUEST,
+            ControlMessage::Rekey(RekeyMessage::Confirm { .. }) => CONTROL_REKEY_CONFIRM,
+            ControlMessage::Resync(ResyncMessage::Request { .. }) => CONTROL_RESYNC_REQUEST,
+            ControlMessage::Resync(ResyncMessage::Response { .. }) => CONTROL_RESYNC_RESPONSE,
+            ControlMessage::Unknown { code, .. } => *code,
+        }
+    }
+
+    pub fn to_payload(&self) -> Payload {
+        let code = self.code();
+        if let ControlMessage::Unknown { data, .. } = self {
+            return Payload::Control {
+                code,
+                data: data.clone(),
+            };
+        }
+        let mut buf = [0u8; CONTROL_DATA_MAX_BYTES];
+        let len = match self {
+            ControlMessage::BatteryLevel {
+                percent,
+                millivolts,
+            } => {
+                buf[0] = *percent;
+                buf[1..3].copy_from_slice(&millivolts.to_le_bytes());
+                3
+            }
+            ControlMessage::LedState { leds } => {
+                buf[0] = *leds;
+                1
+            }
+            ControlMessage::Unpair
+            | ControlMessage::GetStats
+            | ControlMessage::CapabilityQuery
+            | ControlMessage::Unknown { .. } => 0,
+            ControlMessage::Stats(stats) => {
+                buf[..4].copy_from_slice(&stats.frames_sent.to_le_bytes());
+                buf[4..8].copy_from_slice(&stats.retransmits.to_le_bytes());
+                buf[8..12].copy_from_slice(&stats.rejected.to_le_bytes());
+                12
+            }
+            ControlMessage::SetConfig { key, value } => {
+                buf[0] = *key;
+                buf[1..5].copy_from_slice(&value.to_le_bytes());
+                5
+            }
+            ControlMessage::Capabilities { features } => {
+                buf[..4].copy_from_slice(&features.to_le_bytes());
+                4
+            }
+            ControlMessage::Rekey(
+                RekeyMessage::Request { epoch } | RekeyMessage::Confirm { epoch },
+            ) => {
+                buf[..2].copy_from_slice(&epoch.to_le_bytes());
+                2
+            }
+            ControlMessage::Resync(
+                ResyncMessage::Request { challenge } | ResyncMessage::Response { challenge },
+            ) => {
+                buf[..4].copy_from_slice(&challenge.to_le_bytes());
+                4
+            }
+        };
+        Payload::Control {
+            code,
+            data: payload_bytes(&buf[..len]).expect("known Control data fits any payload buffer"),
+        }
+    }
+
+    /// Decode the code and data of a Control payload; unknown codes are kept verbatim.
+    pub fn decode(code: u8, data: &[u8]) -> Result<Self, ControlError> {
+        let msg = match code {
+            CONTROL_BATTERY_LEVEL => {
+                let [percent, mv_lo, mv_hi] = fixed(data)?;
+                ControlMessage::BatteryLevel {
+                    percent,
+                    millivolts: u16::from_le_bytes([mv_lo, mv_hi]),
+                }
+            }
+            CONTROL_LED_STATE => {
+                let [leds] = fixed(data)?;
+                ControlMessage::LedState { leds }
+            }
+            CONTROL_UNPAIR => {
+                fixed::<0>(data)?;
+                ControlMessage::Unpair
+            }
+            CONTROL_GET_STATS => {
+                fixed::<0>(data)?;
+                ControlMessage::GetStats
+            }
+            CONTROL_STATS => {
+                let bytes: [u8; 12] = fixed(data)?;
+                ControlMessage::Stats(LinkStats {
+                    frames_sent: u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+                    retransmits: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+                    rejected: u32::from_le_bytes(bytes[8..].try_into().unwrap()),
+                })
+            }
+            CONTROL_SET_CONFIG => {
+                let [key, value @ ..] = fixed::<5>(data)?;
+                ControlMessage::SetConfig {
+                    key,
+                    value: u32::from_le_bytes(value),
+                }
+            }
+            CONTROL_CAPABILITY_QUERY => {
+                fixed::<0>(data)?;
+                ControlMessage::CapabilityQuery
+            }
+            CONTROL_CAPABILITIES => ControlMessage::Capabilities {
+                features: u32::from_le_bytes(fixed(data)?),
+            },
+            CONTROL_REKEY_REQUEST => ControlMessage::Rekey(RekeyMessage::Request {
+                epoch: u16::from_le_bytes(fixed(data)?),
+            }),
+            CONTROL_REKEY_CONFIRM => ControlMessage::Rekey(RekeyMessage::Confirm {
+                epoch: u16::from_le_bytes(fixed(data)?),
+            }),
+            CONTROL_RESYNC_REQUEST => ControlMessage::Resync(ResyncMessage::Request {
+                challenge: u32::from_le_bytes(fixed(data)?),
+            }),
+            CONTROL_RESYNC_RESPONSE => ControlMessage::Resync(ResyncMessage::Response {
+                challenge: u32::from_le_bytes(fixed(data)?),
+            }),
+            code => ControlMessage::Unknown {
+                code,
+                data: payload_bytes(data).map_err(|_| ControlError::UnexpectedLength)?,
+            },
+        };
+        Ok(msg)
+    }
+
+    pub fn from_payload(payload: &Payload) -> Result<Self, ControlError> {
+        match payload {
+            Payload::Control { code, data } => Self::decode(*code, data),
+            _ => Err(ControlError::NotControl),
+        }
+    }
+}
+
+fn fixed<const N: usize>(data: &[u8]) -> Result<[u8; N], ControlError> {
+    data.try_into().map_err(|_| ControlError::UnexpectedLength)
+}
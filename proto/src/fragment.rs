@@ -0,0 +1,297 @@
+//! Fragmentation of Control messages larger than one frame.
+//!
+//! Config blobs, keymaps and firmware chunks do not fit in `max_payload_bytes`. The sender
+//! splits such a message into up to `MAX_FRAGMENTS` Control frames and sends them under
+//! consecutive counters; the receiver's `Reassembler` puts them back together. Each fragment is
+//! an ordinary authenticated, acked frame, so loss and reordering are handled per fragment by
+//! the retransmit path, and the receiver identifies a message by the counter of its first
+//! fragment (`counter - fragment_index`) without a message id on the wire.
+//!
+//! The reassembly buffer is bounded: at most `MAX_REASSEMBLIES` messages of
+//! `MAX_CONTROL_MESSAGE_BYTES` each, dropped if they do not complete within the timeout.
+//! A fragment whose counter falls inside another message, a retransmitted fragment whose bytes
+//! changed, or one past the final fragment is rejected instead of being spliced in.
+//!
+//! Wire encoding: fragment `i` is `Payload::Control` with the message's code and data bytes
+//! `i * chunk ..`, where `chunk = max_payload_bytes - 1` (less `PADDED_RECORD_HEADER_BYTES`
+//! with padding on); every fragment but the last carries a full chunk. Header flags: bit 3
+//! `more_fragments` (clear on the last fragment), bits 4..7 `fragment_index`. A message that
+//! fits in one frame goes out unfragmented (both zero).
+
+use crate::{
+    data_payload_limit, payload_bytes, PacketHeader, PacketKind, Payload, ProtocolConfig,
+    MAX_RETRANSMIT_ATTEMPTS,
+};
+
+/// Fragments per message; the index is four bits of the header flags.
+pub const MAX_FRAGMENTS: usize = 16;
+/// Largest Control data field a message may carry once reassembled.
+pub const MAX_CONTROL_MESSAGE_BYTES: usize = 512;
+/// Messages a `Reassembler` collects at once.
+pub const MAX_REASSEMBLIES: usize = 2;
+
+const _: () = assert!(MAX_FRAGMENTS <= 16);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FragmentError {
+    /// Frame is not a Control frame with fragment flags set.
+    NotFragment,
+    /// Message needs more than `MAX_FRAGMENTS` frames or `MAX_CONTROL_MESSAGE_BYTES`.
+    TooLarge,
+    /// Short non-final fragment, or a fragment past the final one (the message is dropped).
+    Malformed,
+    /// Fragment conflicts with bytes already received: its counter belongs to another message,
+    /// or a repeated index or the code changed (a message in progress is dropped).
+    Overlap,
+    /// `MAX_REASSEMBLIES` messages are already in progress.
+    Busy,
+}
+
+/// One frame's worth of a message, from `fragments`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fragment {
+    pub more_fragments: bool,
+    pub fragment_index: u8,
+    pub payload: Payload,
+}
+
+/// Split Control `code` / `data` into the frames to send under consecutive counters; a single
+/// unfragmented frame when it fits.
+pub fn fragments<'a>(
+    cfg: &ProtocolConfig,
+    code: u8,
+    data: &'a [u8],
+) -> Result<Fragments<'a>, FragmentError> {
+    let chunk = chunk_len(cfg);
+    let count = data.len().div_ceil(chunk).max(1);
+    if count > MAX_FRAGMENTS || data.len() > MAX_CONTROL_MESSAGE_BYTES {
+        return Err(FragmentError::TooLarge);
+    }
+    Ok(Fragments {
+        code,
+        data,
+        chunk,
+        count,
+        next: 0,
+    })
+}
+
+pub struct Fragments<'a> {
+    code: u8,
+    data: &'a [u8],
+    chunk: usize,
+    count: usize,
+    next: usize,
+}
+
+impl Iterator for Fragments<'_> {
+    type Item = Fragment;
+
+    fn next(&mut self) -> Option<Fragment> {
+        if self.next == self.count {
+            return None;
+        }
+        let index = self.next;
+        self.next += 1;
+        let start = index * self.chunk;
+        let end = (start + self.chunk).min(self.data.len());
+        Some(Fragment {
+            more_fragments: self.next < self.count,
+            fragment_index: index as u8,
+            payload: Payload::Control {
+                code: self.code,
+                data: payload_bytes(&self.data[start..end])
+                    .expect("chunk is within the payload limit"),
+            },
+        })
+    }
+}
+
+/// A completed message; borrows the `Reassembler` until the next `push`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Reassembled<'a> {
+    pub code: u8,
+    pub data: &'a [u8],
+}
+
+#[derive(Clone, Copy)]
+struct Partial {
+    active: bool,
+    first_counter: u32,
+    code: u8,
+    started_ms: u64,
+    /// Bit `i` set once fragment `i` arrived.
+    received: u16,
+    /// Fragment count and message length, known once the final fragment arrived.
+    total: Option<u8>,
+    len: usize,
+    data: [u8; MAX_CONTROL_MESSAGE_BYTES],
+}
+
+impl Partial {
+    const EMPTY: Partial = Partial {
+        active: false,
+        first_counter: 0,
+        code: 0,
+        started_ms: 0,
+        received: 0,
+        total: None,
+        len: 0,
+        data: [0; MAX_CONTROL_MESSAGE_BYTES],
+    };
+
+    /// Last counter this message is known to occupy.
+    fn last_counter(&self) -> u32 {
+        let last_index = match self.total {
+            Some(total) => u32::from(total) - 1,
+            None => 15 - self.received.leading_zeros(),
+        };
+        self.first_counter + last_index
+    }
+}
+
+/// Receiver side: collects fragments from `Session::receive_with_header` into whole messages.
+pub struct Reassembler {
+    chunk: usize,
+    timeout_ms: u64,
+    slots: [Partial; MAX_REASSEMBLIES],
+}
+
+impl Reassembler {
+    /// A message must complete within one retransmit cycle per fragment at the latency budget.
+    pub fn new(cfg: &ProtocolConfig) -> Self {
+        let cycle_ms = cfg.latency.max.as_millis() as u64 * (MAX_RETRANSMIT_ATTEMPTS as u64 + 1);
+        Self {
+            chunk: chunk_len(cfg),
+            timeout_ms: cycle_ms * MAX_FRAGMENTS as u64,
+            slots: [Partial::EMPTY; MAX_REASSEMBLIES],
+        }
+    }
+
+    /// Add a fragment (`header.flags.is_fragment()`); returns the message once every fragment
+    /// is in. Repeats of a fragment already held are ignored.
+    pub fn push(
+        &mut self,
+        header: &PacketHeader,
+        payload: &Payload,
+        now_ms: u64,
+    ) -> Result<Option<Reassembled<'_>>, FragmentError> {
+        self.expire(now_ms);
+        let (code, data) = match payload {
+            Payload::Control { code, data }
+                if header.kind == PacketKind::Control && header.flags.is_fragment() =>
+            {
+                (*code, &data[..])
+            }
+            _ => return Err(FragmentError::NotFragment),
+        };
+        let index = header.flags.fragment_index;
+        if usize::from(index) >= MAX_FRAGMENTS {
+            return Err(FragmentError::TooLarge);
+        }
+        let last = !header.flags.more_fragments;
+        let first_counter = header
+            .counter
+            .checked_sub(u32::from(index))
+            .ok_or(FragmentError::Malformed)?;
+        if data.len() > self.chunk || (!last && data.len() != self.chunk) {
+            return Err(FragmentError::Malformed);
+        }
+        let offset = usize::from(index) * self.chunk;
+        let end = offset + data.len();
+        if end > MAX_CONTROL_MESSAGE_BYTES {
+            return Err(FragmentError::TooLarge);
+        }
+        let overlaps = self.slots.iter().any(|s| {
+            s.active
+                && s.first_counter != first_counter
+                && s.first_counter <= header.counter
+                && first_counter <= s.last_counter()
+        });
+        if overlaps {
+            return Err(FragmentError::Overlap);
+        }
+
+        let slot = match self
+            .slots
+            .iter()
+            .position(|s| s.active && s.first_counter == first_counter)
+        {
+            Some(slot) => slot,
+            None => {
+                let slot = self
+                    .slots
+                    .iter()
+                    .position(|s| !s.active)
+                    .ok_or(FragmentError::Busy)?;
+                let partial = &mut self.slots[slot];
+                *partial = Partial::EMPTY;
+                partial.active = true;
+                partial.first_counter = first_counter;
+                partial.code = code;
+                partial.started_ms = now_ms;
+                slot
+            }
+        };
+
+        let partial = &mut self.slots[slot];
+        let bit = 1u16 << index;
+        if partial.code != code {
+            partial.active = false;
+            return Err(FragmentError::Overlap);
+        }
+        if partial.received & bit != 0 {
+            let same = partial.data[offset..end] == *data && (!last || partial.len == end);
+            if same {
+                return Ok(None);
+            }
+            partial.active = false;
+            return Err(FragmentError::Overlap);
+        }
+        let past_final = partial.total.is_some_and(|total| index >= total);
+        let before_received = last && u32::from(partial.received) >> (index + 1) != 0;
+        if past_final || before_received {
+            partial.active = false;
+            return Err(FragmentError::Malformed);
+        }
+
+        partial.data[offset..end].copy_from_slice(data);
+        partial.received |= bit;
+        if last {
+            partial.total = Some(index + 1);
+            partial.len = end;
+        }
+        match partial.total {
+            Some(total) if u32::from(partial.received) == (1u32 << total) - 1 => {
+                partial.active = false;
+                Ok(Some(Reassembled {
+                    code: partial.code,
+                    data: &partial.data[..partial.len],
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Drop messages that did not complete in time; returns how many.
+    pub fn expire(&mut self, now_ms: u64) -> usize {
+        let mut dropped = 0;
+        for partial in self.slots.iter_mut().filter(|s| s.active) {
+            if now_ms.saturating_sub(partial.started_ms) > self.timeout_ms {
+                partial.active = false;
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+
+    /// Messages with some but not all fragments in.
+    pub fn in_progress(&self) -> usize {
+        self.slots.iter().filter(|s| s.active).count()
+    }
+}
+
+/// Data bytes per fragment: the payload cap minus the code byte.
+fn chunk_len(cfg: &ProtocolConfig) -> usize {
+    data_payload_limit(cfg).saturating_sub(1).max(1)
+}
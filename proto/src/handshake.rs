@@ -0,0 +1,677 @@
+//! Sans-IO handshake state machines that turn `PacketKind::Handshake` frames into session keys.
+//!
+//! Handshake frames use the normal framing; the MAC field carries a full-length HMAC-SHA256 tag
+//! (`MAX_MAC_BYTES`, whatever `mac_len` the peers go on to negotiate) keyed from the running
+//! transcript, so the payload stays readable to the peer.
+//!
+//! Every message carries the sender's `Capabilities`. The responder settles the session config
+//! with `negotiate` and, if the offers cannot be reconciled, answers with an authenticated
+//! `HandshakeReject` so the keyboard reports `Incompatible` instead of retrying forever.
+
+use crate::{
+    associated_data, encode_payload, negotiate, parse_framed, serialize_framed, Capabilities,
+    Incompatibility, Packet, PacketFlags, PacketHeader, PacketKind, ParseError, Payload,
+    ProtocolConfig, Role, SerializationError, SessionKeys, Vec, KEY_BYTES, MAX_MAC_BYTES,
+    NONCE_BYTES, SESSION_SALT_BYTES,
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+#[cfg(feature = "crypto")]
+use x25519_dalek::{PublicKey, StaticSecret};
+
+#[cfg(feature = "crypto")]
+const NOISE_PROTOCOL_NAME: &[u8] = b"Noise_NK_25519_HMAC_SHA256+keyboard-project/v1";
+const PSK_PROTOCOL_NAME: &[u8] = b"PSK_HMAC_SHA256+keyboard-project/v1";
+const SPLIT_INFO: &[u8] = b"keyboard-project session keys";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HandshakeError {
+    Parse(ParseError),
+    Serialize(SerializationError),
+    /// Frame was not the handshake message expected in the current state.
+    UnexpectedMessage,
+    /// Handshake tag did not verify (wrong peer key or tampering).
+    AuthFailed,
+    /// Peer sent a low-order public key; the shared secret would be predictable.
+    InvalidPublicKey,
+    /// Initiator: the responder's offer (in its reply or `HandshakeReject`) cannot be used.
+    Incompatible(Incompatibility),
+    /// Responder: the initiator's offer cannot be used. Transmit `reply` (a `HandshakeReject`)
+    /// so the initiator fails with `Incompatible` too.
+    Rejected {
+        reason: Incompatibility,
+        reply: Vec<u8>,
+    },
+    /// Pre-shared-key handshake while `SecurityConfig::forward_secure` is set: its session keys
+    /// fall with the PSK.
+    NotForwardSecure,
+}
+
+/// Result of a completed handshake: counters start fresh, keys are ready for the AEAD.
+pub struct EstablishedSession {
+    pub session: SessionKeys,
+    pub key: [u8; KEY_BYTES],
+    /// Final transcript hash; identical on both sides and usable as a channel binding.
+    pub handshake_hash: [u8; 32],
+    /// Protocol version both sides agreed on.
+    pub version: u8,
+    /// Local config with the negotiated MAC length, payload cap and features; use it for the
+    /// data path (`Session::new`).
+    pub config: ProtocolConfig,
+}
+
+/// Running Noise-style symmetric state: chaining key plus transcript hash.
+#[derive(Clone)]
+struct Transcript {
+    ck: [u8; 32],
+    h: [u8; 32],
+}
+
+impl Transcript {
+    fn new(protocol_name: &[u8], cfg: &ProtocolConfig) -> Self {
+        let h: [u8; 32] = Sha256::digest(protocol_name).into();
+        let mut transcript = Self { ck: h, h };
+        transcript.mix_hash(&policy_bytes(cfg));
+        transcript
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        let mut hasher = Sha256::new();
+        hasher.update(self.h);
+        hasher.update(data);
+        self.h = hasher.finalize().into();
+    }
+
+    /// Fold new key material into the chaining key and return a fresh frame-auth key.
+    fn mix_key(&mut self, ikm: &[u8]) -> [u8; 32] {
+        let mut okm = [0u8; 64];
+        Hkdf::<Sha256>::new(Some(&self.ck), ikm)
+            .expand(&[], &mut okm)
+            .expect("64 bytes is a valid HKDF-SHA256 length");
+        self.ck.copy_from_slice(&okm[..32]);
+        let mut k = [0u8; 32];
+        k.copy_from_slice(&okm[32..]);
+        k
+    }
+
+    /// Derive the AEAD key, session salt and a session id from the final transcript state.
+    fn split(&self) -> ([u8; KEY_BYTES], [u8; SESSION_SALT_BYTES], u32) {
+        let mut okm = [0u8; KEY_BYTES + SESSION_SALT_BYTES + 4];
+        let mut info = [0u8; SPLIT_INFO.len() + 32];
+        info[..SPLIT_INFO.len()].copy_from_slice(SPLIT_INFO);
+        info[SPLIT_INFO.len()..].copy_from_slice(&self.h);
+        Hkdf::<Sha256>::new(Some(&self.ck), &[])
+            .expand(&info, &mut okm)
+            .expect("52 bytes is a valid HKDF-SHA256 length");
+        let mut key = [0u8; KEY_BYTES];
+        let mut salt = [0u8; SESSION_SALT_BYTES];
+        key.copy_from_slice(&okm[..KEY_BYTES]);
+        salt.copy_from_slice(&okm[KEY_BYTES..KEY_BYTES + SESSION_SALT_BYTES]);
+        let session_id =
+            u32::from_le_bytes(okm[KEY_BYTES + SESSION_SALT_BYTES..].try_into().unwrap());
+        (key, salt, session_id)
+    }
+}
+
+/// Stable encoding of the `ProtocolConfig` fields that are not negotiated, so both sides must
+/// agree on them to finish. The negotiated ones (suite, MAC length, payload cap, features) are
+/// bound through the `Capabilities` in the handshake payloads.
+fn policy_bytes(cfg: &ProtocolConfig) -> [u8; 24] {
+    let mut out = [0u8; 24];
+    out[0] = cfg.security.handshake as u8;
+    out[1] = cfg.security.forward_secure as u8;
+    out[2] = cfg.security.replay_protection as u8;
+    out[3..7].copy_from_slice(&(cfg.wake.idle_sleep.as_millis() as u32).to_le_bytes());
+    out[7..11].copy_from_slice(&(cfg.wake.listen_window.as_millis() as u32).to_le_bytes());
+    out[11..15].copy_from_slice(&(cfg.wake.reconnect_timeout.as_millis() as u32).to_le_bytes());
+    out[15..17].copy_from_slice(&(cfg.latency.target.as_millis() as u16).to_le_bytes());
+    out[17..19].copy_from_slice(&(cfg.latency.max.as_millis() as u16).to_le_bytes());
+    out[19..23].copy_from_slice(&cfg.counters.max_jump.to_le_bytes());
+    out[23] = cfg.counters.resync as u8;
+    out
+}
+
+/// Framing for handshake frames: full-length tags, since `mac_len` is not negotiated yet.
+fn handshake_framing(cfg: &ProtocolConfig) -> ProtocolConfig {
+    let mut framing = *cfg;
+    framing.security.mac_len = MAX_MAC_BYTES;
+    framing
+}
+
+fn handshake_header(session_id: u32, needs_ack: bool) -> PacketHeader {
+    PacketHeader {
+        session_id,
+        counter: 0,
+        kind: PacketKind::Handshake,
+        flags: PacketFlags {
+            encrypted: false,
+            needs_ack,
+            retransmit: false,
+            more_fragments: false,
+            fragment_index: 0,
+        },
+    }
+}
+
+/// HMAC over transcript hash || AAD || payload, so each tag covers everything exchanged so far.
+fn frame_tag(
+    auth_key: &[u8; 32],
+    h: &[u8; 32],
+    aad: &[u8],
+    payload: &[u8],
+    mac_len: usize,
+) -> Vec<u8> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(auth_key).expect("HMAC accepts any key length");
+    mac.update(h);
+    mac.update(aad);
+    mac.update(payload);
+    let tag = mac.finalize().into_bytes();
+    tag[..mac_len.min(tag.len())].to_vec()
+}
+
+/// Frame a handshake payload, tagging it with `auth_key`. Returns the frame and the tag.
+fn seal_handshake(
+    header: PacketHeader,
+    payload: Payload,
+    cfg: &ProtocolConfig,
+    transcript: &Transcript,
+    auth_key: &[u8; 32],
+) -> Result<(Vec<u8>, Vec<u8>), HandshakeError> {
+    let payload_bytes = encode_payload(&payload);
+    let aad = associated_data(&header, payload_bytes.len());
+    let mac = frame_tag(auth_key, &transcript.h, &aad, &payload_bytes, MAX_MAC_BYTES);
+    let packet = Packet {
+        header,
+        payload,
+        mac: mac.clone(),
+    };
+    let frame =
+        serialize_framed(&packet, &handshake_framing(cfg)).map_err(HandshakeError::Serialize)?;
+    Ok((frame, mac))
+}
+
+fn parse_handshake(frame: &[u8], cfg: &ProtocolConfig) -> Result<Packet, HandshakeError> {
+    let packet = parse_framed(frame, &handshake_framing(cfg)).map_err(HandshakeError::Parse)?;
+    if packet.header.kind != PacketKind::Handshake {
+        return Err(HandshakeError::UnexpectedMessage);
+    }
+    Ok(packet)
+}
+
+fn verify_handshake(
+    packet: &Packet,
+    payload_bytes: &[u8],
+    transcript: &Transcript,
+    auth_key: &[u8; 32],
+) -> Result<(), HandshakeError> {
+    let aad = associated_data(&packet.header, payload_bytes.len());
+    let expected = frame_tag(
+        auth_key,
+        &transcript.h,
+        &aad,
+        payload_bytes,
+        packet.mac.len(),
+    );
+    if expected.ct_eq(&packet.mac).into() {
+        Ok(())
+    } else {
+        Err(HandshakeError::AuthFailed)
+    }
+}
+
+/// Answer an init whose offer failed `negotiate` with our own offer, tagged under the key the
+/// init was verified with so the initiator can tell it from a forgery.
+fn reject(
+    reason: Incompatibility,
+    cfg: &ProtocolConfig,
+    transcript: &Transcript,
+    auth_key: &[u8; 32],
+) -> HandshakeError {
+    let payload = Payload::HandshakeReject {
+        caps: Capabilities::from_config(cfg),
+    };
+    let mut transcript = transcript.clone();
+    transcript.mix_hash(&encode_payload(&payload));
+    match seal_handshake(
+        handshake_header(0, false),
+        payload,
+        cfg,
+        &transcript,
+        auth_key,
+    ) {
+        Ok((reply, _)) => HandshakeError::Rejected { reason, reply },
+        Err(e) => e,
+    }
+}
+
+/// Initiator side of `reject`: authenticate the `HandshakeReject` and explain it.
+fn rejected(
+    packet: &Packet,
+    peer: &Capabilities,
+    cfg: &ProtocolConfig,
+    transcript: &Transcript,
+    auth_key: &[u8; 32],
+) -> HandshakeError {
+    let mut transcript = transcript.clone();
+    let payload_bytes = encode_payload(&packet.payload);
+    transcript.mix_hash(&payload_bytes);
+    if let Err(e) = verify_handshake(packet, &payload_bytes, &transcript, auth_key) {
+        return e;
+    }
+    HandshakeError::Incompatible(
+        negotiate(cfg, peer)
+            .err()
+            .unwrap_or(Incompatibility::Refused),
+    )
+}
+
+#[cfg(feature = "crypto")]
+fn dh(secret: &StaticSecret, public: &PublicKey) -> Result<[u8; 32], HandshakeError> {
+    let shared = secret.diffie_hellman(public);
+    if !shared.was_contributory() {
+        return Err(HandshakeError::InvalidPublicKey);
+    }
+    Ok(shared.to_bytes())
+}
+
+/// X25519 public key for a static or ephemeral secret (used when pairing a dongle).
+#[cfg(feature = "crypto")]
+pub fn noise_public_key(secret: &[u8; KEY_BYTES]) -> [u8; KEY_BYTES] {
+    PublicKey::from(&StaticSecret::from(*secret)).to_bytes()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Ready,
+    AwaitingResponse,
+    Done,
+}
+
+/// Keyboard side of the Noise NK handshake (`-> e, es` / `<- e, ee`).
+///
+/// The keyboard knows the dongle's static public key from pairing; the ephemeral secret and
+/// nonce come from the caller's `EntropySource` so the state machine stays deterministic.
+#[cfg(feature = "crypto")]
+pub struct NoiseInitiator {
+    cfg: ProtocolConfig,
+    transcript: Transcript,
+    ephemeral: StaticSecret,
+    remote_static: PublicKey,
+    nonce: [u8; NONCE_BYTES],
+    /// Key that tagged the init; a `HandshakeReject` is tagged with it too.
+    init_key: [u8; 32],
+    stage: Stage,
+}
+
+#[cfg(feature = "crypto")]
+impl NoiseInitiator {
+    pub fn new(
+        cfg: &ProtocolConfig,
+        responder_static: [u8; KEY_BYTES],
+        eph_secret: [u8; KEY_BYTES],
+        nonce: [u8; NONCE_BYTES],
+    ) -> Self {
+        let mut transcript = Transcript::new(NOISE_PROTOCOL_NAME, cfg);
+        transcript.mix_hash(&responder_static);
+        Self {
+            cfg: *cfg,
+            transcript,
+            ephemeral: StaticSecret::from(eph_secret),
+            remote_static: PublicKey::from(responder_static),
+            nonce,
+            init_key: [0; 32],
+            stage: Stage::Ready,
+        }
+    }
+
+    /// Build the `HandshakeInit` frame. Call once, then feed the reply to `finish`.
+    pub fn start(&mut self) -> Result<Vec<u8>, HandshakeError> {
+        if self.stage != Stage::Ready {
+            return Err(HandshakeError::UnexpectedMessage);
+        }
+
+        let payload = Payload::HandshakeInit {
+            caps: Capabilities::from_config(&self.cfg),
+            eph_pubkey: PublicKey::from(&self.ephemeral).to_bytes(),
+            nonce: self.nonce,
+        };
+        self.transcript.mix_hash(&encode_payload(&payload));
+        let es = dh(&self.ephemeral, &self.remote_static)?;
+        let auth_key = self.transcript.mix_key(&es);
+        self.init_key = auth_key;
+
+        let (frame, tag) = seal_handshake(
+            handshake_header(0, true),
+            payload,
+            &self.cfg,
+            &self.transcript,
+            &auth_key,
+        )?;
+        self.transcript.mix_hash(&tag);
+        self.stage = Stage::AwaitingResponse;
+        Ok(frame)
+    }
+
+    /// Consume the responder's `HandshakeResponse` frame and derive the session.
+    ///
+    /// A frame that fails authentication leaves the initiator waiting, so a forged reply
+    /// cannot abort a handshake that the real dongle is still answering. An authentic
+    /// `HandshakeReject` ends the handshake with `Incompatible`.
+    pub fn finish(&mut self, frame: &[u8]) -> Result<EstablishedSession, HandshakeError> {
+        if self.stage != Stage::AwaitingResponse {
+            return Err(HandshakeError::UnexpectedMessage);
+        }
+
+        let packet = parse_handshake(frame, &self.cfg)?;
+        let (remote_caps, remote_eph, session_id) = match packet.payload {
+            Payload::HandshakeResponse {
+                caps,
+                eph_pubkey,
+                session_id,
+            } if packet.header.session_id == session_id => (caps, eph_pubkey, session_id),
+            Payload::HandshakeReject { caps } => {
+                let err = rejected(&packet, &caps, &self.cfg, &self.transcript, &self.init_key);
+                if matches!(err, HandshakeError::Incompatible(_)) {
+                    self.stage = Stage::Done;
+                }
+                return Err(err);
+            }
+            _ => return Err(HandshakeError::UnexpectedMessage),
+        };
+
+        let mut transcript = self.transcript.clone();
+        let payload_bytes = encode_payload(&packet.payload);
+        transcript.mix_hash(&payload_bytes);
+        let ee = dh(&self.ephemeral, &PublicKey::from(remote_eph))?;
+        let auth_key = transcript.mix_key(&ee);
+        verify_handshake(&packet, &payload_bytes, &transcript, &auth_key)?;
+        transcript.mix_hash(&packet.mac);
+
+        self.stage = Stage::Done;
+        let (version, config) =
+            negotiate(&self.cfg, &remote_caps).map_err(HandshakeError::Incompatible)?;
+        let (key, salt, _) = transcript.split();
+        Ok(EstablishedSession {
+            session: SessionKeys::new(session_id, salt),
+            key,
+            handshake_hash: transcript.h,
+            version,
+            config,
+        })
+    }
+}
+
+/// Dongle side of the Noise NK handshake; holds the static key the keyboard was paired with.
+#[cfg(feature = "crypto")]
+pub struct NoiseResponder {
+    cfg: ProtocolConfig,
+    transcript: Transcript,
+    static_secret: StaticSecret,
+    ephemeral: StaticSecret,
+    session_id: u32,
+    stage: Stage,
+}
+
+#[cfg(feature = "crypto")]
+impl NoiseResponder {
+    pub fn new(
+        cfg: &ProtocolConfig,
+        static_secret: [u8; KEY_BYTES],
+        eph_secret: [u8; KEY_BYTES],
+        session_id: u32,
+    ) -> Self {
+        let static_secret = StaticSecret::from(static_secret);
+        let mut transcript = Transcript::new(NOISE_PROTOCOL_NAME, cfg);
+        transcript.mix_hash(PublicKey::from(&static_secret).as_bytes());
+        Self {
+            cfg: *cfg,
+            transcript,
+            static_secret,
+            ephemeral: StaticSecret::from(eph_secret),
+            session_id,
+            stage: Stage::Ready,
+        }
+    }
+
+    /// Verify a `HandshakeInit` frame and answer it. Returns the reply frame and the session.
+    ///
+    /// An init whose offer fails `negotiate` yields `Rejected` with the reply to send; the
+    /// responder stays ready for another init.
+    pub fn respond(
+        &mut self,
+        frame: &[u8],
+    ) -> Result<(Vec<u8>, EstablishedSession), HandshakeError> {
+        if self.stage != Stage::Ready {
+            return Err(HandshakeError::UnexpectedMessage);
+        }
+
+        let packet = parse_handshake(frame, &self.cfg)?;
+        let (remote_caps, remote_eph) = match packet.payload {
+            Payload::HandshakeInit {
+                caps, eph_pubkey, ..
+            } => (caps, PublicKey::from(eph_pubkey)),
+            _ => return Err(HandshakeError::UnexpectedMessage),
+        };
+
+        let mut transcript = self.transcript.clone();
+        let payload_bytes = encode_payload(&packet.payload);
+        transcript.mix_hash(&payload_bytes);
+        let es = dh(&self.static_secret, &remote_eph)?;
+        let auth_key = transcript.mix_key(&es);
+        verify_handshake(&packet, &payload_bytes, &transcript, &auth_key)?;
+        transcript.mix_hash(&packet.mac);
+        let (version, config) = negotiate(&self.cfg, &remote_caps)
+            .map_err(|reason| reject(reason, &self.cfg, &transcript, &auth_key))?;
+
+        let payload = Payload::HandshakeResponse {
+            caps: Capabilities::from_config(&self.cfg),
+            eph_pubkey: PublicKey::from(&self.ephemeral).to_bytes(),
+            session_id: self.session_id,
+        };
+        transcript.mix_hash(&encode_payload(&payload));
+        let ee = dh(&self.ephemeral, &remote_eph)?;
+        let auth_key = transcript.mix_key(&ee);
+        let (reply, tag) = seal_handshake(
+            handshake_header(self.session_id, false),
+            payload,
+            &self.cfg,
+            &transcript,
+            &auth_key,
+        )?;
+        transcript.mix_hash(&tag);
+
+        self.stage = Stage::Done;
+        let (key, salt, _) = transcript.split();
+        Ok((
+            reply,
+            EstablishedSession {
+                session: SessionKeys::for_role(self.session_id, salt, Role::Dongle),
+                key,
+                handshake_hash: transcript.h,
+                version,
+                config,
+            },
+        ))
+    }
+}
+
+/// Keyboard side of the pre-shared key handshake used on the factory line and CI rigs.
+///
+/// Both sides contribute a fresh nonce; the session key, salt and session id all come from
+/// the provisioned PSK and the transcript, so no X25519 is needed. Not forward secure: with
+/// `SecurityConfig::forward_secure` set, `start` fails with `NotForwardSecure`.
+pub struct PskInitiator {
+    cfg: ProtocolConfig,
+    transcript: Transcript,
+    psk: [u8; KEY_BYTES],
+    nonce: [u8; NONCE_BYTES],
+    /// Key that tagged the init; a `HandshakeReject` is tagged with it too.
+    init_key: [u8; 32],
+    stage: Stage,
+}
+
+impl PskInitiator {
+    pub fn new(cfg: &ProtocolConfig, psk: [u8; KEY_BYTES], nonce: [u8; NONCE_BYTES]) -> Self {
+        Self {
+            cfg: *cfg,
+            transcript: Transcript::new(PSK_PROTOCOL_NAME, cfg),
+            psk,
+            nonce,
+            init_key: [0; 32],
+            stage: Stage::Ready,
+        }
+    }
+
+    /// Build the `PskInit` frame. Call once, then feed the reply to `finish`.
+    pub fn start(&mut self) -> Result<Vec<u8>, HandshakeError> {
+        if self.cfg.security.forward_secure {
+            return Err(HandshakeError::NotForwardSecure);
+        }
+        if self.stage != Stage::Ready {
+            return Err(HandshakeError::UnexpectedMessage);
+        }
+
+        let payload = Payload::PskInit {
+            caps: Capabilities::from_config(&self.cfg),
+            nonce: self.nonce,
+        };
+        self.transcript.mix_hash(&encode_payload(&payload));
+        let auth_key = self.transcript.mix_key(&self.psk);
+        self.init_key = auth_key;
+
+        let (frame, tag) = seal_handshake(
+            handshake_header(0, true),
+            payload,
+            &self.cfg,
+            &self.transcript,
+            &auth_key,
+        )?;
+        self.transcript.mix_hash(&tag);
+        self.stage = Stage::AwaitingResponse;
+        Ok(frame)
+    }
+
+    /// Consume the responder's `PskResponse` frame and derive the session.
+    /// Frames that fail authentication leave the initiator waiting for the genuine reply; an
+    /// authentic `HandshakeReject` ends the handshake with `Incompatible`.
+    pub fn finish(&mut self, frame: &[u8]) -> Result<EstablishedSession, HandshakeError> {
+        if self.stage != Stage::AwaitingResponse {
+            return Err(HandshakeError::UnexpectedMessage);
+        }
+
+        let packet = parse_handshake(frame, &self.cfg)?;
+        let (remote_caps, remote_nonce) = match packet.payload {
+            Payload::PskResponse { caps, nonce } => (caps, nonce),
+            Payload::HandshakeReject { caps } => {
+                let err = rejected(&packet, &caps, &self.cfg, &self.transcript, &self.init_key);
+                if matches!(err, HandshakeError::Incompatible(_)) {
+                    self.stage = Stage::Done;
+                }
+                return Err(err);
+            }
+            _ => return Err(HandshakeError::UnexpectedMessage),
+        };
+
+        let mut transcript = self.transcript.clone();
+        let payload_bytes = encode_payload(&packet.payload);
+        transcript.mix_hash(&payload_bytes);
+        let auth_key = transcript.mix_key(&remote_nonce);
+        verify_handshake(&packet, &payload_bytes, &transcript, &auth_key)?;
+        transcript.mix_hash(&packet.mac);
+
+        self.stage = Stage::Done;
+        let (version, config) =
+            negotiate(&self.cfg, &remote_caps).map_err(HandshakeError::Incompatible)?;
+        let (key, salt, session_id) = transcript.split();
+        Ok(EstablishedSession {
+            session: SessionKeys::new(session_id, salt),
+            key,
+            handshake_hash: transcript.h,
+            version,
+            config,
+        })
+    }
+}
+
+/// Dongle side of the pre-shared key handshake; refuses every init with `NotForwardSecure`
+/// while `SecurityConfig::forward_secure` is set.
+pub struct PskResponder {
+    cfg: ProtocolConfig,
+    transcript: Transcript,
+    psk: [u8; KEY_BYTES],
+    nonce: [u8; NONCE_BYTES],
+    stage: Stage,
+}
+
+impl PskResponder {
+    pub fn new(cfg: &ProtocolConfig, psk: [u8; KEY_BYTES], nonce: [u8; NONCE_BYTES]) -> Self {
+        Self {
+            cfg: *cfg,
+            transcript: Transcript::new(PSK_PROTOCOL_NAME, cfg),
+            psk,
+            nonce,
+            stage: Stage::Ready,
+        }
+    }
+
+    /// Verify a `PskInit` frame and answer it. Returns the reply frame and the session.
+    ///
+    /// An init whose offer fails `negotiate` yields `Rejected` with the reply to send.
+    pub fn respond(
+        &mut self,
+        frame: &[u8],
+    ) -> Result<(Vec<u8>, EstablishedSession), HandshakeError> {
+        if self.cfg.security.forward_secure {
+            return Err(HandshakeError::NotForwardSecure);
+        }
+        if self.stage != Stage::Ready {
+            return Err(HandshakeError::UnexpectedMessage);
+        }
+
+        let packet = parse_handshake(frame, &self.cfg)?;
+        let remote_caps = match packet.payload {
+            Payload::PskInit { caps, .. } => caps,
+            _ => return Err(HandshakeError::UnexpectedMessage),
+        };
+
+        let mut transcript = self.transcript.clone();
+        let payload_bytes = encode_payload(&packet.payload);
+        transcript.mix_hash(&payload_bytes);
+        let auth_key = transcript.mix_key(&self.psk);
+        verify_handshake(&packet, &payload_bytes, &transcript, &auth_key)?;
+        transcript.mix_hash(&packet.mac);
+        let (version, config) = negotiate(&self.cfg, &remote_caps)
+            .map_err(|reason| reject(reason, &self.cfg, &transcript, &auth_key))?;
+
+        let payload = Payload::PskResponse {
+            caps: Capabilities::from_config(&self.cfg),
+            nonce: self.nonce,
+        };
+        transcript.mix_hash(&encode_payload(&payload));
+        let auth_key = transcript.mix_key(&self.nonce);
+        let (reply, tag) = seal_handshake(
+            handshake_header(0, false),
+            payload,
+            &self.cfg,
+            &transcript,
+            &auth_key,
+        )?;
+        transcript.mix_hash(&tag);
+
+        self.stage = Stage::Done;
+        let (key, salt, session_id) = transcript.split();
+        Ok((
+            reply,
+            EstablishedSession {
+                session: SessionKeys::for_role(session_id, salt, Role::Dongle),
+                key,
+                handshake_hash: transcript.h,
+                version,
+                config,
+            },
+        ))
+    }
+}
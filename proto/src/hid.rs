@@ -0,0 +1,395 @@
+//! Typed keyboard state carried in `Payload::KeyReport`.
+//!
+//! A `KeyReport` is the full set of keys held at one instant: every report replaces the previous
+//! one, and a report with no keys and no modifiers means "everything released". Keys are USB HID
+//! keyboard-page usages; the eight modifier usages (0xE0..=0xE7) live in the modifier byte.
+//!
+//! Wire encoding (always within `KEY_REPORT_MAX_BYTES`):
+//! - `0x00 || modifiers || up to 6 usages` (boot 6KRO, empty slots dropped)
+//! - `0x01 || modifiers || NKRO bitmap` (bit `u % 8` of byte `u / 8` is usage `u`; trailing zero
+//!   bytes dropped)
+//!
+//! Format `0x02` is the press/release event stream in `key_events`.
+//!
+//! Media keys and power buttons travel separately as `Payload::ConsumerControl` and
+//! `Payload::SystemControl`, pointer motion as `Payload::Pointer`; `HidReport` maps each payload
+//! onto the report the dongle forwards. Battery level arrives as a Control message and becomes the
+//! Battery Strength feature of its own report.
+
+use crate::{
+    encode_payload_into, payload_bytes, ControlMessage, Payload, SerializationError,
+    MAX_PAYLOAD_BYTES, POINTER_PAYLOAD_BYTES,
+};
+
+/// Format byte for a boot-style report with at most six keys.
+pub const KEY_REPORT_BOOT: u8 = 0x00;
+/// Format byte for an n-key-rollover bitmap report.
+pub const KEY_REPORT_NKRO: u8 = 0x01;
+/// Format byte for a `KeyEventBatch`; `KeyReport::decode` rejects it as `UnknownFormat`.
+pub const KEY_REPORT_EVENTS: u8 = 0x02;
+/// Key slots in a USB HID boot keyboard report.
+pub const BOOT_KEY_SLOTS: usize = 6;
+/// Bitmap covering usages 0x00..=0xDF; modifiers are carried separately.
+pub const NKRO_BITMAP_BYTES: usize = 28;
+/// Largest encoded report: format + modifiers + full bitmap.
+pub const KEY_REPORT_MAX_BYTES: usize = 2 + NKRO_BITMAP_BYTES;
+/// Length of a USB HID boot keyboard input report.
+pub const BOOT_REPORT_LEN: usize = 8;
+/// Usage a boot report repeats in every slot when more than six keys are held.
+pub const HID_ERROR_ROLL_OVER: u8 = 0x01;
+
+/// Report ID of the dongle's Consumer Control input report.
+pub const HID_REPORT_ID_CONSUMER: u8 = 0x02;
+/// Report ID of the dongle's System Control input report.
+pub const HID_REPORT_ID_SYSTEM: u8 = 0x03;
+/// Report ID of the dongle's mouse input report.
+pub const HID_REPORT_ID_MOUSE: u8 = 0x04;
+/// Report ID of the dongle's Battery Strength (Generic Device Controls 0x20) input report.
+pub const HID_REPORT_ID_BATTERY: u8 = 0x05;
+
+pub const CONSUMER_SCAN_NEXT_TRACK: u16 = 0x00B5;
+pub const CONSUMER_SCAN_PREVIOUS_TRACK: u16 = 0x00B6;
+pub const CONSUMER_PLAY_PAUSE: u16 = 0x00CD;
+pub const CONSUMER_MUTE: u16 = 0x00E2;
+pub const CONSUMER_VOLUME_UP: u16 = 0x00E9;
+pub const CONSUMER_VOLUME_DOWN: u16 = 0x00EA;
+
+pub const SYSTEM_POWER_DOWN: u8 = 0x81;
+pub const SYSTEM_SLEEP: u8 = 0x82;
+pub const SYSTEM_WAKE_UP: u8 = 0x83;
+
+pub const MODIFIER_LEFT_CTRL: u8 = 0x01;
+pub const MODIFIER_LEFT_SHIFT: u8 = 0x02;
+pub const MODIFIER_LEFT_ALT: u8 = 0x04;
+pub const MODIFIER_LEFT_GUI: u8 = 0x08;
+pub const MODIFIER_RIGHT_CTRL: u8 = 0x10;
+pub const MODIFIER_RIGHT_SHIFT: u8 = 0x20;
+pub const MODIFIER_RIGHT_ALT: u8 = 0x40;
+pub const MODIFIER_RIGHT_GUI: u8 = 0x80;
+
+/// First usage that is a real key; 0x00..=0x03 are reserved / error codes.
+const FIRST_KEY_USAGE: u8 = 0x04;
+/// Left Control; 0xE0..=0xE7 map onto the modifier bits in order.
+const FIRST_MODIFIER_USAGE: u8 = 0xE0;
+
+const _: () = assert!(KEY_REPORT_MAX_BYTES <= MAX_PAYLOAD_BYTES);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyReportError {
+    /// Payload is not `Payload::KeyReport`.
+    NotKeyReport,
+    /// Missing format or modifier byte, or more key bytes than the format allows.
+    UnexpectedLength,
+    /// Format byte is neither boot nor NKRO.
+    UnknownFormat(u8),
+    /// Usage is reserved, a modifier outside the modifier byte, or beyond the NKRO bitmap.
+    InvalidUsage(u8),
+    /// A seventh key was pressed on a boot report.
+    TooManyKeys,
+    /// The same usage appears in more than one boot report slot.
+    DuplicateUsage(u8),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HidReportError {
+    /// Payload has no host report (KeepAlive, Ack, non-battery Control, encoder detents, ...).
+    NotHidReport,
+    /// Key report snapshot that does not decode.
+    KeyReport(KeyReportError),
+}
+
+impl From<KeyReportError> for HidReportError {
+    fn from(err: KeyReportError) -> Self {
+        HidReportError::KeyReport(err)
+    }
+}
+
+/// Input report the dongle forwards to the host; each kind is its own HID report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HidReport {
+    /// Boot keyboard report, sent without a report ID.
+    Keyboard([u8; BOOT_REPORT_LEN]),
+    /// `HID_REPORT_ID_CONSUMER || usage u16 LE`.
+    Consumer([u8; 3]),
+    /// `HID_REPORT_ID_SYSTEM || usage`.
+    System([u8; 2]),
+    /// `HID_REPORT_ID_MOUSE || buttons || dx i16 LE || dy i16 LE || wheel || pan`.
+    Mouse([u8; 1 + POINTER_PAYLOAD_BYTES]),
+    /// `HID_REPORT_ID_BATTERY || percent`.
+    Battery([u8; 2]),
+}
+
+impl HidReport {
+    /// Map a received payload onto its host report. Key reports must be snapshots; event
+    /// batches go through `KeyEventReceiver`, which yields the `KeyReport` to forward. Encoder
+    /// detents have no report of their own: the dongle's keymap turns them into key or consumer
+    /// reports.
+    pub fn from_payload(payload: &Payload) -> Result<Self, HidReportError> {
+        match payload {
+            Payload::KeyReport { keys } => Ok(HidReport::Keyboard(
+                KeyReport::decode(keys)?.to_boot_report(),
+            )),
+            Payload::ConsumerControl { usage } => {
+                let [lo, hi] = usage.to_le_bytes();
+                Ok(HidReport::Consumer([HID_REPORT_ID_CONSUMER, lo, hi]))
+            }
+            Payload::SystemControl { usage } => {
+                Ok(HidReport::System([HID_REPORT_ID_SYSTEM, *usage]))
+            }
+            Payload::Pointer { .. } => {
+                let mut report = [0u8; 1 + POINTER_PAYLOAD_BYTES];
+                report[0] = HID_REPORT_ID_MOUSE;
+                encode_payload_into(payload, &mut report[1..])
+                    .expect("sized for a pointer payload");
+                Ok(HidReport::Mouse(report))
+            }
+            Payload::Control { .. } => match ControlMessage::from_payload(payload) {
+                Ok(ControlMessage::BatteryLevel { percent, .. }) => Ok(HidReport::Battery([
+                    HID_REPORT_ID_BATTERY,
+                    percent.min(100),
+                ])),
+                _ => Err(HidReportError::NotHidReport),
+            },
+            _ => Err(HidReportError::NotHidReport),
+        }
+    }
+
+    /// Bytes to hand to the USB stack.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            HidReport::Keyboard(report) => report,
+            HidReport::Consumer(report) => report,
+            HidReport::System(report) => report,
+            HidReport::Mouse(report) => report,
+            HidReport::Battery(report) => report,
+        }
+    }
+}
+
+/// How the non-modifier keys are carried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyRollover {
+    /// Up to six usages in press order; unused slots are 0.
+    Boot([u8; BOOT_KEY_SLOTS]),
+    /// One bit per usage 0x00..=0xDF.
+    Nkro([u8; NKRO_BITMAP_BYTES]),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyReport {
+    pub modifiers: u8,
+    pub keys: KeyRollover,
+}
+
+impl KeyReport {
+    /// Boot-style report holding `keys` in order.
+    pub fn boot(modifiers: u8, keys: &[u8]) -> Result<Self, KeyReportError> {
+        let mut report = KeyReport {
+            modifiers,
+            keys: KeyRollover::Boot([0; BOOT_KEY_SLOTS]),
+        };
+        for &usage in keys {
+            report.press(usage)?;
+        }
+        Ok(report)
+    }
+
+    /// NKRO report with no keys held.
+    pub fn nkro(modifiers: u8) -> Self {
+        KeyReport {
+            modifiers,
+            keys: KeyRollover::Nkro([0; NKRO_BITMAP_BYTES]),
+        }
+    }
+
+    /// Mark `usage` as held. Modifier usages set their bit; pressing a held key is a no-op.
+    pub fn press(&mut self, usage: u8) -> Result<(), KeyReportError> {
+        if let Some(bit) = modifier_bit(usage) {
+            self.modifiers |= bit;
+            return Ok(());
+        }
+        check_key_usage(usage)?;
+        match &mut self.keys {
+            KeyRollover::Boot(slots) => {
+                if slots.contains(&usage) {
+                    return Ok(());
+                }
+                let free = slots
+                    .iter_mut()
+                    .find(|slot| **slot == 0)
+                    .ok_or(KeyReportError::TooManyKeys)?;
+                *free = usage;
+            }
+            KeyRollover::Nkro(bitmap) => bitmap[usage as usize / 8] |= 1 << (usage % 8),
+        }
+        Ok(())
+    }
+
+    /// Mark `usage` as released. Boot slots close up so held keys keep their press order.
+    pub fn release(&mut self, usage: u8) {
+        if let Some(bit) = modifier_bit(usage) {
+            self.modifiers &= !bit;
+            return;
+        }
+        match &mut self.keys {
+            KeyRollover::Boot(slots) => {
+                if let Some(pos) = slots.iter().position(|&slot| slot == usage && slot != 0) {
+                    slots.copy_within(pos + 1.., pos);
+                    slots[BOOT_KEY_SLOTS - 1] = 0;
+                }
+            }
+            KeyRollover::Nkro(bitmap) => {
+                if let Some(byte) = bitmap.get_mut(usage as usize / 8) {
+                    *byte &= !(1 << (usage % 8));
+                }
+            }
+        }
+    }
+
+    pub fn is_pressed(&self, usage: u8) -> bool {
+        if let Some(bit) = modifier_bit(usage) {
+            return self.modifiers & bit != 0;
+        }
+        match &self.keys {
+            KeyRollover::Boot(slots) => usage != 0 && slots.contains(&usage),
+            KeyRollover::Nkro(bitmap) => bitmap
+                .get(usage as usize / 8)
+                .is_some_and(|byte| byte & (1 << (usage % 8)) != 0),
+        }
+    }
+
+    /// Held non-modifier usages: press order for boot, ascending for NKRO.
+    pub fn pressed(&self) -> impl Iterator<Item = u8> + '_ {
+        let (slots, bitmap): (&[u8], &[u8]) = match &self.keys {
+            KeyRollover::Boot(slots) => (slots, &[]),
+            KeyRollover::Nkro(bitmap) => (&[], bitmap),
+        };
+        let boot = slots.iter().copied().take_while(|&usage| usage != 0);
+        let nkro = (0..bitmap.len() * 8)
+            .filter(move |&usage| bitmap[usage / 8] & (1 << (usage % 8)) != 0)
+            .map(|usage| usage as u8);
+        boot.chain(nkro)
+    }
+
+    /// True when no key and no modifier is held.
+    pub fn is_released(&self) -> bool {
+        self.modifiers == 0 && self.pressed().next().is_none()
+    }
+
+    /// Bytes `encode_into` writes.
+    pub fn encoded_len(&self) -> usize {
+        2 + match &self.keys {
+            KeyRollover::Boot(slots) => slots.iter().take_while(|&&usage| usage != 0).count(),
+            KeyRollover::Nkro(bitmap) => bitmap.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1),
+        }
+    }
+
+    pub fn encode_into(&self, out: &mut [u8]) -> Result<usize, SerializationError> {
+        let len = self.encoded_len();
+        let out = out
+            .get_mut(..len)
+            .ok_or(SerializationError::BufferTooSmall)?;
+        let (format, keys) = match &self.keys {
+            KeyRollover::Boot(slots) => (KEY_REPORT_BOOT, &slots[..]),
+            KeyRollover::Nkro(bitmap) => (KEY_REPORT_NKRO, &bitmap[..]),
+        };
+        out[0] = format;
+        out[1] = self.modifiers;
+        out[2..].copy_from_slice(&keys[..len - 2]);
+        Ok(len)
+    }
+
+    /// Parse the bytes of a `KeyReport` payload; works on `PayloadRef::KeyReport` without copying.
+    pub fn decode(bytes: &[u8]) -> Result<Self, KeyReportError> {
+        let [format, modifiers, keys @ ..] = bytes else {
+            return Err(KeyReportError::UnexpectedLength);
+        };
+        match *format {
+            KEY_REPORT_BOOT => {
+                if keys.len() > BOOT_KEY_SLOTS {
+                    return Err(KeyReportError::UnexpectedLength);
+                }
+                let mut slots = [0; BOOT_KEY_SLOTS];
+                for (i, &usage) in keys.iter().enumerate() {
+                    check_key_usage(usage)?;
+                    if keys[..i].contains(&usage) {
+                        return Err(KeyReportError::DuplicateUsage(usage));
+                    }
+                    slots[i] = usage;
+                }
+                Ok(KeyReport {
+                    modifiers: *modifiers,
+                    keys: KeyRollover::Boot(slots),
+                })
+            }
+            KEY_REPORT_NKRO => {
+                if keys.len() > NKRO_BITMAP_BYTES {
+                    return Err(KeyReportError::UnexpectedLength);
+                }
+                let mut bitmap = [0; NKRO_BITMAP_BYTES];
+                bitmap[..keys.len()].copy_from_slice(keys);
+                if let Some(reserved) = (0..FIRST_KEY_USAGE).find(|u| bitmap[0] & (1 << u) != 0) {
+                    return Err(KeyReportError::InvalidUsage(reserved));
+                }
+                Ok(KeyReport {
+                    modifiers: *modifiers,
+                    keys: KeyRollover::Nkro(bitmap),
+                })
+            }
+            other => Err(KeyReportError::UnknownFormat(other)),
+        }
+    }
+
+    pub fn to_payload(&self) -> Payload {
+        let mut buf = [0u8; KEY_REPORT_MAX_BYTES];
+        let len = self
+            .encode_into(&mut buf)
+            .expect("buffer sized for the largest report");
+        Payload::KeyReport {
+            keys: payload_bytes(&buf[..len]).expect("KEY_REPORT_MAX_BYTES fits MAX_PAYLOAD_BYTES"),
+        }
+    }
+
+    pub fn from_payload(payload: &Payload) -> Result<Self, KeyReportError> {
+        match payload {
+            Payload::KeyReport { keys } => Self::decode(keys),
+            _ => Err(KeyReportError::NotKeyReport),
+        }
+    }
+
+    /// USB HID boot keyboard input report: `modifiers || reserved || 6 usages`.
+    ///
+    /// More than six held keys report `HID_ERROR_ROLL_OVER` in every slot, as the boot protocol
+    /// requires; the modifier byte is always exact.
+    pub fn to_boot_report(&self) -> [u8; BOOT_REPORT_LEN] {
+        let mut report = [0u8; BOOT_REPORT_LEN];
+        report[0] = self.modifiers;
+        let slots = &mut report[2..];
+        for (held, usage) in self.pressed().enumerate() {
+            if held == BOOT_KEY_SLOTS {
+                slots.fill(HID_ERROR_ROLL_OVER);
+                break;
+            }
+            slots[held] = usage;
+        }
+        report
+    }
+}
+
+fn modifier_bit(usage: u8) -> Option<u8> {
+    (FIRST_MODIFIER_USAGE..=FIRST_MODIFIER_USAGE + 7)
+        .contains(&usage)
+        .then(|| 1 << (usage - FIRST_MODIFIER_USAGE))
+}
+
+fn check_key_usage(usage: u8) -> Result<(), KeyReportError> {
+    if usage < FIRST_KEY_USAGE || usage as usize >= NKRO_BITMAP_BYTES * 8 {
+        return Err(KeyReportError::InvalidUsage(usage));
+    }
+    Ok(())
+}
+
+/// Usage a key event may carry: a key in the NKRO range or one of the eight modifiers.
+pub(crate) fn is_event_usage(usage: u8) -> bool {
+    modifier_bit(usage).is_some() || check_key_usage(usage).is_ok()
+}
@@ -0,0 +1,88 @@
+//! Host LED state (Caps Lock, Num Lock, ...) carried from the dongle down to the keyboard.
+//!
+//! The host writes the HID keyboard output report to the dongle whenever an indicator changes.
+//! The dongle has no frame of its own to send it in, and keeping the keyboard's listen window
+//! open for one would cost power, so the state rides on the downlink frames the keyboard already
+//! waits for: once the host has set a report, every Ack carries the LED byte as a fifth payload
+//! byte. A lost Ack costs nothing beyond the retry the keyboard makes anyway. A dongle that does
+//! send other downlink traffic may also use `ControlMessage::LedState`.
+
+use crate::{ControlMessage, Payload};
+
+pub const LED_NUM_LOCK: u8 = 0x01;
+pub const LED_CAPS_LOCK: u8 = 0x02;
+pub const LED_SCROLL_LOCK: u8 = 0x04;
+pub const LED_COMPOSE: u8 = 0x08;
+pub const LED_KANA: u8 = 0x10;
+
+/// Dongle side: latest LED state written by the USB host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HostIndicators {
+    leds: Option<u8>,
+}
+
+impl HostIndicators {
+    /// No output report seen yet; Acks stay four bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the LED bitmap from the host's HID output report (`LED_*` bits).
+    pub fn set_output_report(&mut self, leds: u8) {
+        self.leds = Some(leds);
+    }
+
+    pub fn leds(&self) -> Option<u8> {
+        self.leds
+    }
+
+    /// Ack for `ack_counter` carrying the current LED state once the host has set one.
+    pub fn ack(&self, ack_counter: u32) -> Payload {
+        Payload::Ack {
+            ack_counter,
+            sack: 0,
+            indicators: self.leds,
+        }
+    }
+}
+
+/// Keyboard side: LED state to drive the indicator LEDs from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Indicators {
+    leds: u8,
+}
+
+impl Indicators {
+    /// All indicators off until the dongle reports otherwise.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn leds(&self) -> u8 {
+        self.leds
+    }
+
+    pub fn is_on(&self, led: u8) -> bool {
+        self.leds & led != 0
+    }
+
+    /// Pick up LED state from a downlink payload (an Ack that carries it, or a `LedState`
+    /// Control message). Returns the new bitmap when it changed.
+    pub fn apply(&mut self, payload: &Payload) -> Option<u8> {
+        let leds = match payload {
+            Payload::Ack {
+                indicators: Some(leds),
+                ..
+            } => *leds,
+            Payload::Control { .. } => match ControlMessage::from_payload(payload) {
+                Ok(ControlMessage::LedState { leds }) => leds,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        (leds != self.leds).then(|| {
+            self.leds = leds;
+            leds
+        })
+    }
+}
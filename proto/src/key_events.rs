@@ -0,0 +1,309 @@
+//! Press/release event stream: the delta alternative to `KeyReport` snapshots.
+//!
+//! Every event carries its own u16 sequence number, so a burst of keys, or a press-release pair
+//! shorter than the report interval, rides in one frame and the receiver replays it in order.
+//! The sender keeps each event until the frame that carried it is acked and repeats all pending
+//! events in the next batch, so a lost frame costs nothing but the retry. If the queue fills up,
+//! the sender sends a `KeyReport` snapshot instead and clears the queue. The snapshot travels with
+//! the queue's `next_seq`, e.g. as the empty `batch` that follows `clear` in the same `Batch`
+//! frame, and the receiver anchors on it, so a pre-snapshot batch that arrives late is trimmed
+//! as a repeat instead of re-anchoring the stream.
+//!
+//! Wire encoding, format `KEY_REPORT_EVENTS` inside `Payload::KeyReport`:
+//! `0x02 || first_seq u16 LE || count u8 || press bits || usages`, where bit `i % 8` of press
+//! byte `i / 8` is set when event `i` is a press. Event `i` has sequence `first_seq + i`.
+
+use crate::hid::is_event_usage;
+use crate::{
+    data_payload_limit, payload_bytes, KeyReport, Payload, ProtocolConfig, SerializationError,
+    KEY_REPORT_EVENTS, MAX_PAYLOAD_BYTES,
+};
+
+/// Events one batch can carry at `MAX_PAYLOAD_BYTES`.
+pub const MAX_KEY_EVENTS: usize = 24;
+/// Largest encoded batch.
+pub const KEY_EVENTS_MAX_BYTES: usize = encoded_events_len(MAX_KEY_EVENTS);
+
+const _: () = assert!(KEY_EVENTS_MAX_BYTES <= MAX_PAYLOAD_BYTES);
+
+/// Sequence distance treated as "behind" rather than "ahead" once the u16 wraps.
+const SEQ_HALF_RANGE: u16 = 0x8000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyEventError {
+    /// Payload is not a `Payload::KeyReport` carrying format `KEY_REPORT_EVENTS`.
+    NotKeyEvents,
+    /// Header truncated or the count does not match the remaining bytes.
+    UnexpectedLength,
+    /// Usage is reserved or outside the keyboard and modifier ranges.
+    InvalidUsage(u8),
+    /// Sender holds `MAX_KEY_EVENTS` unacked events; fall back to a snapshot.
+    QueueFull,
+    /// Batch starts after the next expected sequence; the events in between were lost.
+    Gap { expected: u16, received: u16 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub seq: u16,
+    pub usage: u8,
+    pub pressed: bool,
+}
+
+/// Consecutive events starting at `first_seq`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyEventBatch {
+    first_seq: u16,
+    events: heapless::Vec<KeyEvent, MAX_KEY_EVENTS>,
+}
+
+impl KeyEventBatch {
+    pub fn first_seq(&self) -> u16 {
+        self.first_seq
+    }
+
+    /// Sequence of the newest event, or `None` for an empty batch.
+    pub fn last_seq(&self) -> Option<u16> {
+        self.events.last().map(|event| event.seq)
+    }
+
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        encoded_events_len(self.events.len())
+    }
+
+    pub fn encode_into(&self, out: &mut [u8]) -> Result<usize, SerializationError> {
+        let len = self.encoded_len();
+        let out = out
+            .get_mut(..len)
+            .ok_or(SerializationError::BufferTooSmall)?;
+        let count = self.events.len();
+        let (header, rest) = out.split_at_mut(4);
+        let (press_bits, usages) = rest.split_at_mut(count.div_ceil(8));
+        header[0] = KEY_REPORT_EVENTS;
+        header[1..3].copy_from_slice(&self.first_seq.to_le_bytes());
+        header[3] = count as u8;
+        press_bits.fill(0);
+        for (i, event) in self.events.iter().enumerate() {
+            if event.pressed {
+                press_bits[i / 8] |= 1 << (i % 8);
+            }
+            usages[i] = event.usage;
+        }
+        Ok(len)
+    }
+
+    /// Parse the bytes of a `KeyReport` payload whose format byte is `KEY_REPORT_EVENTS`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, KeyEventError> {
+        let [format, seq_lo, seq_hi, count, rest @ ..] = bytes else {
+            return Err(KeyEventError::UnexpectedLength);
+        };
+        if *format != KEY_REPORT_EVENTS {
+            return Err(KeyEventError::NotKeyEvents);
+        }
+        let count = *count as usize;
+        if count > MAX_KEY_EVENTS || bytes.len() != encoded_events_len(count) {
+            return Err(KeyEventError::UnexpectedLength);
+        }
+        let (press_bits, usages) = rest.split_at(count.div_ceil(8));
+        let first_seq = u16::from_le_bytes([*seq_lo, *seq_hi]);
+        let mut events = heapless::Vec::new();
+        for (i, &usage) in usages.iter().enumerate() {
+            if !is_event_usage(usage) {
+                return Err(KeyEventError::InvalidUsage(usage));
+            }
+            let event = KeyEvent {
+                seq: first_seq.wrapping_add(i as u16),
+                usage,
+                pressed: press_bits[i / 8] & (1 << (i % 8)) != 0,
+            };
+            events
+                .push(event)
+                .expect("count checked against MAX_KEY_EVENTS");
+        }
+        Ok(KeyEventBatch { first_seq, events })
+    }
+
+    pub fn to_payload(&self) -> Payload {
+        let mut buf = [0u8; KEY_EVENTS_MAX_BYTES];
+        let len = self
+            .encode_into(&mut buf)
+            .expect("buffer sized for the largest batch");
+        Payload::KeyReport {
+            keys: payload_bytes(&buf[..len]).expect("KEY_EVENTS_MAX_BYTES fits MAX_PAYLOAD_BYTES"),
+        }
+    }
+
+    pub fn from_payload(payload: &Payload) -> Result<Self, KeyEventError> {
+        match payload {
+            Payload::KeyReport { keys } => Self::decode(keys),
+            _ => Err(KeyEventError::NotKeyEvents),
+        }
+    }
+}
+
+/// Keyboard side: numbers events and holds them until acknowledged.
+#[derive(Clone, Debug, Default)]
+pub struct KeyEventQueue {
+    next_seq: u16,
+    pending: heapless::Vec<KeyEvent, MAX_KEY_EVENTS>,
+}
+
+impl KeyEventQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a press or release and return its sequence number.
+    pub fn push(&mut self, usage: u8, pressed: bool) -> Result<u16, KeyEventError> {
+        if !is_event_usage(usage) {
+            return Err(KeyEventError::InvalidUsage(usage));
+        }
+        let seq = self.next_seq;
+        self.pending
+            .push(KeyEvent {
+                seq,
+                usage,
+                pressed,
+            })
+            .map_err(|_| KeyEventError::QueueFull)?;
+        self.next_seq = seq.wrapping_add(1);
+        Ok(seq)
+    }
+
+    /// Sequence the next pushed event gets; send it along with a snapshot.
+    pub fn next_seq(&self) -> u16 {
+        self.next_seq
+    }
+
+    /// Unacked events.
+    pub fn pending(&self) -> &[KeyEvent] {
+        &self.pending
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Oldest pending events that fit in one data payload: `cfg.max_payload_bytes`, less the
+    /// padded record header when `CAP_PADDING` is negotiated.
+    pub fn batch(&self, cfg: &ProtocolConfig) -> KeyEventBatch {
+        let limit = data_payload_limit(cfg).min(MAX_PAYLOAD_BYTES);
+        let fit = (0..=self.pending.len())
+            .rev()
+            .find(|&n| encoded_events_len(n) <= limit)
+            .unwrap_or(0);
+        KeyEventBatch {
+            first_seq: self.pending.first().map_or(self.next_seq, |e| e.seq),
+            events: self.pending[..fit].iter().copied().collect(),
+        }
+    }
+
+    /// Drop every pending event up to and including `through`, e.g. `batch.last_seq()` once the
+    /// frame carrying that batch is acked.
+    pub fn acknowledge(&mut self, through: u16) {
+        let acked = self
+            .pending
+            .iter()
+            .take_while(|event| through.wrapping_sub(event.seq) < SEQ_HALF_RANGE)
+            .count();
+        let remaining = self.pending.len() - acked;
+        self.pending.rotate_left(acked);
+        self.pending.truncate(remaining);
+    }
+
+    /// Forget pending events after their effect went out in a `KeyReport` snapshot.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// Dongle side: rebuilds the held-key state from events and spots lost ones.
+#[derive(Clone, Debug)]
+pub struct KeyEventReceiver {
+    state: KeyReport,
+    next_seq: Option<u16>,
+}
+
+impl Default for KeyEventReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyEventReceiver {
+    /// Nothing held; the first batch sets the expected sequence.
+    pub fn new() -> Self {
+        Self {
+            state: KeyReport::nkro(0),
+            next_seq: None,
+        }
+    }
+
+    /// Current held-key state (NKRO, so nothing is lost to rollover).
+    pub fn state(&self) -> &KeyReport {
+        &self.state
+    }
+
+    /// Sequence the next new event must have; `None` until anchored.
+    pub fn next_expected(&self) -> Option<u16> {
+        self.next_seq
+    }
+
+    /// Apply the events in `batch` that have not been seen yet, calling `emit` with the state
+    /// after each one so every press and release reaches the host. Returns how many were new.
+    ///
+    /// Batches that repeat already-applied events (retransmits) are trimmed. A batch that starts
+    /// after the expected sequence is rejected as `Gap` and applies nothing; recover with a
+    /// snapshot via `apply_snapshot`.
+    pub fn apply(
+        &mut self,
+        batch: &KeyEventBatch,
+        mut emit: impl FnMut(&KeyReport),
+    ) -> Result<usize, KeyEventError> {
+        let expected = self.next_seq.unwrap_or(batch.first_seq);
+        let seen = expected.wrapping_sub(batch.first_seq);
+        if seen >= SEQ_HALF_RANGE {
+            return Err(KeyEventError::Gap {
+                expected,
+                received: batch.first_seq,
+            });
+        }
+        let fresh = batch.events.get(usize::from(seen)..).unwrap_or(&[]);
+        for event in fresh {
+            if event.pressed {
+                self.state
+                    .press(event.usage)
+                    .expect("decoded usages fit an NKRO report");
+            } else {
+                self.state.release(event.usage);
+            }
+            emit(&self.state);
+            self.next_seq = Some(event.seq.wrapping_add(1));
+        }
+        if self.next_seq.is_none() {
+            self.next_seq = Some(expected);
+        }
+        Ok(fresh.len())
+    }
+
+    /// Replace the state with a full snapshot taken when the sender's `next_seq` was `next_seq`,
+    /// and expect that sequence next. Older events are ignored from then on.
+    pub fn apply_snapshot(&mut self, snapshot: &KeyReport, next_seq: u16) {
+        let mut state = KeyReport::nkro(snapshot.modifiers);
+        for usage in snapshot.pressed() {
+            state
+                .press(usage)
+                .expect("snapshot usages fit an NKRO report");
+        }
+        self.state = state;
+        self.next_seq = Some(next_seq);
+    }
+}
+
+const fn encoded_events_len(count: usize) -> usize {
+    4 + count.div_ceil(8) + count
+}
@@ -6,21 +6,102 @@
 #[cfg(all(not(feature = "std"), feature = "alloc"))]
 extern crate alloc;
 
-#[cfg(all(not(feature = "std"), feature = "alloc"))]
+#[cfg(not(feature = "std"))]
 use core::time::Duration;
 #[cfg(feature = "std")]
 use std::time::Duration;
 
 mod aead;
 pub mod backend;
+mod batch;
+mod battery;
+mod control;
+mod fragment;
+#[cfg(any(feature = "std", feature = "alloc"))]
+mod handshake;
+mod hid;
+mod indicators;
+mod key_events;
+mod negotiation;
+mod packet_ref;
+mod pointer;
+mod policy;
+mod privacy;
+mod rekey;
+mod replay;
+mod sack;
+#[cfg(any(feature = "std", feature = "alloc"))]
+mod session;
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub mod sim;
 #[cfg(not(feature = "crypto"))]
+#[allow(deprecated)]
 pub use aead::DummyAead as DefaultAead;
 #[cfg(feature = "crypto")]
-pub use aead::RealAead;
-#[cfg(feature = "crypto")]
 pub use aead::RealAead as DefaultAead;
+#[allow(deprecated)]
 pub use aead::{Aead, CryptoError, DummyAead};
+#[cfg(feature = "crypto")]
+pub use aead::{AesCcmAead, ChaCha20Aead, RealAead, SuiteAead};
+pub use batch::{batch_records, BatchError, BatchRecords, Batcher, BATCH_RECORD_HEADER_BYTES};
+pub use battery::{
+    millivolts_to_percent, BatteryConfig, BatteryMonitor, CurvePoint, LIPO_DISCHARGE_CURVE,
+};
+pub use control::{
+    ControlError, ControlMessage, LinkStats, CAP_CONSUMER_CONTROL, CAP_ENCODER, CAP_FRAGMENTS,
+    CAP_KEY_EVENTS, CAP_NKRO, CAP_PADDING, CAP_POINTER, CAP_REKEY, CONTROL_BATTERY_LEVEL,
+    CONTROL_CAPABILITIES, CONTROL_CAPABILITY_QUERY, CONTROL_GET_STATS, CONTROL_LED_STATE,
+    CONTROL_SET_CONFIG, CONTROL_STATS, CONTROL_UNPAIR,
+};
+pub use fragment::{
+    fragments, Fragment, FragmentError, Fragments, Reassembled, Reassembler,
+    MAX_CONTROL_MESSAGE_BYTES, MAX_FRAGMENTS, MAX_REASSEMBLIES,
+};
+#[cfg(all(feature = "crypto", any(feature = "std", feature = "alloc")))]
+pub use handshake::{noise_public_key, NoiseInitiator, NoiseResponder};
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use handshake::{EstablishedSession, HandshakeError, PskInitiator, PskResponder};
+pub use hid::{
+    HidReport, HidReportError, KeyReport, KeyReportError, KeyRollover, BOOT_KEY_SLOTS,
+    BOOT_REPORT_LEN, CONSUMER_MUTE, CONSUMER_PLAY_PAUSE, CONSUMER_SCAN_NEXT_TRACK,
+    CONSUMER_SCAN_PREVIOUS_TRACK, CONSUMER_VOLUME_DOWN, CONSUMER_VOLUME_UP, HID_ERROR_ROLL_OVER,
+    HID_REPORT_ID_BATTERY, HID_REPORT_ID_CONSUMER, HID_REPORT_ID_MOUSE, HID_REPORT_ID_SYSTEM,
+    KEY_REPORT_BOOT, KEY_REPORT_EVENTS, KEY_REPORT_MAX_BYTES, KEY_REPORT_NKRO, MODIFIER_LEFT_ALT,
+    MODIFIER_LEFT_CTRL, MODIFIER_LEFT_GUI, MODIFIER_LEFT_SHIFT, MODIFIER_RIGHT_ALT,
+    MODIFIER_RIGHT_CTRL, MODIFIER_RIGHT_GUI, MODIFIER_RIGHT_SHIFT, NKRO_BITMAP_BYTES,
+    SYSTEM_POWER_DOWN, SYSTEM_SLEEP, SYSTEM_WAKE_UP,
+};
+pub use indicators::{
+    HostIndicators, Indicators, LED_CAPS_LOCK, LED_COMPOSE, LED_KANA, LED_NUM_LOCK, LED_SCROLL_LOCK,
+};
+pub use key_events::{
+    KeyEvent, KeyEventBatch, KeyEventError, KeyEventQueue, KeyEventReceiver, KEY_EVENTS_MAX_BYTES,
+    MAX_KEY_EVENTS,
+};
+pub use negotiation::{
+    negotiate, Capabilities, Incompatibility, CAPABILITIES_BYTES, MIN_NEGOTIATED_PAYLOAD_BYTES,
+    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+pub use packet_ref::{decode_payload_ref, PacketRef, PayloadRef};
+pub use pointer::{
+    EncoderAccumulator, EncoderError, PointerAccumulator, MAX_BUTTON_TRANSITIONS, MAX_ENCODERS,
+    MOUSE_BUTTON_BACK, MOUSE_BUTTON_FORWARD, MOUSE_BUTTON_LEFT, MOUSE_BUTTON_MIDDLE,
+    MOUSE_BUTTON_RIGHT,
+};
+pub use policy::check_security_policy;
+pub use privacy::{
+    pad_payload, padded_payload, CoverTraffic, PaddingError, PADDED_RECORD_HEADER_BYTES,
+};
+pub use rekey::{
+    EpochKeys, RekeyError, RekeyMessage, RekeyState, CONTROL_REKEY_CONFIRM, CONTROL_REKEY_REQUEST,
+};
+pub use replay::{
+    ReplayWindow, ResyncAction, ResyncMessage, ResyncState, CONTROL_RESYNC_REQUEST,
+    CONTROL_RESYNC_RESPONSE, REPLAY_WINDOW_BITS, REPLAY_WINDOW_BYTES,
+};
+pub use sack::{RetransmitError, RetransmitQueue, SelectiveAck, MAX_IN_FLIGHT, SACK_BITS};
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use session::Session;
 
 #[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::vec;
@@ -28,20 +109,82 @@ use alloc::vec;
 use alloc::vec::Vec as StdVec;
 
 pub const MAX_MAC_BYTES: usize = 16;
+/// Tag lengths `SecurityConfig::mac_len` may select: 8, 12 or the full 16 bytes, for every suite.
+pub const MAC_LENGTHS: [usize; 3] = [8, 12, MAX_MAC_BYTES];
+/// Capacity of `PayloadBytes` in no-alloc builds; data payloads above it are rejected there.
+pub const MAX_PAYLOAD_BYTES: usize = 32;
 pub const KEY_BYTES: usize = 32;
 pub const NONCE_BYTES: usize = 24; // XChaCha20-Poly1305 nonce size
+/// Counter, direction and epoch bytes after the salt in a protocol nonce.
+const NONCE_UNIQUE_BYTES: usize = 7;
 pub const SESSION_SALT_BYTES: usize = 16;
 pub const MAX_RETRANSMIT_ATTEMPTS: u8 = 1; // single retry, no backoff, to bound latency
 pub const HEADER_LEN: usize = 10; // session_id (4) + counter (4) + kind (1) + flags (1)
 pub const AAD_LEN: usize = HEADER_LEN + 2; // header + payload length (u16 LE)
+/// `Payload::Pointer` on the wire: buttons + dx + dy + wheel + pan.
+pub const POINTER_PAYLOAD_BYTES: usize = 7;
+/// Largest handshake payload: message type (1) + capabilities + ephemeral public key + nonce.
+pub const HANDSHAKE_MAX_BYTES: usize = 1 + CAPABILITIES_BYTES + KEY_BYTES + NONCE_BYTES;
 /// Counter limit before session must be rekeyed to prevent nonce reuse (2^31, half of u32::MAX).
 pub const COUNTER_REKEY_THRESHOLD: u32 = 1 << 31;
+/// Counter at which an in-band rekey should start, leaving headroom for the exchange to finish.
+pub const COUNTER_REKEY_SOFT_LIMIT: u32 = COUNTER_REKEY_THRESHOLD - (1 << 16);
 
 #[cfg(all(not(feature = "std"), feature = "alloc"))]
 pub(crate) type Vec<T> = StdVec<T>;
 #[cfg(feature = "std")]
 pub(crate) type Vec<T> = std::vec::Vec<T>;
 
+/// Variable-length payload bytes (key reports, Control data): heap-backed with `std`/`alloc`,
+/// a fixed `MAX_PAYLOAD_BYTES` buffer otherwise so firmware links without an allocator.
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub type PayloadBytes = Vec<u8>;
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+pub type PayloadBytes = heapless::Vec<u8, MAX_PAYLOAD_BYTES>;
+
+/// Packet MAC bytes; fixed `MAX_MAC_BYTES` buffer in no-alloc builds.
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub type MacBytes = Vec<u8>;
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+pub type MacBytes = heapless::Vec<u8, MAX_MAC_BYTES>;
+
+/// Copy into `PayloadBytes`; fails only when a no-alloc buffer is too small.
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub(crate) fn payload_bytes(bytes: &[u8]) -> Result<PayloadBytes, ParseError> {
+    Ok(bytes.to_vec())
+}
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+pub(crate) fn payload_bytes(bytes: &[u8]) -> Result<PayloadBytes, ParseError> {
+    PayloadBytes::from_slice(bytes).map_err(|_| ParseError::UnexpectedLength)
+}
+
+/// `len` zero bytes; fails only when a no-alloc buffer is too small.
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub(crate) fn zeroed_payload_bytes(len: usize) -> Result<PayloadBytes, ParseError> {
+    Ok(vec![0; len])
+}
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+pub(crate) fn zeroed_payload_bytes(len: usize) -> Result<PayloadBytes, ParseError> {
+    let mut bytes = PayloadBytes::new();
+    bytes
+        .resize(len, 0)
+        .map_err(|_| ParseError::UnexpectedLength)?;
+    Ok(bytes)
+}
+
+/// Copy into `MacBytes`; fails only when a no-alloc buffer is too small.
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub(crate) fn mac_bytes(bytes: &[u8]) -> Result<MacBytes, ParseError> {
+    Ok(bytes.to_vec())
+}
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+pub(crate) fn mac_bytes(bytes: &[u8]) -> Result<MacBytes, ParseError> {
+    MacBytes::from_slice(bytes).map_err(|_| ParseError::MacLengthMismatch)
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct WakeTiming {
     pub idle_sleep: Duration,
@@ -49,9 +192,43 @@ pub struct WakeTiming {
     pub reconnect_timeout: Duration,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Frame AEAD; the discriminant is the wire code offered in `Capabilities`. `SuiteAead::new`
+/// builds the matching `Aead`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CipherSuite {
-    XChaCha20Poly1305,
+    XChaCha20Poly1305 = 0,
+    /// IETF ChaCha20-Poly1305 (RFC 8439), 12-byte nonce.
+    ChaCha20Poly1305 = 1,
+    /// AES-128-CCM (RFC 3610) with a 13-byte nonce and an HKDF-derived AES-128 key.
+    Aes128Ccm = 2,
+}
+
+impl CipherSuite {
+    /// Nonce bytes the suite's cipher takes.
+    pub const fn nonce_len(self) -> usize {
+        match self {
+            CipherSuite::XChaCha20Poly1305 => NONCE_BYTES,
+            CipherSuite::ChaCha20Poly1305 => 12,
+            CipherSuite::Aes128Ccm => 13,
+        }
+    }
+
+    /// The cipher's nonce for a protocol nonce from `derive_directional_nonce`, in the first
+    /// `nonce_len` bytes (the rest are zero). Shorter nonces keep counter, direction and epoch,
+    /// which make the nonce unique under one key, and as much of the session salt as fits in
+    /// front of them.
+    pub fn cipher_nonce(self, nonce: &[u8; NONCE_BYTES]) -> [u8; NONCE_BYTES] {
+        let len = self.nonce_len();
+        if len == NONCE_BYTES {
+            return *nonce;
+        }
+        let salt = len - NONCE_UNIQUE_BYTES;
+        let mut out = [0u8; NONCE_BYTES];
+        out[..salt].copy_from_slice(&nonce[..salt]);
+        out[salt..len]
+            .copy_from_slice(&nonce[SESSION_SALT_BYTES..SESSION_SALT_BYTES + NONCE_UNIQUE_BYTES]);
+        out
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -75,12 +252,24 @@ pub struct LatencyBudget {
     pub max: Duration,
 }
 
+/// Receiver-side rules for how far a peer's counter may move between accepted packets.
+#[derive(Clone, Copy, Debug)]
+pub struct CounterPolicy {
+    /// Largest forward gap from the highest accepted counter before a packet is `CounterJump`.
+    pub max_jump: u32,
+    /// Answer an authenticated `CounterJump` with a resync challenge instead of dropping it.
+    pub resync: bool,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct ProtocolConfig {
     pub wake: WakeTiming,
     pub security: SecurityConfig,
     pub latency: LatencyBudget,
+    pub counters: CounterPolicy,
     pub max_payload_bytes: u16,
+    /// `CAP_*` bits offered in the handshake; after it, the ones both peers support.
+    pub features: u32,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -90,6 +279,46 @@ pub enum PacketKind {
     KeyReport,
     Ack,
     KeepAlive,
+    /// HID Consumer Page usage (media keys).
+    ConsumerControl,
+    /// HID Generic Desktop System Control usage (power, sleep, wake).
+    SystemControl,
+    /// Relative pointer motion, scroll and buttons.
+    Pointer,
+    /// Rotary encoder detents.
+    Encoder,
+    /// Several report payloads under one header and MAC; see `Batcher`.
+    Batch,
+    /// A data payload or KeepAlive padded to the full frame size; see `privacy`.
+    Padded,
+}
+
+impl PacketKind {
+    /// Whether `Session::send` asks the peer to Ack this kind of packet.
+    pub fn needs_ack(self) -> bool {
+        matches!(
+            self,
+            PacketKind::KeyReport
+                | PacketKind::Control
+                | PacketKind::ConsumerControl
+                | PacketKind::SystemControl
+                | PacketKind::Pointer
+                | PacketKind::Encoder
+                | PacketKind::Batch
+                | PacketKind::Padded
+        )
+    }
+
+    /// Whether a payload of this kind may ride as a record in a `Batch`.
+    pub fn batchable(self) -> bool {
+        self.needs_ack() && !matches!(self, PacketKind::Batch | PacketKind::Padded)
+    }
+
+    /// Whether a payload of this kind may be carried by a `Padded` payload: the acked kinds,
+    /// and KeepAlive for cover traffic.
+    pub fn paddable(self) -> bool {
+        (self.needs_ack() && self != PacketKind::Padded) || self == PacketKind::KeepAlive
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -97,6 +326,17 @@ pub struct PacketFlags {
     pub encrypted: bool,
     pub needs_ack: bool,
     pub retransmit: bool,
+    /// Control fragment that is not the last of its message (see `fragment`).
+    pub more_fragments: bool,
+    /// Position of a Control fragment in its message, 0..`MAX_FRAGMENTS`.
+    pub fragment_index: u8,
+}
+
+impl PacketFlags {
+    /// Frame carries one piece of a fragmented Control message.
+    pub fn is_fragment(&self) -> bool {
+        self.more_fragments || self.fragment_index != 0
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -110,23 +350,104 @@ pub struct PacketHeader {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Payload {
     HandshakeInit {
+        caps: Capabilities,
         eph_pubkey: [u8; KEY_BYTES],
         nonce: [u8; NONCE_BYTES],
     },
     HandshakeAccept {
         session_id: u32,
     },
+    /// Responder's ephemeral key and the session id it assigned.
+    HandshakeResponse {
+        caps: Capabilities,
+        eph_pubkey: [u8; KEY_BYTES],
+        session_id: u32,
+    },
+    /// Pre-shared key mode: initiator's fresh nonce.
+    PskInit {
+        caps: Capabilities,
+        nonce: [u8; NONCE_BYTES],
+    },
+    /// Pre-shared key mode: responder's fresh nonce.
+    PskResponse {
+        caps: Capabilities,
+        nonce: [u8; NONCE_BYTES],
+    },
+    /// Responder's offer, sent instead of a response when `negotiate` fails.
+    HandshakeReject {
+        caps: Capabilities,
+    },
+    /// Raw Control frame; `ControlMessage` gives it a typed view.
     Control {
         code: u8,
-        data: Vec<u8>,
+        data: PayloadBytes,
     },
+    /// Encoded `hid::KeyReport`; see `KeyReport::to_payload` / `from_payload`.
     KeyReport {
-        keys: Vec<u8>,
+        keys: PayloadBytes,
     },
+    /// Acknowledges every counter up to `ack_counter` plus those set in `sack` (see
+    /// `SelectiveAck`); a dongle may append the host's LED state (see `HostIndicators`).
     Ack {
         ack_counter: u32,
+        sack: u32,
+        indicators: Option<u8>,
     },
     KeepAlive,
+    /// Consumer Page usage held, u16 LE on the wire; 0 releases.
+    ConsumerControl {
+        usage: u16,
+    },
+    /// System Control usage held, one byte on the wire; 0 releases.
+    SystemControl {
+        usage: u8,
+    },
+    /// Motion and scroll since the previous frame, plus the buttons held; see `PointerAccumulator`.
+    Pointer {
+        buttons: u8,
+        dx: i16,
+        dy: i16,
+        wheel: i8,
+        pan: i8,
+    },
+    /// Net detents of one encoder since its previous frame; see `EncoderAccumulator`.
+    Encoder {
+        encoder: u8,
+        detents: i8,
+    },
+    /// Encoded records of batchable payloads; `batch_records` gives the typed view.
+    Batch {
+        records: PayloadBytes,
+    },
+    /// One encoded record of a paddable payload and its zero padding; `padded_payload` gives
+    /// the typed view.
+    Padded {
+        record: PayloadBytes,
+    },
+}
+
+impl Payload {
+    /// Packet kind this payload travels under.
+    pub fn kind(&self) -> PacketKind {
+        match self {
+            Payload::HandshakeInit { .. }
+            | Payload::HandshakeAccept { .. }
+            | Payload::HandshakeResponse { .. }
+            | Payload::PskInit { .. }
+            | Payload::PskResponse { .. }
+            | Payload::HandshakeReject { .. } => PacketKind::Handshake,
+            Payload::Control { .. } => PacketKind::Control,
+            Payload::KeyReport { .. } => PacketKind::KeyReport,
+            Payload::Ack { .. } => PacketKind::Ack,
+            Payload::KeepAlive => PacketKind::KeepAlive,
+            Payload::ConsumerControl { .. } => PacketKind::ConsumerControl,
+            Payload::SystemControl { .. } => PacketKind::SystemControl,
+            Payload::Pointer { .. } => PacketKind::Pointer,
+            Payload::Encoder { .. } => PacketKind::Encoder,
+            Payload::Batch { .. } => PacketKind::Batch,
+            Payload::Padded { .. } => PacketKind::Padded,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -134,42 +455,96 @@ pub struct Packet {
     pub header: PacketHeader,
     pub payload: Payload,
     /// Authentication tag; length dictated by `SecurityConfig::mac_len`.
-    pub mac: Vec<u8>,
+    pub mac: MacBytes,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ValidationError {
+    /// MAC absent or not `mac_len` bytes: the frame is unauthenticated.
     MissingMac,
     ReplayDetected,
     PayloadTooLarge,
     CounterJump,
     SessionMismatch,
+    /// Frame after the handshake without `flags.encrypted` (see `check_security_policy`).
+    Unencrypted,
+    /// Pre-shared-key handshake while `SecurityConfig::forward_secure` is set.
+    NotForwardSecure,
+    /// `SecurityConfig::replay_protection` is set but no replay window was given.
+    NoReplayWindow,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum SessionError {
     CounterExhausted,
+    /// Frame failed to parse or authenticate (`Session::receive`).
+    Crypto(CryptoError),
+    /// Frame authenticated but broke a validation rule (security policy, replay, counter jump,
+    /// session id).
+    Invalid(ValidationError),
+    /// In-band rekey refused (`Session::begin_rekey`, or a rekey message from the peer).
+    Rekey(RekeyError),
+}
+
+impl From<CryptoError> for SessionError {
+    fn from(e: CryptoError) -> Self {
+        SessionError::Crypto(e)
+    }
+}
+
+impl From<ParseError> for SessionError {
+    fn from(e: ParseError) -> Self {
+        SessionError::Crypto(CryptoError::Parse(e))
+    }
+}
+
+impl From<ValidationError> for SessionError {
+    fn from(e: ValidationError) -> Self {
+        SessionError::Invalid(e)
+    }
+}
+
+impl From<RekeyError> for SessionError {
+    fn from(e: RekeyError) -> Self {
+        SessionError::Rekey(e)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum SerializationError {
     PayloadTooLarge,
     MacLengthMismatch,
+    /// Caller-provided buffer cannot hold the encoded output.
+    BufferTooSmall,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ParseError {
     UnexpectedLength,
     UnknownKind(u8),
+    UnknownHandshake(u8),
     MacLengthMismatch,
+    /// `Batch` record of a kind that cannot be batched (handshake, Ack, KeepAlive, Batch).
+    NotBatchable(u8),
+    /// `Padded` record of a kind that cannot be padded (handshake, Ack, Padded).
+    NotPaddable(u8),
 }
 
+/// Security policy, structural and replay checks for a received packet.
+///
+/// `replay` is the receiver's window; late packets inside it pass, duplicates and packets older
+/// than the window are `ReplayDetected`. It must be given for every non-handshake frame while
+/// `replay_protection` is set, and is ignored otherwise. Record the counter only after the AEAD
+/// opened the frame.
 pub fn validate_packet(
     packet: &Packet,
     cfg: &ProtocolConfig,
     expected_session: Option<u32>,
-    last_counter: Option<u32>,
+    replay: Option<&ReplayWindow>,
 ) -> Result<(), ValidationError> {
+    check_security_policy(packet, cfg, replay)?;
+    let replay = replay.filter(|_| cfg.security.replay_protection);
+
     if packet.mac.is_empty() || packet.mac.len() != cfg.security.mac_len {
         return Err(ValidationError::MissingMac);
     }
@@ -180,15 +555,14 @@ pub fn validate_packet(
         }
     }
 
-    if let Some(last) = last_counter {
-        if packet.header.counter == last {
-            return Err(ValidationError::ReplayDetected);
-        }
-        if packet.header.counter < last {
-            return Err(ValidationError::CounterJump);
-        }
-        if packet.header.counter - last > 50 {
-            return Err(ValidationError::CounterJump);
+    if let Some(window) = replay {
+        window.check(packet.header.counter)?;
+        if let Some(highest) = window.highest() {
+            if packet.header.counter > highest
+                && packet.header.counter - highest > cfg.counters.max_jump
+            {
+                return Err(ValidationError::CounterJump);
+            }
         }
     }
 
@@ -202,12 +576,28 @@ pub fn validate_packet(
 
 fn payload_len(payload: &Payload) -> usize {
     match payload {
-        Payload::HandshakeInit { .. } => KEY_BYTES + NONCE_BYTES,
-        Payload::HandshakeAccept { .. } => 4,
+        Payload::HandshakeInit { .. } => 1 + CAPABILITIES_BYTES + KEY_BYTES + NONCE_BYTES,
+        Payload::HandshakeAccept { .. } => 1 + 4,
+        Payload::HandshakeResponse { .. } => 1 + CAPABILITIES_BYTES + KEY_BYTES + 4,
+        Payload::PskInit { .. } | Payload::PskResponse { .. } => {
+            1 + CAPABILITIES_BYTES + NONCE_BYTES
+        }
+        Payload::HandshakeReject { .. } => 1 + CAPABILITIES_BYTES,
         Payload::Control { data, .. } => 1 + data.len(),
         Payload::KeyReport { keys } => keys.len(),
-        Payload::Ack { .. } => 4,
+        Payload::Ack {
+            sack, indicators, ..
+        } => {
+            let sack_len = if *sack != 0 { 4 } else { 0 };
+            4 + sack_len + usize::from(indicators.is_some())
+        }
         Payload::KeepAlive => 0,
+        Payload::ConsumerControl { .. } => 2,
+        Payload::SystemControl { .. } => 1,
+        Payload::Pointer { .. } => POINTER_PAYLOAD_BYTES,
+        Payload::Encoder { .. } => 2,
+        Payload::Batch { records } => records.len(),
+        Payload::Padded { record } => record.len(),
     }
 }
 
@@ -229,28 +619,108 @@ pub fn associated_data(header: &PacketHeader, payload_len: usize) -> [u8; AAD_LE
     out
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 /// Encode payload into the on-wire representation (no MAC/encryption).
 pub fn encode_payload(payload: &Payload) -> Vec<u8> {
+    let mut buf = vec![0u8; payload_len(payload)];
+    encode_payload_into(payload, &mut buf).expect("buffer sized by payload_len");
+    buf
+}
+
+/// Allocation-free `encode_payload`: writes into the front of `out`, returns the length.
+pub fn encode_payload_into(payload: &Payload, out: &mut [u8]) -> Result<usize, SerializationError> {
+    let len = payload_len(payload);
+    let out = out
+        .get_mut(..len)
+        .ok_or(SerializationError::BufferTooSmall)?;
     match payload {
-        Payload::HandshakeInit { eph_pubkey, nonce } => {
-            let mut buf = Vec::with_capacity(KEY_BYTES + NONCE_BYTES);
-            buf.extend_from_slice(eph_pubkey);
-            buf.extend_from_slice(nonce);
-            buf
+        Payload::HandshakeInit {
+            caps,
+            eph_pubkey,
+            nonce,
+        } => {
+            out[0] = HANDSHAKE_INIT;
+            let body = encode_caps(caps, out);
+            body[..KEY_BYTES].copy_from_slice(eph_pubkey);
+            body[KEY_BYTES..].copy_from_slice(nonce);
+        }
+        Payload::HandshakeAccept { session_id } => {
+            out[0] = HANDSHAKE_ACCEPT;
+            out[1..].copy_from_slice(&session_id.to_le_bytes());
+        }
+        Payload::HandshakeResponse {
+            caps,
+            eph_pubkey,
+            session_id,
+        } => {
+            out[0] = HANDSHAKE_RESPONSE;
+            let body = encode_caps(caps, out);
+            body[..KEY_BYTES].copy_from_slice(eph_pubkey);
+            body[KEY_BYTES..].copy_from_slice(&session_id.to_le_bytes());
+        }
+        Payload::PskInit { caps, nonce } => {
+            out[0] = HANDSHAKE_PSK_INIT;
+            encode_caps(caps, out).copy_from_slice(nonce);
+        }
+        Payload::PskResponse { caps, nonce } => {
+            out[0] = HANDSHAKE_PSK_RESPONSE;
+            encode_caps(caps, out).copy_from_slice(nonce);
+        }
+        Payload::HandshakeReject { caps } => {
+            out[0] = HANDSHAKE_REJECT;
+            encode_caps(caps, out);
         }
-        Payload::HandshakeAccept { session_id } => session_id.to_le_bytes().to_vec(),
         Payload::Control { code, data } => {
-            let mut buf = Vec::with_capacity(1 + data.len());
-            buf.push(*code);
-            buf.extend_from_slice(data);
-            buf
+            out[0] = *code;
+            out[1..].copy_from_slice(data);
+        }
+        Payload::KeyReport { keys } => out.copy_from_slice(keys),
+        Payload::Ack {
+            ack_counter,
+            sack,
+            indicators,
+        } => {
+            out[..4].copy_from_slice(&ack_counter.to_le_bytes());
+            if *sack != 0 {
+                out[4..8].copy_from_slice(&sack.to_le_bytes());
+            }
+            if let Some(leds) = indicators {
+                out[len - 1] = *leds;
+            }
         }
-        Payload::KeyReport { keys } => keys.clone(),
-        Payload::Ack { ack_counter } => ack_counter.to_le_bytes().to_vec(),
-        Payload::KeepAlive => Vec::new(),
+        Payload::KeepAlive => {}
+        Payload::ConsumerControl { usage } => out.copy_from_slice(&usage.to_le_bytes()),
+        Payload::SystemControl { usage } => out[0] = *usage,
+        Payload::Pointer {
+            buttons,
+            dx,
+            dy,
+            wheel,
+            pan,
+        } => {
+            out[0] = *buttons;
+            out[1..3].copy_from_slice(&dx.to_le_bytes());
+            out[3..5].copy_from_slice(&dy.to_le_bytes());
+            out[5] = *wheel as u8;
+            out[6] = *pan as u8;
+        }
+        Payload::Encoder { encoder, detents } => {
+            out[0] = *encoder;
+            out[1] = *detents as u8;
+        }
+        Payload::Batch { records } => out.copy_from_slice(records),
+        Payload::Padded { record } => out.copy_from_slice(record),
     }
+    Ok(len)
+}
+
+/// Write `caps` after the message type byte of a handshake payload; returns the rest of `out`.
+fn encode_caps<'a>(caps: &Capabilities, out: &'a mut [u8]) -> &'a mut [u8] {
+    out[1..1 + CAPABILITIES_BYTES].copy_from_slice(&caps.to_bytes());
+    &mut out[1 + CAPABILITIES_BYTES..]
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 /// Serialize a packet into (header bytes, payload bytes, associated data) with basic checks.
 pub fn serialize_packet(
     packet: &Packet,
@@ -276,6 +746,7 @@ pub fn serialize_packet(
     })
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub struct SerializedPacket {
     pub header: [u8; HEADER_LEN],
     pub payload: Vec<u8>,
@@ -283,6 +754,7 @@ pub struct SerializedPacket {
     pub aad: [u8; AAD_LEN],
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 /// Header || payload_len (u16 LE) || payload || mac
 pub fn serialize_framed(
     packet: &Packet,
@@ -305,14 +777,7 @@ pub fn decode_header(bytes: &[u8]) -> Result<PacketHeader, ParseError> {
 
     let session_id = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
     let counter = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
-    let kind = match bytes[8] {
-        0 => PacketKind::Handshake,
-        1 => PacketKind::Control,
-        2 => PacketKind::KeyReport,
-        3 => PacketKind::Ack,
-        4 => PacketKind::KeepAlive,
-        other => return Err(ParseError::UnknownKind(other)),
-    };
+    let kind = kind_from_byte(bytes[8])?;
     let flags = flags_from_byte(bytes[9]);
 
     Ok(PacketHeader {
@@ -324,49 +789,7 @@ pub fn decode_header(bytes: &[u8]) -> Result<PacketHeader, ParseError> {
 }
 
 pub fn decode_payload(kind: PacketKind, bytes: &[u8]) -> Result<Payload, ParseError> {
-    match kind {
-        PacketKind::Handshake => {
-            if bytes.len() == KEY_BYTES + NONCE_BYTES {
-                let mut key = [0u8; KEY_BYTES];
-                let mut nonce = [0u8; NONCE_BYTES];
-                key.copy_from_slice(&bytes[..KEY_BYTES]);
-                nonce.copy_from_slice(&bytes[KEY_BYTES..]);
-                Ok(Payload::HandshakeInit {
-                    eph_pubkey: key,
-                    nonce,
-                })
-            } else if bytes.len() == 4 {
-                let session_id = u32::from_le_bytes(bytes.try_into().unwrap());
-                Ok(Payload::HandshakeAccept { session_id })
-            } else {
-                Err(ParseError::UnexpectedLength)
-            }
-        }
-        PacketKind::Control => {
-            if bytes.is_empty() {
-                return Err(ParseError::UnexpectedLength);
-            }
-            let code = bytes[0];
-            let data = bytes[1..].to_vec();
-            Ok(Payload::Control { code, data })
-        }
-        PacketKind::KeyReport => Ok(Payload::KeyReport {
-            keys: bytes.to_vec(),
-        }),
-        PacketKind::Ack => {
-            if bytes.len() != 4 {
-                return Err(ParseError::UnexpectedLength);
-            }
-            let ack_counter = u32::from_le_bytes(bytes.try_into().unwrap());
-            Ok(Payload::Ack { ack_counter })
-        }
-        PacketKind::KeepAlive => {
-            if !bytes.is_empty() {
-                return Err(ParseError::UnexpectedLength);
-            }
-            Ok(Payload::KeepAlive)
-        }
-    }
+    decode_payload_ref(kind, bytes).map(|payload| payload.to_owned())
 }
 
 pub fn parse_packet(
@@ -375,6 +798,16 @@ pub fn parse_packet(
     mac_bytes: &[u8],
     cfg: &ProtocolConfig,
 ) -> Result<Packet, ParseError> {
+    parse_packet_ref(header_bytes, payload_bytes, mac_bytes, cfg).map(|packet| packet.to_owned())
+}
+
+/// Borrowing `parse_packet`: payload and MAC point into the given slices.
+pub fn parse_packet_ref<'a>(
+    header_bytes: &[u8],
+    payload_bytes: &'a [u8],
+    mac_bytes: &'a [u8],
+    cfg: &ProtocolConfig,
+) -> Result<PacketRef<'a>, ParseError> {
     if mac_bytes.len() != cfg.security.mac_len {
         return Err(ParseError::MacLengthMismatch);
     }
@@ -383,42 +816,56 @@ pub fn parse_packet(
     if payload_bytes.len() > payload_limit(header.kind, cfg) {
         return Err(ParseError::UnexpectedLength);
     }
-    let payload = decode_payload(header.kind, payload_bytes)?;
+    let payload = decode_payload_ref(header.kind, payload_bytes)?;
 
-    Ok(Packet {
+    Ok(PacketRef {
         header,
         payload,
-        mac: mac_bytes.to_vec(),
+        mac: mac_bytes,
     })
 }
 
 /// Parse from header || payload_len (u16 LE) || payload || mac framing.
 pub fn parse_framed(bytes: &[u8], cfg: &ProtocolConfig) -> Result<Packet, ParseError> {
+    parse_framed_ref(bytes, cfg).map(|packet| packet.to_owned())
+}
+
+/// Zero-copy `parse_framed`: same validation, but the payload and MAC borrow from `bytes`.
+pub fn parse_framed_ref<'a>(
+    bytes: &'a [u8],
+    cfg: &ProtocolConfig,
+) -> Result<PacketRef<'a>, ParseError> {
+    let payload_len = framed_payload_len(bytes, cfg)?;
+    let payload_start = HEADER_LEN + 2;
+    let payload_end = payload_start + payload_len;
+
+    let header_bytes = &bytes[..HEADER_LEN];
+    let payload_bytes = &bytes[payload_start..payload_end];
+    let mac_bytes = &bytes[payload_end..];
+
+    parse_packet_ref(header_bytes, payload_bytes, mac_bytes, cfg)
+}
+
+/// Length checks shared by the framed parsers; returns the payload length.
+fn framed_payload_len(bytes: &[u8], cfg: &ProtocolConfig) -> Result<usize, ParseError> {
     if bytes.len() < HEADER_LEN + 2 + cfg.security.mac_len {
         return Err(ParseError::UnexpectedLength);
     }
 
-    let header_bytes = &bytes[..HEADER_LEN];
     let payload_len =
         u16::from_le_bytes(bytes[HEADER_LEN..HEADER_LEN + 2].try_into().unwrap()) as usize;
-    let payload_start = HEADER_LEN + 2;
-    let payload_end = payload_start + payload_len;
+    let payload_end = HEADER_LEN + 2 + payload_len;
     if payload_end > bytes.len() {
         return Err(ParseError::UnexpectedLength);
     }
 
-    let mac_start = payload_end;
-    let mac_len = bytes.len().saturating_sub(mac_start);
-    if mac_len != cfg.security.mac_len {
+    if bytes.len() - payload_end != cfg.security.mac_len {
         return Err(ParseError::MacLengthMismatch);
     }
-
-    let payload_bytes = &bytes[payload_start..payload_end];
-    let mac_bytes = &bytes[mac_start..];
-
-    parse_packet(header_bytes, payload_bytes, mac_bytes, cfg)
+    Ok(payload_len)
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 /// Seal a packet and frame it (header || len || ciphertext || mac) using the provided AEAD.
 /// Placeholder: ciphertext may equal plaintext depending on the AEAD implementation.
 pub fn seal_framed(
@@ -443,6 +890,35 @@ pub fn seal_framed(
     Ok(out)
 }
 
+/// Allocation-free `seal_framed`: encodes and encrypts straight into `out` and returns the frame
+/// length. `packet.mac` is ignored; the AEAD writes the tag after the ciphertext.
+pub fn seal_framed_into(
+    packet: &Packet,
+    cfg: &ProtocolConfig,
+    aead: &dyn Aead,
+    nonce: &[u8],
+    out: &mut [u8],
+) -> Result<usize, CryptoError> {
+    let payload_len = payload_len(&packet.payload);
+    if payload_len > payload_limit(packet.header.kind, cfg) {
+        return Err(CryptoError::Serialize(SerializationError::PayloadTooLarge));
+    }
+    let payload_start = HEADER_LEN + 2;
+    let frame_len = payload_start + payload_len + cfg.security.mac_len;
+    let out = out
+        .get_mut(..frame_len)
+        .ok_or(CryptoError::Serialize(SerializationError::BufferTooSmall))?;
+
+    out[..HEADER_LEN].copy_from_slice(&encode_header(&packet.header));
+    out[HEADER_LEN..payload_start].copy_from_slice(&(payload_len as u16).to_le_bytes());
+    let (body, tag) = out[payload_start..].split_at_mut(payload_len);
+    encode_payload_into(&packet.payload, body).map_err(CryptoError::Serialize)?;
+    let aad = associated_data(&packet.header, payload_len);
+    aead.seal_in_place_detached(nonce, &aad, body, tag)?;
+    Ok(frame_len)
+}
+
+#[cfg(any(feature = "std", feature = "alloc"))]
 /// Parse and authenticate a framed packet. Payload is returned as-is from the AEAD (plaintext if no encryption).
 pub fn open_framed(
     bytes: &[u8],
@@ -450,72 +926,137 @@ pub fn open_framed(
     aead: &dyn Aead,
     nonce: &[u8],
 ) -> Result<Packet, CryptoError> {
-    if bytes.len() < HEADER_LEN + 2 + cfg.security.mac_len {
+    let payload_len = framed_payload_len(bytes, cfg).map_err(CryptoError::Parse)?;
+    let header = decode_header(&bytes[..HEADER_LEN]).map_err(CryptoError::Parse)?;
+    if payload_len > payload_limit(header.kind, cfg) {
         return Err(CryptoError::Parse(ParseError::UnexpectedLength));
     }
 
-    let header_bytes = &bytes[..HEADER_LEN];
-    let header = decode_header(header_bytes).map_err(CryptoError::Parse)?;
-
-    let payload_len =
-        u16::from_le_bytes(bytes[HEADER_LEN..HEADER_LEN + 2].try_into().unwrap()) as usize;
     let payload_start = HEADER_LEN + 2;
-    let payload_end = payload_start + payload_len;
-    if payload_end > bytes.len() {
-        return Err(CryptoError::Parse(ParseError::UnexpectedLength));
-    }
+    let payload_bytes = &bytes[payload_start..payload_start + payload_len];
+    let mac_bytes = &bytes[payload_start + payload_len..];
 
-    let mac_start = payload_end;
-    let mac_len = bytes.len().saturating_sub(mac_start);
-    if mac_len != cfg.security.mac_len {
-        return Err(CryptoError::Parse(ParseError::MacLengthMismatch));
-    }
+    let aad = associated_data(&header, payload_len);
+    let plaintext = aead.open(nonce, &aad, payload_bytes, mac_bytes)?;
 
-    let payload_bytes = &bytes[payload_start..payload_end];
-    let mac_bytes = &bytes[mac_start..];
+    let payload = decode_payload(header.kind, &plaintext).map_err(CryptoError::Parse)?;
 
-    if payload_bytes.len() > payload_limit(header.kind, cfg) {
+    Ok(Packet {
+        header,
+        payload,
+        mac: mac_bytes.to_vec(),
+    })
+}
+
+/// Allocation-free `open_framed`: decrypts the payload inside `bytes` and returns a view that
+/// borrows it. On failure the payload region of `bytes` is unspecified.
+pub fn open_framed_in_place<'a>(
+    bytes: &'a mut [u8],
+    cfg: &ProtocolConfig,
+    aead: &dyn Aead,
+    nonce: &[u8],
+) -> Result<PacketRef<'a>, CryptoError> {
+    let payload_len = framed_payload_len(bytes, cfg).map_err(CryptoError::Parse)?;
+    let header = decode_header(&bytes[..HEADER_LEN]).map_err(CryptoError::Parse)?;
+    if payload_len > payload_limit(header.kind, cfg) {
         return Err(CryptoError::Parse(ParseError::UnexpectedLength));
     }
 
-    let aad = associated_data(&header, payload_bytes.len());
-    let plaintext = aead
-        .open(nonce, &aad, payload_bytes, mac_bytes)
-        .map_err(|e| e)?;
+    let aad = associated_data(&header, payload_len);
+    let (body, mac) = bytes[HEADER_LEN + 2..].split_at_mut(payload_len);
+    aead.open_in_place_detached(nonce, &aad, body, mac)?;
 
-    let payload = decode_payload(header.kind, &plaintext).map_err(CryptoError::Parse)?;
-
-    Ok(Packet {
+    let body: &'a [u8] = body;
+    let payload = decode_payload_ref(header.kind, body).map_err(CryptoError::Parse)?;
+    Ok(PacketRef {
         header,
         payload,
-        mac: mac_bytes.to_vec(),
+        mac,
     })
 }
 
+/// Which end of the link a `SessionKeys` belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Handshake initiator; transmits `KeyboardToDongle`.
+    Keyboard,
+    /// Handshake responder; transmits `DongleToKeyboard`.
+    Dongle,
+}
+
+/// Traffic direction encoded into every nonce so both sides never share one under a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    KeyboardToDongle = 0,
+    DongleToKeyboard = 1,
+}
+
+impl Role {
+    pub fn tx_direction(self) -> Direction {
+        match self {
+            Role::Keyboard => Direction::KeyboardToDongle,
+            Role::Dongle => Direction::DongleToKeyboard,
+        }
+    }
+
+    pub fn rx_direction(self) -> Direction {
+        match self {
+            Role::Keyboard => Direction::DongleToKeyboard,
+            Role::Dongle => Direction::KeyboardToDongle,
+        }
+    }
+}
+
 /// Session-scoped keys and counters; session reset implies counter reset.
+///
+/// Each side owns a transmit counter space; the peer's counters are tracked separately and
+/// never feed our own nonces.
 pub struct SessionKeys {
     pub session_id: u32,
     pub salt: [u8; SESSION_SALT_BYTES],
+    role: Role,
+    epoch: u16,
     counter: u32,
+    rx_window: ReplayWindow,
+    // Peer counters seen under the epoch retired by the last `install_epoch`.
+    retired_window: Option<(u16, ReplayWindow)>,
 }
 
 impl SessionKeys {
+    /// Keyboard-side session (the common case for firmware and the sims).
     pub fn new(session_id: u32, salt: [u8; SESSION_SALT_BYTES]) -> Self {
+        Self::for_role(session_id, salt, Role::Keyboard)
+    }
+
+    pub fn for_role(session_id: u32, salt: [u8; SESSION_SALT_BYTES], role: Role) -> Self {
         Self {
             session_id,
             salt,
+            role,
+            epoch: 0,
             counter: 1, // first data packet after handshake
+            rx_window: ReplayWindow::new(),
+            retired_window: None,
         }
     }
 
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Key epoch mixed into nonces; bumped when the session is rekeyed.
+    pub fn epoch(&self) -> u16 {
+        self.epoch
+    }
+
     /// Counter used for handshake auth; fixed to zero for deterministic nonce derivation.
     pub fn handshake_nonce(&self) -> [u8; NONCE_BYTES] {
-        derive_nonce(&self.salt, 0)
+        self.nonce_for(0)
     }
 
     /// Retrieve and increment the counter for the next packet in this session.
     /// Returns an error if the counter has reached the rekey threshold to prevent nonce reuse.
-    /// When this occurs, the session must be rekeyed (establish new session with fresh salt).
+    /// Rekey in-band (`RekeyState`) once `needs_rekey` trips to avoid reaching it.
     pub fn next_counter(&mut self) -> Result<u32, SessionError> {
         if self.counter >= COUNTER_REKEY_THRESHOLD {
             return Err(SessionError::CounterExhausted);
@@ -525,9 +1066,28 @@ impl SessionKeys {
         Ok(current)
     }
 
+    /// True once the transmit counter passes `COUNTER_REKEY_SOFT_LIMIT`.
+    pub fn needs_rekey(&self) -> bool {
+        self.counter >= COUNTER_REKEY_SOFT_LIMIT
+    }
+
+    /// Move to a ratcheted epoch: new salt, both counter spaces restart.
+    ///
+    /// The old epoch's replay window is kept so frames still in flight during the rekey grace
+    /// window are checked against the counters already seen under that epoch.
+    pub fn install_epoch(&mut self, epoch: u16, salt: [u8; SESSION_SALT_BYTES]) {
+        let retired = (self.epoch, self.rx_window);
+        self.epoch = epoch;
+        self.salt = salt;
+        self.reset_counter();
+        self.retired_window = Some(retired);
+    }
+
     /// Reset counters when a session is rekeyed/restarted.
     pub fn reset_counter(&mut self) {
         self.counter = 1;
+        self.rx_window = ReplayWindow::new();
+        self.retired_window = None;
     }
 
     /// Resume from a persisted next counter (e.g., warm wake with cached session).
@@ -535,19 +1095,78 @@ impl SessionKeys {
         self.counter = next_counter.max(1);
     }
 
-    /// Deterministic nonce derivation: session_salt || counter (LE).
+    /// Nonce for a packet we transmit with `counter`.
     pub fn nonce_for(&self, counter: u32) -> [u8; NONCE_BYTES] {
-        derive_nonce(&self.salt, counter)
+        derive_directional_nonce(&self.salt, self.role.tx_direction(), self.epoch, counter)
+    }
+
+    /// Nonce for a packet the peer transmitted with `counter` (taken from its header).
+    pub fn rx_nonce_for(&self, counter: u32) -> [u8; NONCE_BYTES] {
+        derive_directional_nonce(&self.salt, self.role.rx_direction(), self.epoch, counter)
+    }
+
+    /// Counters accepted from the peer, for `validate_packet`.
+    pub fn replay_window(&self) -> &ReplayWindow {
+        &self.rx_window
+    }
+
+    /// Record an authenticated packet from the peer.
+    pub fn record_rx(&mut self, counter: u32) {
+        self.rx_window.record(counter);
+    }
+
+    /// Replay window for frames that opened under `epoch`: the current one, or the epoch
+    /// retired by the last rekey. `None` for any other epoch.
+    pub fn replay_window_for(&self, epoch: u16) -> Option<&ReplayWindow> {
+        if epoch == self.epoch {
+            return Some(&self.rx_window);
+        }
+        self.retired_window
+            .as_ref()
+            .filter(|(retired, _)| *retired == epoch)
+            .map(|(_, window)| window)
+    }
+
+    /// Record an authenticated packet from the peer that opened under `epoch`.
+    pub fn record_rx_in(&mut self, epoch: u16, counter: u32) {
+        if epoch == self.epoch {
+            self.rx_window.record(counter);
+        } else if let Some((_, window)) = self
+            .retired_window
+            .as_mut()
+            .filter(|(retired, _)| *retired == epoch)
+        {
+            window.record(counter);
+        }
+    }
+
+    /// Restore the peer's replay window persisted before sleep (warm wake).
+    pub fn restore_replay_window(&mut self, window: ReplayWindow) {
+        self.rx_window = window;
     }
 }
 
+/// Keyboard-to-dongle nonce at epoch 0; prefer `SessionKeys::nonce_for` / `rx_nonce_for`.
 pub fn derive_nonce(session_salt: &[u8; SESSION_SALT_BYTES], counter: u32) -> [u8; NONCE_BYTES] {
+    derive_directional_nonce(session_salt, Direction::KeyboardToDongle, 0, counter)
+}
+
+/// Nonce layout: session_salt (16) || counter (u32 LE) || direction (1) || epoch (u16 LE) || 0.
+pub fn derive_directional_nonce(
+    session_salt: &[u8; SESSION_SALT_BYTES],
+    direction: Direction,
+    epoch: u16,
+    counter: u32,
+) -> [u8; NONCE_BYTES] {
     let mut out = [0u8; NONCE_BYTES];
     out[..SESSION_SALT_BYTES].copy_from_slice(session_salt);
     out[SESSION_SALT_BYTES..SESSION_SALT_BYTES + 4].copy_from_slice(&counter.to_le_bytes());
+    out[SESSION_SALT_BYTES + 4] = direction as u8;
+    out[SESSION_SALT_BYTES + 5..SESSION_SALT_BYTES + 7].copy_from_slice(&epoch.to_le_bytes());
     out
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 #[derive(Clone, Debug)]
 pub enum SimEvent {
     KeyboardWakes,
@@ -556,6 +1175,7 @@ pub enum SimEvent {
     Idle,
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 #[derive(Clone, Debug)]
 pub struct SimFrame {
     pub t_ms: u64,
@@ -580,10 +1200,22 @@ pub fn demo_config() -> ProtocolConfig {
             target: Duration::from_millis(6),
             max: Duration::from_millis(10),
         },
+        counters: CounterPolicy {
+            max_jump: 50,
+            resync: true,
+        },
         max_payload_bytes: 32,
+        features: CAP_NKRO
+            | CAP_KEY_EVENTS
+            | CAP_CONSUMER_CONTROL
+            | CAP_POINTER
+            | CAP_ENCODER
+            | CAP_REKEY
+            | CAP_FRAGMENTS,
     }
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 /// Build a high-level wake/auth/key delivery timeline for quick iteration in host-side sims.
 pub fn simulate_wake_sequence(cfg: &ProtocolConfig) -> Vec<SimFrame> {
     let mut t = 0;
@@ -615,6 +1247,7 @@ pub fn simulate_wake_sequence(cfg: &ProtocolConfig) -> Vec<SimFrame> {
     frames
 }
 
+#[cfg(any(feature = "std", feature = "alloc"))]
 /// Construct sample packets showing the expected header/payload/MAC makeup.
 pub fn sample_packets(cfg: &ProtocolConfig) -> Vec<Packet> {
     let session_id = 0x88_77_66_55;
@@ -629,9 +1262,12 @@ pub fn sample_packets(cfg: &ProtocolConfig) -> Vec<Packet> {
                 encrypted: false,
                 needs_ack: true,
                 retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
             },
         },
         payload: Payload::HandshakeInit {
+            caps: Capabilities::from_config(cfg),
             eph_pubkey: [0xAA; KEY_BYTES],
             nonce: session.handshake_nonce(),
         },
@@ -650,9 +1286,13 @@ pub fn sample_packets(cfg: &ProtocolConfig) -> Vec<Packet> {
                 encrypted: true,
                 needs_ack: true,
                 retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
             },
         },
-        payload: Payload::KeyReport { keys: vec![0x04] },
+        payload: KeyReport::boot(0, &[0x04])
+            .expect("one key fits a boot report")
+            .to_payload(),
         mac: vec![0x22; cfg.security.mac_len],
     };
 
@@ -668,10 +1308,14 @@ pub fn sample_packets(cfg: &ProtocolConfig) -> Vec<Packet> {
                 encrypted: true,
                 needs_ack: false,
                 retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
             },
         },
         payload: Payload::Ack {
             ack_counter: key_report.header.counter,
+            sack: 0,
+            indicators: None,
         },
         mac: vec![0x33; cfg.security.mac_len],
     };
@@ -679,8 +1323,38 @@ pub fn sample_packets(cfg: &ProtocolConfig) -> Vec<Packet> {
     vec![handshake, key_report, ack]
 }
 
+// Handshake payloads lead with a message type byte so new messages can't collide by length.
+const HANDSHAKE_INIT: u8 = 0x01;
+const HANDSHAKE_ACCEPT: u8 = 0x02;
+const HANDSHAKE_RESPONSE: u8 = 0x03;
+const HANDSHAKE_PSK_INIT: u8 = 0x04;
+const HANDSHAKE_PSK_RESPONSE: u8 = 0x05;
+const HANDSHAKE_REJECT: u8 = 0x06;
+
+/// Header `kind` byte; also the record kind inside a `Batch` or `Padded` payload.
+pub(crate) fn kind_from_byte(b: u8) -> Result<PacketKind, ParseError> {
+    Ok(match b {
+        0 => PacketKind::Handshake,
+        1 => PacketKind::Control,
+        2 => PacketKind::KeyReport,
+        3 => PacketKind::Ack,
+        4 => PacketKind::KeepAlive,
+        5 => PacketKind::ConsumerControl,
+        6 => PacketKind::SystemControl,
+        7 => PacketKind::Pointer,
+        8 => PacketKind::Encoder,
+        9 => PacketKind::Batch,
+        10 => PacketKind::Padded,
+        other => return Err(ParseError::UnknownKind(other)),
+    })
+}
+
 fn flags_to_byte(flags: &PacketFlags) -> u8 {
-    (flags.encrypted as u8) | ((flags.needs_ack as u8) << 1) | ((flags.retransmit as u8) << 2)
+    (flags.encrypted as u8)
+        | ((flags.needs_ack as u8) << 1)
+        | ((flags.retransmit as u8) << 2)
+        | ((flags.more_fragments as u8) << 3)
+        | ((flags.fragment_index & 0x0F) << 4)
 }
 
 fn flags_from_byte(b: u8) -> PacketFlags {
@@ -688,16 +1362,42 @@ fn flags_from_byte(b: u8) -> PacketFlags {
         encrypted: b & 0x01 != 0,
         needs_ack: b & 0x02 != 0,
         retransmit: b & 0x04 != 0,
+        more_fragments: b & 0x08 != 0,
+        fragment_index: b >> 4,
     }
 }
 
 fn payload_limit(kind: PacketKind, cfg: &ProtocolConfig) -> usize {
     match kind {
-        PacketKind::Handshake => KEY_BYTES + NONCE_BYTES, // handshake can exceed data payload cap
-        _ => cfg.max_payload_bytes as usize,
+        PacketKind::Handshake => HANDSHAKE_MAX_BYTES, // handshake can exceed data payload cap
+        PacketKind::Padded => frame_payload_limit(cfg),
+        _ => data_payload_limit(cfg),
     }
 }
 
+/// Largest data payload: the frame's, less the `Padded` record header when padding is on.
+pub(crate) fn data_payload_limit(cfg: &ProtocolConfig) -> usize {
+    if privacy::padding_enabled(cfg) {
+        // The padded record stores the inner length in one byte.
+        frame_payload_limit(cfg)
+            .saturating_sub(PADDED_RECORD_HEADER_BYTES)
+            .min(u8::MAX as usize)
+    } else {
+        frame_payload_limit(cfg)
+    }
+}
+
+/// `max_payload_bytes`, capped by the fixed `PayloadBytes` buffer in no-alloc builds.
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub(crate) fn frame_payload_limit(cfg: &ProtocolConfig) -> usize {
+    cfg.max_payload_bytes as usize
+}
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+pub(crate) fn frame_payload_limit(cfg: &ProtocolConfig) -> usize {
+    (cfg.max_payload_bytes as usize).min(MAX_PAYLOAD_BYTES)
+}
+
 #[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
@@ -741,6 +1441,8 @@ mod tests {
                     encrypted: false,
                     needs_ack: true,
                     retransmit: false,
+                    more_fragments: false,
+                    fragment_index: 0,
                 },
             },
             payload: Payload::HandshakeAccept { session_id },
@@ -857,4 +1559,41 @@ mod tests {
         session.reset_counter();
         assert!(session.next_counter().is_ok());
     }
+
+    #[test]
+    fn replay_window_accepts_late_and_rejects_duplicates() {
+        let mut window = ReplayWindow::new();
+        assert_eq!(window.highest(), None);
+        for counter in [10, 12, 11, 9] {
+            assert_eq!(window.check(counter), Ok(()));
+            window.record(counter);
+        }
+        assert_eq!(window.highest(), Some(12));
+        for counter in [9, 10, 11, 12] {
+            assert_eq!(window.check(counter), Err(ValidationError::ReplayDetected));
+        }
+        assert_eq!(window.check(8), Ok(()));
+
+        // Slide far ahead: everything behind the window is too old to tell apart from a replay.
+        window.record(12 + REPLAY_WINDOW_BITS);
+        assert_eq!(window.check(12), Err(ValidationError::ReplayDetected));
+        assert_eq!(window.check(13), Ok(()));
+
+        let restored = ReplayWindow::from_bytes(&window.to_bytes());
+        assert_eq!(restored, window);
+    }
+
+    #[test]
+    fn directions_never_share_a_nonce() {
+        let salt = [0x5C; SESSION_SALT_BYTES];
+        let keyboard = SessionKeys::new(0x1234, salt);
+        let dongle = SessionKeys::for_role(0x1234, salt, Role::Dongle);
+
+        for counter in 0..64 {
+            assert_ne!(keyboard.nonce_for(counter), dongle.nonce_for(counter));
+            assert_eq!(keyboard.nonce_for(counter), dongle.rx_nonce_for(counter));
+            assert_eq!(dongle.nonce_for(counter), keyboard.rx_nonce_for(counter));
+        }
+        assert_eq!(keyboard.nonce_for(9), derive_nonce(&salt, 9));
+    }
 }
@@ -0,0 +1,140 @@
+//! Protocol version and capability negotiation, carried in the handshake.
+//!
+//! Firmware on the two ends is updated independently, so each handshake message leads with the
+//! sender's `Capabilities`: the protocol version it speaks, the oldest one it still accepts, the
+//! settings that change how frames are laid out (cipher suite, MAC length, payload cap) and its
+//! `CAP_*` feature bits. Both sides run `negotiate` over their own config and the peer's offer
+//! and, because the rules are symmetric, arrive at the same session `ProtocolConfig` without an
+//! extra round trip: the lower version, the smaller payload cap, the longer MAC and the features
+//! both support. A peer that cannot be reconciled is an `Incompatibility` at handshake time
+//! instead of a link that mis-parses frames later.
+//!
+//! Wire encoding (10 bytes): `version || min_version || cipher_suite || mac_len ||
+//! max_payload_bytes u16 LE || features u32 LE`.
+
+use crate::{
+    ProtocolConfig, CAP_PADDING, KEY_REPORT_MAX_BYTES, MAC_LENGTHS, PADDED_RECORD_HEADER_BYTES,
+};
+
+/// Protocol version this build speaks.
+pub const PROTOCOL_VERSION: u8 = 1;
+/// Oldest peer version this build still talks to.
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+/// `Capabilities` on the wire.
+pub const CAPABILITIES_BYTES: usize = 10;
+/// Smallest negotiated `max_payload_bytes`: a full NKRO key report must still fit. With
+/// `CAP_PADDING` on both sides the report's `PADDED_RECORD_HEADER_BYTES` must fit as well.
+pub const MIN_NEGOTIATED_PAYLOAD_BYTES: u16 = KEY_REPORT_MAX_BYTES as u16;
+
+/// Why two offers cannot share a session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Incompatibility {
+    /// No version both sides accept.
+    Version { local: u8, peer: u8 },
+    /// Peers are configured for different `CipherSuite`s (wire codes).
+    CipherSuite { local: u8, peer: u8 },
+    /// Local or peer MAC length is not one of `MAC_LENGTHS`.
+    MacLength(u8),
+    /// Negotiated payload cap would fall below `MIN_NEGOTIATED_PAYLOAD_BYTES` (plus the padded
+    /// record header when both sides pad).
+    PayloadSize(u16),
+    /// Peer declined an offer this build considers compatible (newer negotiation rules).
+    Refused,
+}
+
+/// One side's offer, sent in every handshake message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub version: u8,
+    pub min_version: u8,
+    /// `CipherSuite as u8`; kept raw so an unknown suite is an `Incompatibility`, not a parse error.
+    pub cipher_suite: u8,
+    pub mac_len: u8,
+    pub max_payload_bytes: u16,
+    /// `CAP_*` bits.
+    pub features: u32,
+}
+
+impl Capabilities {
+    /// This build's offer for `cfg`.
+    pub fn from_config(cfg: &ProtocolConfig) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+            cipher_suite: cfg.security.cipher_suite as u8,
+            mac_len: cfg.security.mac_len as u8,
+            max_payload_bytes: cfg.max_payload_bytes,
+            features: cfg.features,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; CAPABILITIES_BYTES] {
+        let mut out = [0u8; CAPABILITIES_BYTES];
+        out[0] = self.version;
+        out[1] = self.min_version;
+        out[2] = self.cipher_suite;
+        out[3] = self.mac_len;
+        out[4..6].copy_from_slice(&self.max_payload_bytes.to_le_bytes());
+        out[6..].copy_from_slice(&self.features.to_le_bytes());
+        out
+    }
+
+    /// Any bytes decode; `negotiate` decides whether the offer is usable.
+    pub fn from_bytes(bytes: &[u8; CAPABILITIES_BYTES]) -> Self {
+        Self {
+            version: bytes[0],
+            min_version: bytes[1],
+            cipher_suite: bytes[2],
+            mac_len: bytes[3],
+            max_payload_bytes: u16::from_le_bytes([bytes[4], bytes[5]]),
+            features: u32::from_le_bytes(bytes[6..].try_into().unwrap()),
+        }
+    }
+}
+
+/// Session settings for `cfg` talking to a peer that offered `peer`.
+///
+/// Returns the agreed protocol version and `cfg` with the negotiated MAC length, payload cap and
+/// features. Security flags, timing and counter policy are not negotiated: the handshake binds
+/// them into its transcript, so a peer that differs fails authentication. Both sides get the
+/// same result.
+pub fn negotiate(
+    cfg: &ProtocolConfig,
+    peer: &Capabilities,
+) -> Result<(u8, ProtocolConfig), Incompatibility> {
+    let local = Capabilities::from_config(cfg);
+    let version = local.version.min(peer.version);
+    if version < local.min_version.max(peer.min_version) {
+        return Err(Incompatibility::Version {
+            local: local.version,
+            peer: peer.version,
+        });
+    }
+    if peer.cipher_suite != local.cipher_suite {
+        return Err(Incompatibility::CipherSuite {
+            local: local.cipher_suite,
+            peer: peer.cipher_suite,
+        });
+    }
+    for mac_len in [local.mac_len, peer.mac_len] {
+        if !MAC_LENGTHS.contains(&usize::from(mac_len)) {
+            return Err(Incompatibility::MacLength(mac_len));
+        }
+    }
+    let features = local.features & peer.features;
+    let max_payload_bytes = local.max_payload_bytes.min(peer.max_payload_bytes);
+    let min_payload_bytes = if features & CAP_PADDING != 0 {
+        MIN_NEGOTIATED_PAYLOAD_BYTES + PADDED_RECORD_HEADER_BYTES as u16
+    } else {
+        MIN_NEGOTIATED_PAYLOAD_BYTES
+    };
+    if max_payload_bytes < min_payload_bytes {
+        return Err(Incompatibility::PayloadSize(max_payload_bytes));
+    }
+
+    let mut negotiated = *cfg;
+    negotiated.security.mac_len = usize::from(local.mac_len.max(peer.mac_len));
+    negotiated.max_payload_bytes = max_payload_bytes;
+    negotiated.features = features;
+    Ok((version, negotiated))
+}
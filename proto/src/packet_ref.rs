@@ -0,0 +1,302 @@
+//! Borrowed packet views: decode straight out of a frame buffer without copying.
+//!
+//! `parse_framed_ref` and `open_framed_in_place` return a `PacketRef` that points into the
+//! caller's buffer, so a dongle can inspect a frame and forward the key bytes without an
+//! allocation. `to_owned` converts to `Packet` when the data has to outlive the buffer.
+
+use crate::{
+    batch_records, mac_bytes, padded_payload, payload_bytes, Capabilities, Packet, PacketHeader,
+    PacketKind, ParseError, Payload, CAPABILITIES_BYTES, HANDSHAKE_ACCEPT, HANDSHAKE_INIT,
+    HANDSHAKE_PSK_INIT, HANDSHAKE_PSK_RESPONSE, HANDSHAKE_REJECT, HANDSHAKE_RESPONSE, KEY_BYTES,
+    NONCE_BYTES,
+};
+
+/// `Payload` that borrows variable-length fields from the frame it was decoded from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadRef<'a> {
+    HandshakeInit {
+        caps: Capabilities,
+        eph_pubkey: &'a [u8; KEY_BYTES],
+        nonce: &'a [u8; NONCE_BYTES],
+    },
+    HandshakeAccept {
+        session_id: u32,
+    },
+    HandshakeResponse {
+        caps: Capabilities,
+        eph_pubkey: &'a [u8; KEY_BYTES],
+        session_id: u32,
+    },
+    PskInit {
+        caps: Capabilities,
+        nonce: &'a [u8; NONCE_BYTES],
+    },
+    PskResponse {
+        caps: Capabilities,
+        nonce: &'a [u8; NONCE_BYTES],
+    },
+    HandshakeReject {
+        caps: Capabilities,
+    },
+    Control {
+        code: u8,
+        data: &'a [u8],
+    },
+    KeyReport {
+        keys: &'a [u8],
+    },
+    Ack {
+        ack_counter: u32,
+        sack: u32,
+        indicators: Option<u8>,
+    },
+    KeepAlive,
+    ConsumerControl {
+        usage: u16,
+    },
+    SystemControl {
+        usage: u8,
+    },
+    Pointer {
+        buttons: u8,
+        dx: i16,
+        dy: i16,
+        wheel: i8,
+        pan: i8,
+    },
+    Encoder {
+        encoder: u8,
+        detents: i8,
+    },
+    Batch {
+        records: &'a [u8],
+    },
+    Padded {
+        record: &'a [u8],
+    },
+}
+
+impl PayloadRef<'_> {
+    /// Copy into an owned `Payload`.
+    ///
+    /// Refs from `parse_framed_ref` / `open_framed_in_place` always fit; in no-alloc builds a
+    /// hand-built ref longer than `MAX_PAYLOAD_BYTES` panics.
+    pub fn to_owned(&self) -> Payload {
+        match *self {
+            PayloadRef::HandshakeInit {
+                caps,
+                eph_pubkey,
+                nonce,
+            } => Payload::HandshakeInit {
+                caps,
+                eph_pubkey: *eph_pubkey,
+                nonce: *nonce,
+            },
+            PayloadRef::HandshakeAccept { session_id } => Payload::HandshakeAccept { session_id },
+            PayloadRef::HandshakeResponse {
+                caps,
+                eph_pubkey,
+                session_id,
+            } => Payload::HandshakeResponse {
+                caps,
+                eph_pubkey: *eph_pubkey,
+                session_id,
+            },
+            PayloadRef::PskInit { caps, nonce } => Payload::PskInit {
+                caps,
+                nonce: *nonce,
+            },
+            PayloadRef::PskResponse { caps, nonce } => Payload::PskResponse {
+                caps,
+                nonce: *nonce,
+            },
+            PayloadRef::HandshakeReject { caps } => Payload::HandshakeReject { caps },
+            PayloadRef::Control { code, data } => Payload::Control {
+                code,
+                data: payload_bytes(data).expect("Control data exceeds MAX_PAYLOAD_BYTES"),
+            },
+            PayloadRef::KeyReport { keys } => Payload::KeyReport {
+                keys: payload_bytes(keys).expect("key report exceeds MAX_PAYLOAD_BYTES"),
+            },
+            PayloadRef::Ack {
+                ack_counter,
+                sack,
+                indicators,
+            } => Payload::Ack {
+                ack_counter,
+                sack,
+                indicators,
+            },
+            PayloadRef::KeepAlive => Payload::KeepAlive,
+            PayloadRef::ConsumerControl { usage } => Payload::ConsumerControl { usage },
+            PayloadRef::SystemControl { usage } => Payload::SystemControl { usage },
+            PayloadRef::Pointer {
+                buttons,
+                dx,
+                dy,
+                wheel,
+                pan,
+            } => Payload::Pointer {
+                buttons,
+                dx,
+                dy,
+                wheel,
+                pan,
+            },
+            PayloadRef::Encoder { encoder, detents } => Payload::Encoder { encoder, detents },
+            PayloadRef::Batch { records } => Payload::Batch {
+                records: payload_bytes(records).expect("batch exceeds MAX_PAYLOAD_BYTES"),
+            },
+            PayloadRef::Padded { record } => Payload::Padded {
+                record: payload_bytes(record).expect("padded record exceeds MAX_PAYLOAD_BYTES"),
+            },
+        }
+    }
+}
+
+/// An opened or parsed frame whose payload and MAC borrow from the input buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PacketRef<'a> {
+    pub header: PacketHeader,
+    pub payload: PayloadRef<'a>,
+    pub mac: &'a [u8],
+}
+
+impl PacketRef<'_> {
+    /// Copy into today's owned `Packet`.
+    pub fn to_owned(&self) -> Packet {
+        Packet {
+            header: self.header,
+            payload: self.payload.to_owned(),
+            mac: mac_bytes(self.mac).expect("MAC exceeds MAX_MAC_BYTES"),
+        }
+    }
+}
+
+/// Borrowing counterpart of `decode_payload`; same structural checks.
+pub fn decode_payload_ref(kind: PacketKind, bytes: &[u8]) -> Result<PayloadRef<'_>, ParseError> {
+    match kind {
+        PacketKind::Handshake => {
+            let (&msg_type, body) = bytes.split_first().ok_or(ParseError::UnexpectedLength)?;
+            if msg_type == HANDSHAKE_ACCEPT {
+                let session_id = body.try_into().map_err(|_| ParseError::UnexpectedLength)?;
+                return Ok(PayloadRef::HandshakeAccept {
+                    session_id: u32::from_le_bytes(session_id),
+                });
+            }
+            // Every other handshake message leads with the sender's capabilities.
+            let caps_and_rest = (body.len() >= CAPABILITIES_BYTES)
+                .then(|| body.split_at(CAPABILITIES_BYTES))
+                .map(|(caps, rest)| (Capabilities::from_bytes(caps.try_into().unwrap()), rest));
+            match (msg_type, caps_and_rest) {
+                (HANDSHAKE_INIT, Some((caps, rest))) if rest.len() == KEY_BYTES + NONCE_BYTES => {
+                    let (key, nonce) = rest.split_at(KEY_BYTES);
+                    Ok(PayloadRef::HandshakeInit {
+                        caps,
+                        eph_pubkey: key.try_into().unwrap(),
+                        nonce: nonce.try_into().unwrap(),
+                    })
+                }
+                (HANDSHAKE_RESPONSE, Some((caps, rest))) if rest.len() == KEY_BYTES + 4 => {
+                    let (key, session_id) = rest.split_at(KEY_BYTES);
+                    Ok(PayloadRef::HandshakeResponse {
+                        caps,
+                        eph_pubkey: key.try_into().unwrap(),
+                        session_id: u32::from_le_bytes(session_id.try_into().unwrap()),
+                    })
+                }
+                (HANDSHAKE_PSK_INIT, Some((caps, rest))) if rest.len() == NONCE_BYTES => {
+                    Ok(PayloadRef::PskInit {
+                        caps,
+                        nonce: rest.try_into().unwrap(),
+                    })
+                }
+                (HANDSHAKE_PSK_RESPONSE, Some((caps, rest))) if rest.len() == NONCE_BYTES => {
+                    Ok(PayloadRef::PskResponse {
+                        caps,
+                        nonce: rest.try_into().unwrap(),
+                    })
+                }
+                (HANDSHAKE_REJECT, Some((caps, []))) => Ok(PayloadRef::HandshakeReject { caps }),
+                (
+                    HANDSHAKE_INIT
+                    | HANDSHAKE_RESPONSE
+                    | HANDSHAKE_PSK_INIT
+                    | HANDSHAKE_PSK_RESPONSE
+                    | HANDSHAKE_REJECT,
+                    _,
+                ) => Err(ParseError::UnexpectedLength),
+                (other, _) => Err(ParseError::UnknownHandshake(other)),
+            }
+        }
+        PacketKind::Control => {
+            let (&code, data) = bytes.split_first().ok_or(ParseError::UnexpectedLength)?;
+            Ok(PayloadRef::Control { code, data })
+        }
+        PacketKind::KeyReport => Ok(PayloadRef::KeyReport { keys: bytes }),
+        PacketKind::Ack => {
+            let (counter, rest) = bytes
+                .split_first_chunk::<4>()
+                .ok_or(ParseError::UnexpectedLength)?;
+            // Optional sack (4 bytes) and LED byte: the length says which are present.
+            let (sack, indicators) = match *rest {
+                [] => (0, None),
+                [leds] => (0, Some(leds)),
+                [a, b, c, d] => (u32::from_le_bytes([a, b, c, d]), None),
+                [a, b, c, d, leds] => (u32::from_le_bytes([a, b, c, d]), Some(leds)),
+                _ => return Err(ParseError::UnexpectedLength),
+            };
+            Ok(PayloadRef::Ack {
+                ack_counter: u32::from_le_bytes(*counter),
+                sack,
+                indicators,
+            })
+        }
+        PacketKind::KeepAlive => {
+            if !bytes.is_empty() {
+                return Err(ParseError::UnexpectedLength);
+            }
+            Ok(PayloadRef::KeepAlive)
+        }
+        PacketKind::ConsumerControl => {
+            let usage = bytes.try_into().map_err(|_| ParseError::UnexpectedLength)?;
+            Ok(PayloadRef::ConsumerControl {
+                usage: u16::from_le_bytes(usage),
+            })
+        }
+        PacketKind::SystemControl => match *bytes {
+            [usage] => Ok(PayloadRef::SystemControl { usage }),
+            _ => Err(ParseError::UnexpectedLength),
+        },
+        PacketKind::Pointer => match *bytes {
+            [buttons, dx_lo, dx_hi, dy_lo, dy_hi, wheel, pan] => Ok(PayloadRef::Pointer {
+                buttons,
+                dx: i16::from_le_bytes([dx_lo, dx_hi]),
+                dy: i16::from_le_bytes([dy_lo, dy_hi]),
+                wheel: wheel as i8,
+                pan: pan as i8,
+            }),
+            _ => Err(ParseError::UnexpectedLength),
+        },
+        PacketKind::Encoder => match *bytes {
+            [encoder, detents] => Ok(PayloadRef::Encoder {
+                encoder,
+                detents: detents as i8,
+            }),
+            _ => Err(ParseError::UnexpectedLength),
+        },
+        PacketKind::Batch => {
+            if bytes.is_empty() {
+                return Err(ParseError::UnexpectedLength);
+            }
+            for record in batch_records(bytes) {
+                record?;
+            }
+            Ok(PayloadRef::Batch { records: bytes })
+        }
+        PacketKind::Padded => {
+            padded_payload(bytes)?;
+            Ok(PayloadRef::Padded { record: bytes })
+        }
+    }
+}
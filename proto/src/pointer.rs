@@ -0,0 +1,154 @@
+//! Relative pointer motion and rotary encoder detents, with coalescing for a busy link.
+//!
+//! Sensors and encoders are sampled far more often than frames go out, so samples are summed in
+//! an accumulator and `take` turns whatever has built up into one payload when the radio is free
+//! (typically once the previous frame is acked). Nothing is dropped:
+//! - Motion, wheel and pan add up; a total beyond the wire range goes out clamped and the rest
+//!   stays pending for the next frame.
+//! - Button changes queue up and each button flips at most once per frame, so a click, or a
+//!   double click, shorter than the frame interval still reaches the host as alternating press
+//!   and release frames. Past `MAX_BUTTON_TRANSITIONS` queued changes the newest one is
+//!   overwritten.
+//! - Encoder detents add up per encoder; opposite turns cancel.
+//!
+//! Wire encoding:
+//! - `Payload::Pointer`: `buttons || dx i16 LE || dy i16 LE || wheel i8 || pan i8`
+//! - `Payload::Encoder`: `encoder || detents i8` (positive is clockwise)
+
+use crate::Payload;
+
+/// Encoders an `EncoderAccumulator` tracks (two per Sofle half).
+pub const MAX_ENCODERS: usize = 4;
+/// Button changes a `PointerAccumulator` holds between frames (four clicks).
+pub const MAX_BUTTON_TRANSITIONS: usize = 8;
+
+pub const MOUSE_BUTTON_LEFT: u8 = 0x01;
+pub const MOUSE_BUTTON_RIGHT: u8 = 0x02;
+pub const MOUSE_BUTTON_MIDDLE: u8 = 0x04;
+pub const MOUSE_BUTTON_BACK: u8 = 0x08;
+pub const MOUSE_BUTTON_FORWARD: u8 = 0x10;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EncoderError {
+    /// Encoder index is not below `MAX_ENCODERS`.
+    InvalidEncoder(u8),
+}
+
+/// Keyboard side: sums pointer samples until the next frame can go out.
+#[derive(Clone, Debug, Default)]
+pub struct PointerAccumulator {
+    buttons: u8,
+    reported_buttons: u8,
+    // Button masks after each change not yet reported, oldest first.
+    transitions: heapless::Deque<u8, MAX_BUTTON_TRANSITIONS>,
+    dx: i32,
+    dy: i32,
+    wheel: i32,
+    pan: i32,
+}
+
+impl PointerAccumulator {
+    /// No motion pending and no button held.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_motion(&mut self, dx: i16, dy: i16) {
+        self.dx = self.dx.saturating_add(dx.into());
+        self.dy = self.dy.saturating_add(dy.into());
+    }
+
+    pub fn push_scroll(&mut self, wheel: i8, pan: i8) {
+        self.wheel = self.wheel.saturating_add(wheel.into());
+        self.pan = self.pan.saturating_add(pan.into());
+    }
+
+    /// Record the current button bitmask (`MOUSE_BUTTON_*`).
+    pub fn set_buttons(&mut self, buttons: u8) {
+        if buttons == self.buttons {
+            return;
+        }
+        self.buttons = buttons;
+        if let Err(buttons) = self.transitions.push_back(buttons) {
+            if let Some(newest) = self.transitions.back_mut() {
+                *newest = buttons;
+            }
+        }
+    }
+
+    /// True when `take` would return a payload.
+    pub fn is_pending(&self) -> bool {
+        !self.transitions.is_empty()
+            || self.dx != 0
+            || self.dy != 0
+            || self.wheel != 0
+            || self.pan != 0
+    }
+
+    /// Everything accumulated since the last call as one `Payload::Pointer`, or `None` when
+    /// nothing changed. Call again while `is_pending` to drain clamped motion or queued edges.
+    pub fn take(&mut self) -> Option<Payload> {
+        if !self.is_pending() {
+            return None;
+        }
+        let mut flipped = 0;
+        while let Some(&next) = self.transitions.front() {
+            let edges = self.reported_buttons ^ next;
+            if edges & flipped != 0 {
+                break;
+            }
+            flipped |= edges;
+            self.reported_buttons = next;
+            self.transitions.pop_front();
+        }
+        Some(Payload::Pointer {
+            buttons: self.reported_buttons,
+            dx: drain(&mut self.dx, i16::MIN.into(), i16::MAX.into()) as i16,
+            dy: drain(&mut self.dy, i16::MIN.into(), i16::MAX.into()) as i16,
+            wheel: drain(&mut self.wheel, i8::MIN.into(), i8::MAX.into()) as i8,
+            pan: drain(&mut self.pan, i8::MIN.into(), i8::MAX.into()) as i8,
+        })
+    }
+}
+
+/// Keyboard side: sums detents per encoder until the next frame can go out.
+#[derive(Clone, Debug, Default)]
+pub struct EncoderAccumulator {
+    detents: [i32; MAX_ENCODERS],
+}
+
+impl EncoderAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `detents` turns of `encoder` (positive is clockwise).
+    pub fn push(&mut self, encoder: u8, detents: i8) -> Result<(), EncoderError> {
+        let total = self
+            .detents
+            .get_mut(usize::from(encoder))
+            .ok_or(EncoderError::InvalidEncoder(encoder))?;
+        *total = total.saturating_add(detents.into());
+        Ok(())
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.detents.iter().any(|&d| d != 0)
+    }
+
+    /// Net detents of the lowest-numbered encoder that moved, as one `Payload::Encoder`.
+    pub fn take(&mut self) -> Option<Payload> {
+        let encoder = self.detents.iter().position(|&d| d != 0)?;
+        Some(Payload::Encoder {
+            encoder: encoder as u8,
+            detents: drain(&mut self.detents[encoder], i8::MIN.into(), i8::MAX.into()) as i8,
+        })
+    }
+}
+
+/// Take as much of `total` as fits `min..=max`, leaving the remainder behind.
+fn drain(total: &mut i32, min: i32, max: i32) -> i32 {
+    let out = (*total).clamp(min, max);
+    *total -= out;
+    out
+}
@@ -0,0 +1,38 @@
+//! Receive-side enforcement of the `SecurityConfig` policy flags.
+//!
+//! A receiver that accepts a cleartext key report is open to MouseJack-style keystroke
+//! injection: the attacker never needs the session key, only a radio. `validate_packet` runs
+//! `check_security_policy` before anything else, so every frame after the handshake must
+//! arrive with `flags.encrypted` set. That includes Acks, which carry the host LED state and
+//! the selective-ack bitmap that decides what the keyboard retransmits, and KeepAlives.
+//! Handshake frames are authenticated by the handshake itself.
+//!
+//! `replay_protection` makes the replay window mandatory for every non-handshake frame; with it
+//! off, `validate_packet` ignores the window. `forward_secure` refuses the pre-shared-key
+//! handshake, whose session keys fall with the PSK.
+
+use crate::{Packet, PacketKind, Payload, ProtocolConfig, ReplayWindow, ValidationError};
+
+/// Check `packet` against `cfg.security`; `replay` is the window the caller will check it with.
+pub fn check_security_policy(
+    packet: &Packet,
+    cfg: &ProtocolConfig,
+    replay: Option<&ReplayWindow>,
+) -> Result<(), ValidationError> {
+    let kind = packet.header.kind;
+    if kind != PacketKind::Handshake && !packet.header.flags.encrypted {
+        return Err(ValidationError::Unencrypted);
+    }
+    if cfg.security.forward_secure
+        && matches!(
+            packet.payload,
+            Payload::PskInit { .. } | Payload::PskResponse { .. }
+        )
+    {
+        return Err(ValidationError::NotForwardSecure);
+    }
+    if cfg.security.replay_protection && kind != PacketKind::Handshake && replay.is_none() {
+        return Err(ValidationError::NoReplayWindow);
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Traffic-analysis resistance: constant-size data frames and cover traffic.
+//!
+//! The framed length of a `KeyReport` tells an observer how many keys are down, and the spacing
+//! of frames gives away inter-keystroke intervals; together they are enough for keystroke-timing
+//! attacks. With `CAP_PADDING` negotiated, `Session::send` wraps every data payload, and
+//! KeepAlive, in a `Padded` payload that fills the frame, so on air they all share one length,
+//! kind and flags. The receiving `Session` unwraps them again.
+//!
+//! `CoverTraffic` optionally puts the link on a fixed schedule: one frame per slot, the reports
+//! that piled up if there are any, a padded KeepAlive otherwise. Reports then wait for the next
+//! slot, at most the slot interval, itself capped at `LatencyBudget::target`. Without padding the
+//! cover frames are told apart by their length and kind, so they only help together.
+//!
+//! Both cost energy: padding sends `max_payload_bytes` on every frame and leaves data payloads
+//! `PADDED_RECORD_HEADER_BYTES` less room; cover traffic sends a frame, and receives its Ack, in
+//! every idle slot. `host-sim` prints the cost for a typing burst.
+//!
+//! Wire encoding, `Payload::Padded` under `PacketKind::Padded`: one record `kind || len ||
+//! payload` as in a `Batch`, where `kind` is a paddable kind (`PacketKind::paddable`), then zero
+//! bytes up to `max_payload_bytes`. The padding is inside the ciphertext; only the total shows.
+
+use crate::{
+    decode_payload_ref, encode_payload_into, frame_payload_limit, kind_from_byte, payload_len,
+    zeroed_payload_bytes, ParseError, Payload, PayloadRef, ProtocolConfig, CAP_PADDING,
+};
+#[cfg(not(feature = "std"))]
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+/// Kind and length bytes in front of the padded payload.
+pub const PADDED_RECORD_HEADER_BYTES: usize = 2;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PaddingError {
+    /// Handshake, Ack and Padded payloads are never padded.
+    NotPaddable,
+    /// Payload plus its record header exceeds the frame, or its length does not fit the
+    /// record's length byte.
+    TooLarge,
+}
+
+/// Whether `cfg` (after negotiation, both peers) pads data frames.
+pub(crate) fn padding_enabled(cfg: &ProtocolConfig) -> bool {
+    cfg.features & CAP_PADDING != 0
+}
+
+/// `payload` as a `Padded` payload filling `max_payload_bytes`.
+pub fn pad_payload(cfg: &ProtocolConfig, payload: &Payload) -> Result<Payload, PaddingError> {
+    if !payload.kind().paddable() {
+        return Err(PaddingError::NotPaddable);
+    }
+    let frame = frame_payload_limit(cfg);
+    let len = payload_len(payload);
+    if PADDED_RECORD_HEADER_BYTES + len > frame || len > usize::from(u8::MAX) {
+        return Err(PaddingError::TooLarge);
+    }
+    let mut record = zeroed_payload_bytes(frame).map_err(|_| PaddingError::TooLarge)?;
+    record[0] = payload.kind() as u8;
+    record[1] = len as u8;
+    let end = PADDED_RECORD_HEADER_BYTES + len;
+    encode_payload_into(payload, &mut record[PADDED_RECORD_HEADER_BYTES..end])
+        .expect("sized by payload_len");
+    Ok(Payload::Padded { record })
+}
+
+/// Typed view of the payload inside `Payload::Padded` bytes; non-zero padding is rejected.
+pub fn padded_payload(record: &[u8]) -> Result<PayloadRef<'_>, ParseError> {
+    let (&[code, len], rest) = record
+        .split_first_chunk::<PADDED_RECORD_HEADER_BYTES>()
+        .ok_or(ParseError::UnexpectedLength)?;
+    let kind = kind_from_byte(code)?;
+    if !kind.paddable() {
+        return Err(ParseError::NotPaddable(code));
+    }
+    if rest.len() < usize::from(len) {
+        return Err(ParseError::UnexpectedLength);
+    }
+    let (payload, padding) = rest.split_at(usize::from(len));
+    if padding.iter().any(|&b| b != 0) {
+        return Err(ParseError::UnexpectedLength);
+    }
+    decode_payload_ref(kind, payload)
+}
+
+/// Keyboard side: a fixed schedule of slots, one frame in each.
+pub struct CoverTraffic {
+    interval_ms: u64,
+    next_ms: u64,
+}
+
+impl CoverTraffic {
+    /// Slots every `interval`, capped at `cfg.latency.target` and at least 1 ms; the first ends
+    /// at `now_ms + interval`.
+    pub fn new(cfg: &ProtocolConfig, interval: Duration, now_ms: u64) -> Self {
+        let interval_ms = (interval.min(cfg.latency.target).as_millis() as u64).max(1);
+        Self {
+            interval_ms,
+            next_ms: now_ms + interval_ms,
+        }
+    }
+
+    /// At a slot boundary, the payload to send: whatever `pending` yields (such as
+    /// `|| batcher.flush()`), or a cover KeepAlive. `None` between slots. Slots that passed
+    /// without a `poll` are skipped, not made up.
+    pub fn poll(
+        &mut self,
+        now_ms: u64,
+        pending: impl FnOnce() -> Option<Payload>,
+    ) -> Option<Payload> {
+        if now_ms < self.next_ms {
+            return None;
+        }
+        let missed = (now_ms - self.next_ms) / self.interval_ms;
+        self.next_ms += (missed + 1) * self.interval_ms;
+        Some(pending().unwrap_or(Payload::KeepAlive))
+    }
+
+    pub fn interval_ms(&self) -> u64 {
+        self.interval_ms
+    }
+
+    /// End of the current slot, when `poll` next returns a payload.
+    pub fn deadline_ms(&self) -> u64 {
+        self.next_ms
+    }
+}
@@ -0,0 +1,260 @@
+//! In-band rekeying: ratchet the AEAD key and salt forward over `PacketKind::Control`.
+//!
+//! Either side may start a rekey once `SessionKeys::needs_rekey` trips. The initiator sends
+//! `RekeyRequest(epoch + 1)` under the current keys; the peer switches and answers with
+//! `RekeyConfirm` under the new keys, which proves it derived the same material. The initiator
+//! switches on the first frame that opens under the proposed keys (the confirm, or anything sent
+//! after a lost confirm) and confirms back, so both sides have proven the new epoch.
+//!
+//! The old epoch stays open until the peer is seen on the new one, however long that takes, so a
+//! lost confirm only costs a retransmitted request; after that, frames already in flight are
+//! accepted for a grace window.
+
+use crate::{
+    derive_directional_nonce, ControlMessage, Direction, Payload, ProtocolConfig, SessionKeys,
+    KEY_BYTES, MAX_RETRANSMIT_ATTEMPTS, NONCE_BYTES, SESSION_SALT_BYTES,
+};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+/// Control code asking the peer to move to the epoch in `data` (u16 LE).
+pub const CONTROL_REKEY_REQUEST: u8 = 0x10;
+/// Control code confirming the switch to the epoch in `data` (u16 LE).
+pub const CONTROL_REKEY_CONFIRM: u8 = 0x11;
+
+const RATCHET_INFO: &[u8] = b"keyboard-project rekey";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RekeyError {
+    /// Message names an epoch we cannot move to from the current state.
+    UnexpectedEpoch,
+    /// A rekey is already waiting for confirmation.
+    AlreadyPending,
+    /// u16 epoch space used up; a fresh handshake is required.
+    EpochExhausted,
+    /// `Session::begin_rekey` before `Session::enable_rekey`.
+    NotEnabled,
+    /// The session's AEAD cannot be rebuilt from a key (`Aead::with_key`).
+    AeadNotRekeyable,
+}
+
+/// Key material for one epoch of a session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpochKeys {
+    pub epoch: u16,
+    pub key: [u8; KEY_BYTES],
+    pub salt: [u8; SESSION_SALT_BYTES],
+}
+
+impl EpochKeys {
+    /// Symmetric KDF chain step: HKDF(salt, key, info || next_epoch) -> key' || salt'.
+    /// One-way, so a leaked epoch key does not expose earlier traffic.
+    pub fn ratchet(&self) -> Result<EpochKeys, RekeyError> {
+        let epoch = self
+            .epoch
+            .checked_add(1)
+            .ok_or(RekeyError::EpochExhausted)?;
+        let mut info = [0u8; RATCHET_INFO.len() + 2];
+        info[..RATCHET_INFO.len()].copy_from_slice(RATCHET_INFO);
+        info[RATCHET_INFO.len()..].copy_from_slice(&epoch.to_le_bytes());
+
+        let mut okm = [0u8; KEY_BYTES + SESSION_SALT_BYTES];
+        Hkdf::<Sha256>::new(Some(&self.salt), &self.key)
+            .expand(&info, &mut okm)
+            .expect("48 bytes is a valid HKDF-SHA256 length");
+        let mut key = [0u8; KEY_BYTES];
+        let mut salt = [0u8; SESSION_SALT_BYTES];
+        key.copy_from_slice(&okm[..KEY_BYTES]);
+        salt.copy_from_slice(&okm[KEY_BYTES..]);
+        Ok(EpochKeys { epoch, key, salt })
+    }
+
+    pub fn nonce(&self, direction: Direction, counter: u32) -> [u8; NONCE_BYTES] {
+        derive_directional_nonce(&self.salt, direction, self.epoch, counter)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RekeyMessage {
+    Request { epoch: u16 },
+    Confirm { epoch: u16 },
+}
+
+impl RekeyMessage {
+    pub fn to_payload(self) -> Payload {
+        ControlMessage::Rekey(self).to_payload()
+    }
+
+    /// Returns `None` for anything that is not a well-formed rekey Control payload.
+    pub fn from_payload(payload: &Payload) -> Option<Self> {
+        match ControlMessage::from_payload(payload) {
+            Ok(ControlMessage::Rekey(msg)) => Some(msg),
+            _ => None,
+        }
+    }
+}
+
+/// Tracks the current, proposed and just-retired epochs for one side of a session.
+pub struct RekeyState {
+    grace_ms: u64,
+    current: EpochKeys,
+    pending: Option<EpochKeys>,
+    previous: Option<EpochKeys>,
+    // Accept `previous` until this time; `None` while the peer has not been seen on `current`.
+    previous_until: Option<u64>,
+}
+
+impl RekeyState {
+    /// Start at epoch 0 with the key/salt from the handshake.
+    /// The grace window covers one full retransmit cycle at the latency budget.
+    pub fn new(cfg: &ProtocolConfig, key: [u8; KEY_BYTES], session: &SessionKeys) -> Self {
+        let grace_ms = cfg.latency.max.as_millis() as u64 * (MAX_RETRANSMIT_ATTEMPTS as u64 + 1);
+        Self {
+            grace_ms,
+            current: EpochKeys {
+                epoch: session.epoch(),
+                key,
+                salt: session.salt,
+            },
+            pending: None,
+            previous: None,
+            previous_until: None,
+        }
+    }
+
+    pub fn current(&self) -> &EpochKeys {
+        &self.current
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Keys to try, in order, when opening a frame from the peer: current epoch, the epoch we
+    /// proposed (the peer's confirm arrives under it), then the retired epoch until the peer is
+    /// on the new one plus the grace window. Check the opened frame against
+    /// `SessionKeys::replay_window_for` its epoch, then pass the epoch to `on_authenticated`.
+    pub fn rx_candidates(&self, now_ms: u64) -> impl Iterator<Item = &EpochKeys> {
+        let previous = self
+            .previous
+            .as_ref()
+            .filter(|_| self.previous_until.is_none_or(|until| now_ms <= until));
+        core::iter::once(&self.current)
+            .chain(self.pending.as_ref())
+            .chain(previous)
+    }
+
+    /// Propose the next epoch. Send the returned payload under the current keys.
+    pub fn begin(&mut self) -> Result<Payload, RekeyError> {
+        if self.pending.is_some() {
+            return Err(RekeyError::AlreadyPending);
+        }
+        let next = self.current.ratchet()?;
+        let msg = RekeyMessage::Request { epoch: next.epoch };
+        self.pending = Some(next);
+        Ok(msg.to_payload())
+    }
+
+    /// Note that a frame from the peer authenticated under `received_epoch`; call it for every
+    /// accepted frame, before `on_message`.
+    ///
+    /// A frame under the epoch we proposed means the peer switched: we switch too and return the
+    /// `RekeyConfirm` to send under the new keys. A frame under the current epoch starts the
+    /// grace window for the retired one.
+    pub fn on_authenticated(
+        &mut self,
+        received_epoch: u16,
+        session: &mut SessionKeys,
+        now_ms: u64,
+    ) -> Option<Payload> {
+        if self
+            .pending
+            .as_ref()
+            .is_some_and(|p| p.epoch == received_epoch)
+        {
+            let next = self.pending.take().expect("checked above");
+            self.install(next, session);
+            self.peer_on_current(now_ms);
+            return Some(
+                RekeyMessage::Confirm {
+                    epoch: received_epoch,
+                }
+                .to_payload(),
+            );
+        }
+        if received_epoch == self.current.epoch {
+            self.peer_on_current(now_ms);
+        }
+        None
+    }
+
+    /// Handle a rekey message that opened under `received_epoch`.
+    ///
+    /// Returns a payload to send back (under the now-current keys) when one is required.
+    /// On every switch `session` moves to the new salt/epoch and its counters restart.
+    pub fn on_message(
+        &mut self,
+        msg: RekeyMessage,
+        received_epoch: u16,
+        session: &mut SessionKeys,
+        now_ms: u64,
+    ) -> Result<Option<Payload>, RekeyError> {
+        match msg {
+            RekeyMessage::Request { epoch } if received_epoch == self.current.epoch => {
+                if epoch != self.current.epoch.wrapping_add(1) {
+                    return Err(RekeyError::UnexpectedEpoch);
+                }
+                // Simultaneous rekey: both proposed the same epoch; the request settles it.
+                let next = match self.pending.take() {
+                    Some(pending) => pending,
+                    None => self.current.ratchet()?,
+                };
+                self.install(next, session);
+                Ok(Some(RekeyMessage::Confirm { epoch }.to_payload()))
+            }
+            RekeyMessage::Request { epoch }
+                if epoch == self.current.epoch
+                    && self
+                        .previous
+                        .as_ref()
+                        .is_some_and(|p| p.epoch == received_epoch) =>
+            {
+                // Retransmitted request after we already switched (our confirm was lost):
+                // confirm again, however long ago we switched.
+                Ok(Some(RekeyMessage::Confirm { epoch }.to_payload()))
+            }
+            RekeyMessage::Confirm { epoch }
+                if received_epoch == epoch
+                    && self.pending.as_ref().is_some_and(|p| p.epoch == epoch) =>
+            {
+                let next = self.pending.take().expect("checked above");
+                self.install(next, session);
+                self.peer_on_current(now_ms);
+                // Confirm back so the peer knows we switched too.
+                Ok(Some(RekeyMessage::Confirm { epoch }.to_payload()))
+            }
+            // Confirm for the epoch we already moved to: the peer's answer to ours, or a
+            // duplicate.
+            RekeyMessage::Confirm { epoch }
+                if epoch == self.current.epoch && received_epoch == epoch =>
+            {
+                self.peer_on_current(now_ms);
+                Ok(None)
+            }
+            _ => Err(RekeyError::UnexpectedEpoch),
+        }
+    }
+
+    fn peer_on_current(&mut self, now_ms: u64) {
+        if self.previous.is_some() && self.previous_until.is_none() {
+            self.previous_until = Some(now_ms + self.grace_ms);
+        }
+    }
+
+    fn install(&mut self, next: EpochKeys, session: &mut SessionKeys) {
+        session.install_epoch(next.epoch, next.salt);
+        let retired = core::mem::replace(&mut self.current, next);
+        self.previous = Some(retired);
+        self.previous_until = None;
+    }
+}
@@ -0,0 +1,180 @@
+//! Sliding-window anti-replay (RFC 4303 / DTLS style) for received counters.
+
+use crate::{ControlMessage, Packet, Payload, ProtocolConfig, ValidationError};
+
+/// Number of counters below the highest one that can still arrive late.
+pub const REPLAY_WINDOW_BITS: u32 = 64;
+/// Serialized size: highest counter (u32 LE) || bitmap (u64 LE).
+pub const REPLAY_WINDOW_BYTES: usize = 12;
+
+/// Bitmap of recently accepted counters; bit `i` marks `highest - i` as seen.
+///
+/// `Session` runs `check` (via `validate_packet`) after the AEAD opened the frame, so
+/// `ReplayDetected` always means a genuine duplicate. `record` must likewise only see
+/// authenticated counters, so forged frames can't advance the window.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReplayWindow {
+    highest: u32,
+    bitmap: u64,
+}
+
+impl ReplayWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Highest counter accepted so far, `None` before the first packet.
+    pub fn highest(&self) -> Option<u32> {
+        (self.bitmap != 0).then_some(self.highest)
+    }
+
+    /// Reject duplicates and counters that fell off the back of the window.
+    pub fn check(&self, counter: u32) -> Result<(), ValidationError> {
+        let Some(highest) = self.highest() else {
+            return Ok(());
+        };
+        if counter > highest {
+            return Ok(());
+        }
+        let age = highest - counter;
+        if age >= REPLAY_WINDOW_BITS || self.bitmap & (1 << age) != 0 {
+            return Err(ValidationError::ReplayDetected);
+        }
+        Ok(())
+    }
+
+    /// Whether `counter` was accepted and is still inside the window.
+    pub fn contains(&self, counter: u32) -> bool {
+        match self.highest() {
+            Some(highest) if counter <= highest => {
+                let age = highest - counter;
+                age < REPLAY_WINDOW_BITS && self.bitmap & (1 << age) != 0
+            }
+            _ => false,
+        }
+    }
+
+    /// Mark `counter` as seen, sliding the window forward if it is the new highest.
+    pub fn record(&mut self, counter: u32) {
+        let Some(highest) = self.highest() else {
+            self.highest = counter;
+            self.bitmap = 1;
+            return;
+        };
+        if counter > highest {
+            let shift = counter - highest;
+            self.bitmap = if shift >= REPLAY_WINDOW_BITS {
+                0
+            } else {
+                self.bitmap << shift
+            };
+            self.bitmap |= 1;
+            self.highest = counter;
+        } else if highest - counter < REPLAY_WINDOW_BITS {
+            self.bitmap |= 1 << (highest - counter);
+        }
+    }
+
+    /// Persist alongside the session for warm wake.
+    pub fn to_bytes(&self) -> [u8; REPLAY_WINDOW_BYTES] {
+        let mut out = [0u8; REPLAY_WINDOW_BYTES];
+        out[..4].copy_from_slice(&self.highest.to_le_bytes());
+        out[4..].copy_from_slice(&self.bitmap.to_le_bytes());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8; REPLAY_WINDOW_BYTES]) -> Self {
+        Self {
+            highest: u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            bitmap: u64::from_le_bytes(bytes[4..].try_into().unwrap()),
+        }
+    }
+}
+
+/// Control code: receiver saw an authenticated counter jump and challenges the sender.
+pub const CONTROL_RESYNC_REQUEST: u8 = 0x12;
+/// Control code: sender echoes the challenge; the frame's header counter is the new anchor.
+pub const CONTROL_RESYNC_RESPONSE: u8 = 0x13;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResyncMessage {
+    Request { challenge: u32 },
+    Response { challenge: u32 },
+}
+
+impl ResyncMessage {
+    pub fn to_payload(self) -> Payload {
+        ControlMessage::Resync(self).to_payload()
+    }
+
+    /// Returns `None` for anything that is not a well-formed resync Control payload.
+    pub fn from_payload(payload: &Payload) -> Option<Self> {
+        match ControlMessage::from_payload(payload) {
+            Ok(ControlMessage::Resync(msg)) => Some(msg),
+            _ => None,
+        }
+    }
+}
+
+/// What the receiver should do with an authenticated packet that failed with `CounterJump`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResyncAction {
+    /// Packet answered our challenge; the window now starts at its counter.
+    Reanchored,
+    /// Drop the packet and send this challenge to the peer.
+    Challenge(Payload),
+    /// Resync disabled by `CounterPolicy`; drop the packet.
+    Drop,
+}
+
+/// Receiver side of the counter-resync exchange.
+///
+/// The sender answers a `Request` with a `Response` echoing the challenge in its next packet.
+/// Only that response may move the window past `CounterPolicy::max_jump`, and only after the
+/// AEAD authenticated it, so a stale or forged frame can't re-anchor the receiver.
+#[derive(Clone, Debug, Default)]
+pub struct ResyncState {
+    challenge: Option<u32>,
+}
+
+impl ResyncState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a challenge is outstanding.
+    pub fn is_pending(&self) -> bool {
+        self.challenge.is_some()
+    }
+
+    /// Handle an authenticated packet that `validate_packet` rejected with `CounterJump`.
+    /// `fresh_challenge` only has to be unique within the key epoch, since the echo comes back
+    /// authenticated: `Session` passes its next transmit counter, a value from an
+    /// `EntropySource` works too. It is only used when no challenge is outstanding, so a lost
+    /// request is simply repeated.
+    pub fn on_counter_jump(
+        &mut self,
+        packet: &Packet,
+        cfg: &ProtocolConfig,
+        window: &mut ReplayWindow,
+        fresh_challenge: u32,
+    ) -> ResyncAction {
+        if !cfg.counters.resync {
+            return ResyncAction::Drop;
+        }
+
+        if let (Some(expected), Some(ResyncMessage::Response { challenge })) =
+            (self.challenge, ResyncMessage::from_payload(&packet.payload))
+        {
+            if challenge == expected {
+                self.challenge = None;
+                *window = ReplayWindow::new();
+                window.record(packet.header.counter);
+                return ResyncAction::Reanchored;
+            }
+        }
+
+        let challenge = *self.challenge.get_or_insert(fresh_challenge);
+        ResyncAction::Challenge(ResyncMessage::Request { challenge }.to_payload())
+    }
+}
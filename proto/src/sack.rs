@@ -0,0 +1,177 @@
+//! Selective acknowledgement: a cumulative counter plus a bitmap of the counters after it.
+//!
+//! With drops and reordering on air, an Ack naming one counter leaves the keyboard guessing
+//! about every other frame in flight. A `SelectiveAck` says "everything up to `ack_counter`,
+//! plus these of the next `SACK_BITS`", so the keyboard's `RetransmitQueue` can drop what
+//! arrived and resend only what is missing.
+//!
+//! The dongle builds it from its `ReplayWindow`. Counters more than `SACK_BITS` behind the
+//! newest one it accepted count as settled even if they never arrived; otherwise one lost
+//! frame that nobody retransmits (a KeepAlive, a frame that ran out of retries) would pin the
+//! cumulative counter and push newer frames out of the bitmap. The keyboard keeps its side of
+//! that contract: `RetransmitQueue` gives up on a frame once it falls that far behind.
+//!
+//! Wire encoding, inside `Payload::Ack`: `ack_counter u32 LE || sack u32 LE || leds`, where
+//! bit `i` of `sack` is counter `ack_counter + 1 + i`. `sack` is omitted when zero and `leds`
+//! when absent, so an Ack is 4, 5, 8 or 9 bytes.
+
+use crate::{Payload, ReplayWindow, MAX_RETRANSMIT_ATTEMPTS};
+
+/// Counters after `ack_counter` covered by the bitmap.
+pub const SACK_BITS: u32 = 32;
+/// Frames a `RetransmitQueue` holds while waiting for their Ack.
+pub const MAX_IN_FLIGHT: usize = 8;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RetransmitError {
+    /// `MAX_IN_FLIGHT` frames are unacked; wait for an Ack before sending more.
+    QueueFull,
+}
+
+/// Everything up to `ack_counter`, plus the counters set in `sack`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SelectiveAck {
+    pub ack_counter: u32,
+    pub sack: u32,
+}
+
+impl SelectiveAck {
+    /// Dongle side: the Ack for every counter `window` has accepted, `None` before the first.
+    pub fn from_window(window: &ReplayWindow) -> Option<Self> {
+        let highest = window.highest()?;
+        let mut ack_counter = highest.saturating_sub(SACK_BITS);
+        while ack_counter < highest && window.contains(ack_counter + 1) {
+            ack_counter += 1;
+        }
+        let sack = (0..SACK_BITS)
+            .filter(|&i| {
+                let counter = ack_counter + 1 + i;
+                counter <= highest && window.contains(counter)
+            })
+            .fold(0, |bits, i| bits | 1 << i);
+        Some(Self { ack_counter, sack })
+    }
+
+    /// Keyboard side: the selective view of any Ack payload.
+    pub fn from_payload(payload: &Payload) -> Option<Self> {
+        match *payload {
+            Payload::Ack {
+                ack_counter, sack, ..
+            } => Some(Self { ack_counter, sack }),
+            _ => None,
+        }
+    }
+
+    pub fn to_payload(self, indicators: Option<u8>) -> Payload {
+        Payload::Ack {
+            ack_counter: self.ack_counter,
+            sack: self.sack,
+            indicators,
+        }
+    }
+
+    pub fn acks(&self, counter: u32) -> bool {
+        if counter <= self.ack_counter {
+            return true;
+        }
+        let offset = counter - self.ack_counter - 1;
+        offset < SACK_BITS && self.sack & (1 << offset) != 0
+    }
+
+    /// Newest counter this Ack covers.
+    pub fn highest(&self) -> u32 {
+        self.ack_counter + (SACK_BITS - self.sack.leading_zeros())
+    }
+}
+
+struct InFlight<F> {
+    counter: u32,
+    frame: F,
+    retries: u8,
+}
+
+/// Keyboard side: frames sent with `needs_ack`, held until a `SelectiveAck` covers them.
+///
+/// `F` is whatever the radio driver resends (a `Vec<u8>` from `Session::send`, a fixed buffer
+/// in firmware). Each frame is resent at most `MAX_RETRANSMIT_ATTEMPTS` times, then counted
+/// as lost.
+pub struct RetransmitQueue<F> {
+    pending: heapless::Vec<InFlight<F>, MAX_IN_FLIGHT>,
+    lost: u32,
+}
+
+impl<F> Default for RetransmitQueue<F> {
+    fn default() -> Self {
+        Self {
+            pending: heapless::Vec::new(),
+            lost: 0,
+        }
+    }
+}
+
+impl<F> RetransmitQueue<F> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track a frame just sent with `counter`. Frames `SACK_BITS` or more counters older are
+    /// given up on, since the dongle already treats them as settled.
+    pub fn push(&mut self, counter: u32, frame: F) -> Result<(), RetransmitError> {
+        self.give_up(|f| counter.saturating_sub(f.counter) >= SACK_BITS);
+        self.pending
+            .push(InFlight {
+                counter,
+                frame,
+                retries: 0,
+            })
+            .map_err(|_| RetransmitError::QueueFull)
+    }
+
+    /// Forget every frame `ack` covers; returns how many were acked.
+    pub fn acknowledge(&mut self, ack: &SelectiveAck) -> usize {
+        let before = self.pending.len();
+        self.pending.retain(|f| !ack.acks(f.counter));
+        before - self.pending.len()
+    }
+
+    /// After `acknowledge`: frames older than the newest one `ack` covers. A later frame got
+    /// through, so these were lost rather than still on air.
+    pub fn missing<'a>(&'a mut self, ack: &SelectiveAck) -> impl Iterator<Item = &'a F> {
+        let highest = ack.highest();
+        self.resend(move |f| f.counter < highest)
+    }
+
+    /// No Ack arrived in time: every pending frame.
+    pub fn timed_out(&mut self) -> impl Iterator<Item = &F> {
+        self.resend(|_| true)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Counters still waiting for an Ack, oldest first.
+    pub fn pending(&self) -> impl Iterator<Item = u32> + '_ {
+        self.pending.iter().map(|f| f.counter)
+    }
+
+    /// Frames dropped after running out of retries.
+    pub fn lost(&self) -> u32 {
+        self.lost
+    }
+
+    /// Frames matching `due` that still have a retry left; exhausted ones are dropped.
+    fn resend(&mut self, due: impl Fn(&InFlight<F>) -> bool) -> impl Iterator<Item = &F> {
+        self.give_up(|f| due(f) && f.retries >= MAX_RETRANSMIT_ATTEMPTS);
+        self.pending.iter_mut().filter(move |f| due(f)).map(|f| {
+            f.retries += 1;
+            &f.frame
+        })
+    }
+
+    fn give_up(&mut self, expired: impl Fn(&InFlight<F>) -> bool) {
+        let before = self.pending.len();
+        self.pending.retain(|f| !expired(f));
+        self.lost += (before - self.pending.len()) as u32;
+    }
+}
@@ -0,0 +1,303 @@
+//! High-level session: one `send`/`receive` pair over an established link.
+//!
+//! `Session` owns the transmit counter, nonce derivation, AEAD and the peer's replay window, so
+//! callers never build headers or touch the MAC field. It also answers the counter-resync
+//! exchange on its own, and, once `enable_rekey` is called, the in-band rekey (`RekeyState`):
+//! each switch moves both the salt/epoch and the AEAD to the ratcheted key. Frames it wants on
+//! air are handed out by `poll_transmit`.
+
+use crate::privacy::padding_enabled;
+use crate::{
+    decode_header, fragments, open_framed, pad_payload, padded_payload, seal_framed,
+    validate_packet, Aead, CryptoError, Packet, PacketFlags, PacketHeader, ParseError, Payload,
+    ProtocolConfig, RekeyError, RekeyMessage, RekeyState, ResyncAction, ResyncMessage, ResyncState,
+    SelectiveAck, SerializationError, SessionError, SessionKeys, ValidationError, Vec, HEADER_LEN,
+    KEY_BYTES,
+};
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::collections::VecDeque;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::vec;
+#[cfg(feature = "std")]
+use std::collections::VecDeque;
+
+pub struct Session<A: Aead> {
+    cfg: ProtocolConfig,
+    keys: SessionKeys,
+    aead: A,
+    resync: ResyncState,
+    rekey: Option<RekeyState>,
+    // Clock for the rekey grace window, from `set_time`.
+    now_ms: u64,
+    // Frames generated by the session itself, oldest first, until `poll_transmit` takes them.
+    outbound: VecDeque<Vec<u8>>,
+}
+
+impl<A: Aead> Session<A> {
+    /// Wrap keys from a handshake (or a warm-wake restore) and the AEAD keyed for them.
+    pub fn new(cfg: ProtocolConfig, keys: SessionKeys, aead: A) -> Self {
+        Self {
+            cfg,
+            keys,
+            aead,
+            resync: ResyncState::new(),
+            rekey: None,
+            now_ms: 0,
+            outbound: VecDeque::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ProtocolConfig {
+        &self.cfg
+    }
+
+    /// Counters, salt and replay window; persist these for warm wake.
+    pub fn keys(&self) -> &SessionKeys {
+        &self.keys
+    }
+
+    /// For warm-wake restore (`resume_from`, `restore_replay_window`). Rekey through
+    /// `begin_rekey`, which also moves the AEAD to the new key.
+    pub fn keys_mut(&mut self) -> &mut SessionKeys {
+        &mut self.keys
+    }
+
+    /// Take part in in-band rekeying; `key` is the key the session's AEAD was built with (the
+    /// handshake's `EstablishedSession::key`). Both peers must enable it.
+    pub fn enable_rekey(&mut self, key: [u8; KEY_BYTES]) -> Result<(), RekeyError> {
+        if self.aead.with_key(&key).is_none() {
+            return Err(RekeyError::AeadNotRekeyable);
+        }
+        self.rekey = Some(RekeyState::new(&self.cfg, key, &self.keys));
+        Ok(())
+    }
+
+    /// Epochs of an enabled rekey.
+    pub fn rekey_state(&self) -> Option<&RekeyState> {
+        self.rekey.as_ref()
+    }
+
+    /// Current time, for the grace window in which the retired epoch is still opened. Call it
+    /// before `receive` when rekeying is enabled.
+    pub fn set_time(&mut self, now_ms: u64) {
+        self.now_ms = now_ms;
+    }
+
+    /// Propose the next epoch, e.g. once `keys().needs_rekey()`; returns the `RekeyRequest`
+    /// frame, sealed under the current keys. The switch happens in `receive` when the peer's
+    /// confirm (or any frame under the new keys) arrives, and the confirm back is queued for
+    /// `poll_transmit`.
+    pub fn begin_rekey(&mut self) -> Result<Vec<u8>, SessionError> {
+        let request = self.rekey.as_mut().ok_or(RekeyError::NotEnabled)?.begin()?;
+        self.send(request)
+    }
+
+    /// Seal `payload` under the next counter. Key reports and Control ask for an Ack. With
+    /// `CAP_PADDING` negotiated, data payloads and KeepAlive go out as `Padded` payloads.
+    ///
+    /// Retransmit by sending the returned frame again; it carries the same counter and nonce.
+    pub fn send(&mut self, payload: Payload) -> Result<Vec<u8>, SessionError> {
+        self.seal(payload, false, 0)
+    }
+
+    /// Send Control `code` / `data` of up to `MAX_CONTROL_MESSAGE_BYTES`: one frame when it
+    /// fits, otherwise one fragment per frame under consecutive counters (see `fragments`).
+    ///
+    /// Each frame is acked and retransmitted on its own; the peer rebuilds the message with a
+    /// `Reassembler`. Too large a message is `PayloadTooLarge`, as from `send`.
+    pub fn send_fragmented(&mut self, code: u8, data: &[u8]) -> Result<Vec<Vec<u8>>, SessionError> {
+        let fragments = fragments(&self.cfg, code, data)
+            .map_err(|_| CryptoError::Serialize(SerializationError::PayloadTooLarge))?;
+        fragments
+            .map(|f| self.seal(f.payload, f.more_fragments, f.fragment_index))
+            .collect()
+    }
+
+    fn seal(
+        &mut self,
+        payload: Payload,
+        more_fragments: bool,
+        fragment_index: u8,
+    ) -> Result<Vec<u8>, SessionError> {
+        let payload = if padding_enabled(&self.cfg) && payload.kind().paddable() {
+            pad_payload(&self.cfg, &payload)
+                .map_err(|_| CryptoError::Serialize(SerializationError::PayloadTooLarge))?
+        } else {
+            payload
+        };
+        let kind = payload.kind();
+        let counter = self.keys.next_counter()?;
+        let packet = Packet {
+            header: PacketHeader {
+                session_id: self.keys.session_id,
+                counter,
+                kind,
+                flags: PacketFlags {
+                    encrypted: true,
+                    needs_ack: kind.needs_ack(),
+                    retransmit: false,
+                    more_fragments,
+                    fragment_index,
+                },
+            },
+            payload,
+            // Placeholder only: the AEAD writes the real tag.
+            mac: vec![0; self.cfg.security.mac_len],
+        };
+        Ok(seal_framed(
+            &packet,
+            &self.cfg,
+            &self.aead,
+            &self.keys.nonce_for(counter),
+        )?)
+    }
+
+    /// Open and validate a frame from the peer and return its payload.
+    pub fn receive(&mut self, frame: &[u8]) -> Result<Payload, SessionError> {
+        self.receive_with_header(frame).map(|(_, payload)| payload)
+    }
+
+    /// Like `receive`, but also returns the header (counter to Ack, `needs_ack` flag).
+    ///
+    /// Replay checks run after authentication, so `ReplayDetected` always means a genuine
+    /// duplicate (safe to re-Ack), and only authenticated counters are recorded. A `CounterJump`
+    /// starts a resync (see `ResyncState`): the challenge is queued for `poll_transmit` and the
+    /// frame is still rejected.
+    ///
+    /// A `Padded` frame is unwrapped right after authentication, so validation, resync and the
+    /// caller all see the payload it carries, with `header.kind` set to that payload's kind.
+    pub fn receive_with_header(
+        &mut self,
+        frame: &[u8],
+    ) -> Result<(PacketHeader, Payload), SessionError> {
+        let header_bytes = frame
+            .get(..HEADER_LEN)
+            .ok_or(ParseError::UnexpectedLength)?;
+        let header = decode_header(header_bytes)?;
+        if header.session_id != self.keys.session_id {
+            return Err(ValidationError::SessionMismatch.into());
+        }
+        let (mut packet, epoch) = self.open(frame, header.counter)?;
+        // A frame under the epoch we proposed switches us over before it is validated.
+        let confirm = self.on_rekey_event(|rekey, keys, now_ms| {
+            Ok(rekey.on_authenticated(epoch, keys, now_ms))
+        })?;
+        if let Payload::Padded { record } = &packet.payload {
+            let inner = padded_payload(record)?.to_owned();
+            packet.header.kind = inner.kind();
+            packet.payload = inner;
+        }
+        let window = self
+            .keys
+            .replay_window_for(epoch)
+            .ok_or(ValidationError::ReplayDetected)?;
+        match validate_packet(&packet, &self.cfg, Some(self.keys.session_id), Some(window)) {
+            Ok(()) => self.keys.record_rx_in(epoch, packet.header.counter),
+            Err(ValidationError::CounterJump) if epoch == self.keys.epoch() => {
+                self.on_counter_jump(&packet)?
+            }
+            Err(e) => return Err(e.into()),
+        }
+        if let Some(confirm) = confirm {
+            let confirm = self.send(confirm)?;
+            self.outbound.push_back(confirm);
+        }
+
+        let (header, payload) = (packet.header, packet.payload);
+        if let Some(msg) = RekeyMessage::from_payload(&payload) {
+            let reply = self
+                .on_rekey_event(|rekey, keys, now_ms| rekey.on_message(msg, epoch, keys, now_ms))?;
+            if let Some(reply) = reply {
+                let reply = self.send(reply)?;
+                self.outbound.push_back(reply);
+            }
+        }
+        if let Some(ResyncMessage::Request { challenge }) = ResyncMessage::from_payload(&payload) {
+            let response = self.send(ResyncMessage::Response { challenge }.to_payload())?;
+            self.outbound.push_back(response);
+        }
+        Ok((header, payload))
+    }
+
+    /// Ack covering every frame accepted so far (`None` before the first); send it with
+    /// `SelectiveAck::to_payload` after `receive` returned a frame that `needs_ack`, or a
+    /// `ReplayDetected` duplicate.
+    pub fn selective_ack(&self) -> Option<SelectiveAck> {
+        SelectiveAck::from_window(self.keys.replay_window())
+    }
+
+    /// Next frame the session generated on its own (resync request/response), if any. Frames
+    /// queue up in order, so a `receive` that produced one never displaces an earlier one; call
+    /// until `None`.
+    pub fn poll_transmit(&mut self) -> Option<Vec<u8>> {
+        self.outbound.pop_front()
+    }
+
+    /// Open `frame` under the current keys or, with rekeying enabled, the proposed or retired
+    /// epoch; returns the packet and the epoch it opened under.
+    fn open(&self, frame: &[u8], counter: u32) -> Result<(Packet, u16), SessionError> {
+        let nonce = self.keys.rx_nonce_for(counter);
+        let err = match open_framed(frame, &self.cfg, &self.aead, &nonce) {
+            Ok(packet) => return Ok((packet, self.keys.epoch())),
+            Err(err) => err,
+        };
+        let rx = self.keys.role().rx_direction();
+        let others = self.rekey.iter().flat_map(|rekey| {
+            rekey
+                .rx_candidates(self.now_ms)
+                .filter(|keys| keys.epoch != self.keys.epoch())
+        });
+        for keys in others {
+            let aead = self
+                .aead
+                .with_key(&keys.key)
+                .expect("checked by enable_rekey");
+            if let Ok(packet) = open_framed(frame, &self.cfg, &aead, &keys.nonce(rx, counter)) {
+                return Ok((packet, keys.epoch));
+            }
+        }
+        Err(err.into())
+    }
+
+    /// Run `event` against the rekey state, if enabled, and move the AEAD to the new key when
+    /// it switched epochs.
+    fn on_rekey_event(
+        &mut self,
+        event: impl FnOnce(
+            &mut RekeyState,
+            &mut SessionKeys,
+            u64,
+        ) -> Result<Option<Payload>, RekeyError>,
+    ) -> Result<Option<Payload>, SessionError> {
+        let Some(rekey) = self.rekey.as_mut() else {
+            return Ok(None);
+        };
+        let epoch = self.keys.epoch();
+        let reply = event(rekey, &mut self.keys, self.now_ms)?;
+        if self.keys.epoch() != epoch {
+            self.aead = self
+                .aead
+                .with_key(&rekey.current().key)
+                .expect("checked by enable_rekey");
+        }
+        Ok(reply)
+    }
+
+    fn on_counter_jump(&mut self, packet: &Packet) -> Result<(), SessionError> {
+        let window = &mut self.keys.rx_window;
+        // Our own next counter never repeats within the epoch, which is all a challenge needs.
+        let challenge = self.keys.counter;
+        match self
+            .resync
+            .on_counter_jump(packet, &self.cfg, window, challenge)
+        {
+            ResyncAction::Reanchored => Ok(()),
+            ResyncAction::Challenge(request) => {
+                let request = self.send(request)?;
+                self.outbound.push_back(request);
+                Err(ValidationError::CounterJump.into())
+            }
+            ResyncAction::Drop => Err(ValidationError::CounterJump.into()),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use proto::{
+    batch_records, decode_payload, encode_payload, BatchError, Batcher, DummyAead, HidReport,
+    KeyReport, PacketKind, ParseError, Payload, PayloadRef, Role, Session, SessionKeys,
+    BATCH_RECORD_HEADER_BYTES, CONSUMER_VOLUME_UP, HEADER_LEN, SESSION_SALT_BYTES,
+};
+
+fn key_a() -> Payload {
+    KeyReport::boot(0, &[0x04])
+        .expect("boot report")
+        .to_payload()
+}
+
+fn knob(detents: i8) -> Payload {
+    Payload::Encoder {
+        encoder: 0,
+        detents,
+    }
+}
+
+#[test]
+fn key_and_encoder_share_one_frame() {
+    let cfg = proto::demo_config();
+    let mut batcher = Batcher::new(&cfg, Duration::from_millis(2));
+    assert_eq!(batcher.push(&key_a(), 100), Ok(None));
+    assert_eq!(batcher.push(&knob(-1), 101), Ok(None));
+    let batch = batcher.flush().expect("held reports");
+    assert!(batcher.is_empty());
+
+    let key_bytes = encode_payload(&key_a());
+    let mut wire = vec![PacketKind::KeyReport as u8, key_bytes.len() as u8];
+    wire.extend_from_slice(&key_bytes);
+    wire.extend_from_slice(&[PacketKind::Encoder as u8, 2, 0, 0xFF]);
+    assert_eq!(encode_payload(&batch), wire);
+    assert_eq!(decode_payload(PacketKind::Batch, &wire), Ok(batch.clone()));
+
+    let salt = [0xBA; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(cfg, SessionKeys::new(0xBA7C, salt), DummyAead);
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0xBA7C, salt, Role::Dongle),
+        DummyAead,
+    );
+    let frame = keyboard.send(batch).expect("send");
+    let separate =
+        keyboard.send(key_a()).expect("send").len() + keyboard.send(knob(-1)).expect("send").len();
+    // One frame's header, length and MAC saved; two record headers spent.
+    let overhead = HEADER_LEN + 2 + cfg.security.mac_len;
+    assert_eq!(
+        frame.len(),
+        separate - overhead + 2 * BATCH_RECORD_HEADER_BYTES
+    );
+
+    let (header, payload) = dongle.receive_with_header(&frame).expect("delivered");
+    assert!(header.flags.needs_ack);
+    let Payload::Batch { records } = payload else {
+        panic!("expected a batch, got {payload:?}");
+    };
+    let records: Vec<PayloadRef> = batch_records(&records)
+        .collect::<Result<_, _>>()
+        .expect("well-formed");
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].to_owned(), key_a());
+    assert_eq!(records[1].to_owned(), knob(-1));
+    assert!(HidReport::from_payload(&records[0].to_owned()).is_ok());
+}
+
+#[test]
+fn delay_is_bounded_by_the_latency_target() {
+    let cfg = proto::demo_config();
+    let target_ms = cfg.latency.target.as_millis() as u64;
+    let mut batcher = Batcher::new(&cfg, Duration::from_millis(50));
+    assert_eq!(batcher.deadline_ms(), None);
+
+    assert_eq!(batcher.push(&key_a(), 10), Ok(None));
+    assert_eq!(batcher.deadline_ms(), Some(10 + target_ms));
+    assert_eq!(batcher.poll(10 + target_ms - 1), None);
+    // A report still alone goes out as itself, without record headers.
+    assert_eq!(batcher.poll(10 + target_ms), Some(key_a()));
+    assert_eq!(batcher.poll(100), None);
+
+    let mut eager = Batcher::new(&cfg, Duration::ZERO);
+    assert_eq!(eager.push(&knob(1), 7), Ok(None));
+    assert_eq!(eager.poll(7), Some(knob(1)));
+}
+
+#[test]
+fn full_frame_goes_out_without_waiting() {
+    let cfg = proto::demo_config();
+    let mut batcher = Batcher::new(&cfg, cfg.latency.target);
+    let volume = Payload::ConsumerControl {
+        usage: CONSUMER_VOLUME_UP,
+    };
+
+    // Eight 4-byte records fill the 32-byte frame exactly.
+    for i in 0..7 {
+        assert_eq!(batcher.push(&volume, i), Ok(None));
+    }
+    let Ok(Some(Payload::Batch { records })) = batcher.push(&volume, 7) else {
+        panic!("a full frame is sent at once");
+    };
+    assert_eq!(batch_records(&records).count(), 8);
+    assert!(batcher.is_empty());
+
+    // A report that does not fit next to the held ones starts the next batch.
+    for detents in 1..=6 {
+        assert_eq!(batcher.push(&knob(detents), 20), Ok(None));
+    }
+    let control = Payload::Control {
+        code: 0x20,
+        data: vec![0; 8],
+    };
+    let Ok(Some(Payload::Batch { records })) = batcher.push(&control, 21) else {
+        panic!("held reports are sent first");
+    };
+    assert_eq!(batch_records(&records).count(), 6);
+    assert_eq!(batcher.deadline_ms(), Some(21 + 6));
+    assert_eq!(batcher.flush(), Some(control));
+
+    assert_eq!(
+        batcher.push(&Payload::KeepAlive, 30),
+        Err(BatchError::NotBatchable)
+    );
+    let big = Payload::Control {
+        code: 0x20,
+        data: vec![0; 30],
+    };
+    assert_eq!(batcher.push(&big, 30), Err(BatchError::TooLarge));
+}
+
+#[test]
+fn malformed_batches_are_rejected() {
+    assert_eq!(
+        decode_payload(PacketKind::Batch, &[]),
+        Err(ParseError::UnexpectedLength)
+    );
+    // Record claims more bytes than remain.
+    assert_eq!(
+        decode_payload(PacketKind::Batch, &[PacketKind::Encoder as u8, 3, 0, 1]),
+        Err(ParseError::UnexpectedLength)
+    );
+    // Record payload fails its own kind's checks.
+    assert_eq!(
+        decode_payload(PacketKind::Batch, &[PacketKind::Encoder as u8, 1, 0]),
+        Err(ParseError::UnexpectedLength)
+    );
+    for kind in [PacketKind::Ack, PacketKind::Batch, PacketKind::Handshake] {
+        assert_eq!(
+            decode_payload(PacketKind::Batch, &[kind as u8, 0]),
+            Err(ParseError::NotBatchable(kind as u8))
+        );
+    }
+    assert_eq!(
+        decode_payload(PacketKind::Batch, &[0x7F, 0]),
+        Err(ParseError::UnknownKind(0x7F))
+    );
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use proto::{
+    millivolts_to_percent, BatteryConfig, BatteryMonitor, ControlMessage, CurvePoint, DummyAead,
+    HidReport, Role, Session, SessionKeys, HID_REPORT_ID_BATTERY, LIPO_DISCHARGE_CURVE,
+    SESSION_SALT_BYTES,
+};
+
+#[test]
+fn lipo_curve_interpolates_and_clamps() {
+    let curve = LIPO_DISCHARGE_CURVE;
+    assert_eq!(millivolts_to_percent(curve, 4300), 100);
+    assert_eq!(millivolts_to_percent(curve, 4200), 100);
+    assert_eq!(millivolts_to_percent(curve, 3910), 50);
+    // Halfway between 3910 mV (50 %) and 3950 mV (60 %).
+    assert_eq!(millivolts_to_percent(curve, 3930), 55);
+    // 3700 mV (5 %) .. 3790 mV (10 %): 30 mV above the lower point rounds to 7 %.
+    assert_eq!(millivolts_to_percent(curve, 3730), 7);
+    assert_eq!(millivolts_to_percent(curve, 3500), 0);
+    assert_eq!(millivolts_to_percent(curve, 3000), 0);
+    assert_eq!(millivolts_to_percent(&[], 3800), 0);
+}
+
+#[test]
+fn curve_is_monotonic_over_the_cell_range() {
+    let mut last = 0;
+    for mv in 3400..=4250 {
+        let percent = millivolts_to_percent(LIPO_DISCHARGE_CURVE, mv);
+        assert!(percent >= last, "{mv} mV dropped to {percent} %");
+        last = percent;
+    }
+}
+
+#[test]
+fn custom_curve_is_used() {
+    static LINEAR: &[CurvePoint] = &[
+        CurvePoint {
+            millivolts: 3000,
+            percent: 100,
+        },
+        CurvePoint {
+            millivolts: 2000,
+            percent: 0,
+        },
+    ];
+    assert_eq!(millivolts_to_percent(LINEAR, 2250), 25);
+}
+
+#[test]
+fn hysteresis_ignores_sag_under_load() {
+    let mut monitor = BatteryMonitor::new(BatteryConfig::lipo());
+    assert_eq!(monitor.percent(), None);
+    assert_eq!(monitor.sample(3910), 50);
+    // Radio burst sags the cell into the 49 % range: still reported as 50 %.
+    assert_eq!(monitor.sample(3906), 50);
+    assert_eq!(monitor.sample(3914), 50);
+    // A real drop of two points replaces it.
+    assert_eq!(monitor.sample(3902), 48);
+    assert_eq!(monitor.percent(), Some(48));
+}
+
+#[test]
+fn reports_on_change_and_periodically() {
+    let cfg = BatteryConfig {
+        report_interval: Duration::from_secs(10),
+        ..BatteryConfig::lipo()
+    };
+    let mut monitor = BatteryMonitor::new(cfg);
+    assert_eq!(monitor.poll(0), None, "nothing sampled yet");
+
+    monitor.sample(4020);
+    let first = monitor.poll(0).expect("first level goes out at once");
+    assert_eq!(
+        ControlMessage::from_payload(&first),
+        Ok(ControlMessage::BatteryLevel {
+            percent: 80,
+            millivolts: 4020
+        })
+    );
+    assert_eq!(monitor.poll(5_000), None);
+
+    monitor.sample(4019);
+    assert_eq!(
+        monitor.poll(6_000),
+        None,
+        "within hysteresis, no early report"
+    );
+    assert!(monitor.poll(10_000).is_some(), "interval elapsed");
+
+    monitor.sample(3980);
+    assert!(monitor.poll(10_001).is_some(), "level changed");
+    assert_eq!(monitor.poll(10_002), None);
+}
+
+#[test]
+fn dongle_exposes_battery_strength_report() {
+    let cfg = proto::demo_config();
+    let salt = [0xBA; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(cfg, SessionKeys::new(0x42_41_54_54, salt), DummyAead);
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0x42_41_54_54, salt, Role::Dongle),
+        DummyAead,
+    );
+
+    let mut monitor = BatteryMonitor::new(BatteryConfig::lipo());
+    monitor.sample(3950);
+    let frame = keyboard.send(monitor.poll(0).expect("due")).expect("send");
+    let payload = dongle.receive(&frame).expect("receive");
+
+    let report = HidReport::from_payload(&payload).expect("battery report");
+    assert_eq!(report.as_bytes(), &[HID_REPORT_ID_BATTERY, 60]);
+}
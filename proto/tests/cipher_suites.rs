@@ -0,0 +1,187 @@
+#![cfg(feature = "crypto")]
+
+use proto::{
+    derive_directional_nonce, open_framed, Aead, AesCcmAead, ChaCha20Aead, CipherSuite,
+    CryptoError, Direction, Payload, Role, Session, SessionKeys, SuiteAead, KEY_BYTES, MAC_LENGTHS,
+    NONCE_BYTES, SESSION_SALT_BYTES,
+};
+
+const SUITES: [CipherSuite; 3] = [
+    CipherSuite::XChaCha20Poly1305,
+    CipherSuite::ChaCha20Poly1305,
+    CipherSuite::Aes128Ccm,
+];
+
+const KEY: [u8; KEY_BYTES] = [0x5C; KEY_BYTES];
+const SALT: [u8; SESSION_SALT_BYTES] = [0xC1; SESSION_SALT_BYTES];
+
+fn hex(s: &str) -> Vec<u8> {
+    let s: String = s.split_whitespace().collect();
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).expect("hex"))
+        .collect()
+}
+
+/// Protocol nonce whose suite nonce is `front || tail`, `tail` being the last seven bytes.
+fn protocol_nonce(front: &[u8], tail: &[u8]) -> [u8; NONCE_BYTES] {
+    let mut nonce = [0xEE; NONCE_BYTES];
+    nonce[..front.len()].copy_from_slice(front);
+    nonce[SESSION_SALT_BYTES..SESSION_SALT_BYTES + 7].copy_from_slice(tail);
+    nonce
+}
+
+fn seal(aead: &dyn Aead, nonce: &[u8], aad: &[u8], plaintext: &[u8], mac_len: usize) -> Vec<u8> {
+    let (mut out, tag) = aead.seal(nonce, aad, plaintext, mac_len).expect("seal");
+    out.extend_from_slice(&tag);
+    out
+}
+
+#[test]
+fn aes_ccm_matches_rfc3610() {
+    // RFC 3610 packet vector #1: M = 8, L = 2.
+    let mut key = [0u8; 16];
+    for (i, b) in key.iter_mut().enumerate() {
+        *b = 0xC0 + i as u8;
+    }
+    let ccm_nonce = hex("00000003 020100A0 A1A2A3A4 A5");
+    let nonce = protocol_nonce(&ccm_nonce[..6], &ccm_nonce[6..]);
+    assert_eq!(
+        CipherSuite::Aes128Ccm.cipher_nonce(&nonce)[..13],
+        ccm_nonce[..]
+    );
+    let aad: Vec<u8> = (0x00..0x08).collect();
+    let plaintext: Vec<u8> = (0x08..0x1F).collect();
+
+    let aead = AesCcmAead::from_aes_key(key);
+    let sealed = seal(&aead, &nonce, &aad, &plaintext, 8);
+    assert_eq!(
+        sealed,
+        hex("588C979A 61C663D2 F066D0C2 C0F98980 6D5F6B61 DAC384 17E8D12C FDF926E0")
+    );
+    let (ciphertext, tag) = sealed.split_at(plaintext.len());
+    assert_eq!(aead.open(&nonce, &aad, ciphertext, tag), Ok(plaintext));
+
+    // The tag length is part of the CCM MAC: a longer tag does not extend the shorter one.
+    let full = seal(&aead, &nonce, &aad, &(0x08..0x1F).collect::<Vec<u8>>(), 16);
+    assert_ne!(full[23..31], sealed[23..]);
+}
+
+#[test]
+fn aes_ccm_key_is_derived_from_the_session_key() {
+    let mut derived = [0u8; 16];
+    hkdf::Hkdf::<sha2::Sha256>::new(None, &KEY)
+        .expand(b"keyboard-project aes-128-ccm", &mut derived)
+        .unwrap();
+    let mut truncated = [0u8; 16];
+    truncated.copy_from_slice(&KEY[..16]);
+
+    let nonce = [0x11; NONCE_BYTES];
+    let sealed = seal(&AesCcmAead::new(KEY), &nonce, b"aad", b"report", 16);
+    assert_eq!(
+        sealed,
+        seal(
+            &AesCcmAead::from_aes_key(derived),
+            &nonce,
+            b"aad",
+            b"report",
+            16
+        )
+    );
+    assert_ne!(
+        sealed,
+        seal(
+            &AesCcmAead::from_aes_key(truncated),
+            &nonce,
+            b"aad",
+            b"report",
+            16
+        )
+    );
+}
+
+#[test]
+fn chacha20_poly1305_matches_rfc8439() {
+    // RFC 8439 section 2.8.2.
+    let mut key = [0u8; KEY_BYTES];
+    for (i, b) in key.iter_mut().enumerate() {
+        *b = 0x80 + i as u8;
+    }
+    let ietf_nonce = hex("07000000 40414243 44454647");
+    let nonce = protocol_nonce(&ietf_nonce[..5], &ietf_nonce[5..]);
+    assert_eq!(
+        CipherSuite::ChaCha20Poly1305.cipher_nonce(&nonce)[..12],
+        ietf_nonce[..]
+    );
+    let aad = hex("50515253 C0C1C2C3 C4C5C6C7");
+    let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one \
+                      tip for the future, sunscreen would be it.";
+
+    let aead = ChaCha20Aead::new(key);
+    let sealed = seal(&aead, &nonce, &aad, plaintext, 16);
+    assert_eq!(sealed[..8], hex("D31A8D34 648E60DB")[..]);
+    assert_eq!(
+        sealed[plaintext.len()..],
+        hex("1AE10B59 4F09E26A 7E902ECB D0600691")[..]
+    );
+    let truncated = seal(&aead, &nonce, &aad, plaintext, 8);
+    assert_eq!(truncated[..], sealed[..plaintext.len() + 8]);
+}
+
+#[test]
+fn short_nonces_keep_counter_direction_and_epoch() {
+    let nonce = derive_directional_nonce(&SALT, Direction::DongleToKeyboard, 3, 0x0102_0304);
+    assert_eq!(CipherSuite::XChaCha20Poly1305.cipher_nonce(&nonce), nonce);
+    for suite in [CipherSuite::ChaCha20Poly1305, CipherSuite::Aes128Ccm] {
+        let len = suite.nonce_len();
+        let short = suite.cipher_nonce(&nonce);
+        assert_eq!(short[..len - 7], SALT[..len - 7]);
+        assert_eq!(short[len - 7..len], [0x04, 0x03, 0x02, 0x01, 1, 3, 0]);
+        assert!(short[len..].iter().all(|&b| b == 0));
+    }
+}
+
+#[test]
+fn every_suite_carries_a_session() {
+    let volume_up = Payload::ConsumerControl {
+        usage: proto::CONSUMER_VOLUME_UP,
+    };
+    let mut frames = Vec::new();
+    for suite in SUITES {
+        for mac_len in MAC_LENGTHS {
+            let mut cfg = proto::demo_config();
+            cfg.security.cipher_suite = suite;
+            cfg.security.mac_len = mac_len;
+            let mut keyboard = Session::new(
+                cfg,
+                SessionKeys::new(0x5C1, SALT),
+                SuiteAead::new(suite, KEY),
+            );
+            let boxed: Box<dyn Aead> = Box::new(SuiteAead::new(suite, KEY));
+            let mut dongle = Session::new(
+                cfg,
+                SessionKeys::for_role(0x5C1, SALT, Role::Dongle),
+                boxed.as_ref(),
+            );
+
+            let frame = keyboard.send(volume_up.clone()).expect("send");
+            assert_eq!(dongle.receive(&frame), Ok(volume_up.clone()));
+
+            let mut forged = frame.clone();
+            *forged.last_mut().unwrap() ^= 0x80;
+            let nonce = keyboard.keys().nonce_for(1);
+            assert!(matches!(
+                open_framed(&forged, &cfg, &SuiteAead::new(suite, KEY), &nonce),
+                Err(CryptoError::AuthFailed { .. })
+            ));
+            frames.push((suite, cfg, frame, nonce));
+        }
+    }
+
+    // The same frame opened under another suite fails authentication.
+    for (suite, cfg, frame, nonce) in &frames {
+        for other in SUITES.into_iter().filter(|other| other != suite) {
+            assert!(open_framed(frame, cfg, &SuiteAead::new(other, KEY), nonce).is_err());
+        }
+    }
+}
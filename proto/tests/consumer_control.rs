@@ -0,0 +1,113 @@
+use proto::{
+    decode_payload, encode_payload, DummyAead, HidReport, HidReportError, KeyReport,
+    KeyReportError, PacketKind, ParseError, Payload, Role, Session, SessionKeys,
+    CONSUMER_PLAY_PAUSE, CONSUMER_VOLUME_UP, HID_REPORT_ID_CONSUMER, HID_REPORT_ID_SYSTEM,
+    MODIFIER_LEFT_SHIFT, SESSION_SALT_BYTES, SYSTEM_SLEEP,
+};
+
+#[test]
+fn consumer_and_system_payloads_round_trip() {
+    let volume = Payload::ConsumerControl {
+        usage: CONSUMER_VOLUME_UP,
+    };
+    assert_eq!(volume.kind(), PacketKind::ConsumerControl);
+    assert_eq!(encode_payload(&volume), vec![0xE9, 0x00]);
+    assert_eq!(
+        decode_payload(PacketKind::ConsumerControl, &[0xE9, 0x00]),
+        Ok(volume)
+    );
+
+    let sleep = Payload::SystemControl {
+        usage: SYSTEM_SLEEP,
+    };
+    assert_eq!(sleep.kind(), PacketKind::SystemControl);
+    assert_eq!(encode_payload(&sleep), vec![0x82]);
+    assert_eq!(
+        decode_payload(PacketKind::SystemControl, &[0x82]),
+        Ok(sleep)
+    );
+
+    assert!(PacketKind::ConsumerControl.needs_ack());
+    assert!(PacketKind::SystemControl.needs_ack());
+}
+
+#[test]
+fn decode_rejects_wrong_lengths() {
+    assert_eq!(
+        decode_payload(PacketKind::ConsumerControl, &[0xCD]),
+        Err(ParseError::UnexpectedLength)
+    );
+    assert_eq!(
+        decode_payload(PacketKind::SystemControl, &[]),
+        Err(ParseError::UnexpectedLength)
+    );
+    assert_eq!(
+        decode_payload(PacketKind::SystemControl, &[0x82, 0x00]),
+        Err(ParseError::UnexpectedLength)
+    );
+}
+
+#[test]
+fn hid_report_maps_each_payload_to_its_own_report() {
+    let keys = KeyReport::boot(MODIFIER_LEFT_SHIFT, &[0x04]).expect("shift+a");
+    assert_eq!(
+        HidReport::from_payload(&keys.to_payload()),
+        Ok(HidReport::Keyboard([0x02, 0x00, 0x04, 0, 0, 0, 0, 0]))
+    );
+
+    let play = HidReport::from_payload(&Payload::ConsumerControl {
+        usage: CONSUMER_PLAY_PAUSE,
+    })
+    .expect("consumer report");
+    assert_eq!(play.as_bytes(), &[HID_REPORT_ID_CONSUMER, 0xCD, 0x00]);
+
+    let release = HidReport::from_payload(&Payload::ConsumerControl { usage: 0 }).expect("release");
+    assert_eq!(release.as_bytes(), &[HID_REPORT_ID_CONSUMER, 0x00, 0x00]);
+
+    let sleep = HidReport::from_payload(&Payload::SystemControl {
+        usage: SYSTEM_SLEEP,
+    })
+    .expect("system report");
+    assert_eq!(sleep.as_bytes(), &[HID_REPORT_ID_SYSTEM, 0x82]);
+
+    assert_eq!(
+        HidReport::from_payload(&Payload::KeepAlive),
+        Err(HidReportError::NotHidReport)
+    );
+    assert_eq!(
+        HidReport::from_payload(&Payload::KeyReport {
+            keys: vec![0x07, 0x00]
+        }),
+        Err(HidReportError::KeyReport(KeyReportError::UnknownFormat(
+            0x07
+        )))
+    );
+}
+
+#[test]
+fn dongle_forwards_media_key_tap() {
+    let cfg = proto::demo_config();
+    let salt = [0x6D; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(cfg, SessionKeys::new(0x4D_45_44_49, salt), DummyAead);
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0x4D_45_44_49, salt, Role::Dongle),
+        DummyAead,
+    );
+
+    let mut forwarded = Vec::new();
+    for usage in [CONSUMER_VOLUME_UP, 0] {
+        let frame = keyboard
+            .send(Payload::ConsumerControl { usage })
+            .expect("send");
+        let payload = dongle.receive(&frame).expect("receive");
+        forwarded.push(HidReport::from_payload(&payload).expect("consumer report"));
+    }
+    assert_eq!(
+        forwarded,
+        vec![
+            HidReport::Consumer([HID_REPORT_ID_CONSUMER, 0xE9, 0x00]),
+            HidReport::Consumer([HID_REPORT_ID_CONSUMER, 0x00, 0x00]),
+        ]
+    );
+}
@@ -0,0 +1,108 @@
+use proto::{
+    encode_payload, ControlError, ControlMessage, LinkStats, Payload, RekeyMessage, ResyncMessage,
+    CAP_NKRO, CAP_POINTER, CONTROL_BATTERY_LEVEL, CONTROL_REKEY_REQUEST,
+};
+
+#[test]
+fn known_messages_round_trip_with_stable_codes() {
+    let messages = [
+        (
+            ControlMessage::BatteryLevel {
+                percent: 87,
+                millivolts: 4012,
+            },
+            vec![0x01, 87, 0xAC, 0x0F],
+        ),
+        (ControlMessage::LedState { leds: 0x02 }, vec![0x02, 0x02]),
+        (ControlMessage::Unpair, vec![0x03]),
+        (ControlMessage::GetStats, vec![0x04]),
+        (
+            ControlMessage::Stats(LinkStats {
+                frames_sent: 1000,
+                retransmits: 3,
+                rejected: 1,
+            }),
+            vec![0x05, 0xE8, 0x03, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0],
+        ),
+        (
+            ControlMessage::SetConfig {
+                key: 0x01,
+                value: 250,
+            },
+            vec![0x06, 0x01, 0xFA, 0, 0, 0],
+        ),
+        (ControlMessage::CapabilityQuery, vec![0x07]),
+        (
+            ControlMessage::Capabilities {
+                features: CAP_NKRO | CAP_POINTER,
+            },
+            vec![0x08, 0x09, 0, 0, 0],
+        ),
+        (
+            ControlMessage::Rekey(RekeyMessage::Confirm { epoch: 2 }),
+            vec![0x11, 0x02, 0x00],
+        ),
+        (
+            ControlMessage::Resync(ResyncMessage::Request { challenge: 7 }),
+            vec![0x12, 7, 0, 0, 0],
+        ),
+    ];
+    for (msg, wire) in messages {
+        let payload = msg.to_payload();
+        assert_eq!(encode_payload(&payload), wire, "{msg:?}");
+        assert_eq!(ControlMessage::from_payload(&payload), Ok(msg));
+    }
+}
+
+#[test]
+fn rekey_and_resync_helpers_go_through_the_registry() {
+    let request = RekeyMessage::Request { epoch: 5 };
+    assert_eq!(
+        request.to_payload(),
+        ControlMessage::Rekey(request).to_payload()
+    );
+    assert_eq!(
+        RekeyMessage::from_payload(&request.to_payload()),
+        Some(request)
+    );
+    assert_eq!(
+        ResyncMessage::from_payload(&request.to_payload()),
+        None,
+        "a rekey payload is not a resync message"
+    );
+    assert_eq!(request.to_payload().kind(), proto::PacketKind::Control);
+    assert_eq!(ControlMessage::Rekey(request).code(), CONTROL_REKEY_REQUEST);
+}
+
+#[test]
+fn unknown_codes_round_trip_verbatim() {
+    let payload = Payload::Control {
+        code: 0x7E,
+        data: vec![0xDE, 0xAD, 0xBE, 0xEF],
+    };
+    let msg = ControlMessage::from_payload(&payload).expect("unknown code decodes");
+    assert_eq!(
+        msg,
+        ControlMessage::Unknown {
+            code: 0x7E,
+            data: vec![0xDE, 0xAD, 0xBE, 0xEF],
+        }
+    );
+    assert_eq!(msg.to_payload(), payload);
+}
+
+#[test]
+fn malformed_known_codes_are_rejected() {
+    assert_eq!(
+        ControlMessage::decode(CONTROL_BATTERY_LEVEL, &[50]),
+        Err(ControlError::UnexpectedLength)
+    );
+    assert_eq!(
+        ControlMessage::decode(CONTROL_REKEY_REQUEST, &[1, 0, 0]),
+        Err(ControlError::UnexpectedLength)
+    );
+    assert_eq!(
+        ControlMessage::from_payload(&Payload::KeepAlive),
+        Err(ControlError::NotControl)
+    );
+}
@@ -0,0 +1,188 @@
+#![cfg(feature = "crypto")]
+
+use proto::{
+    decode_header, open_framed, seal_framed, validate_packet, Packet, PacketFlags, PacketHeader,
+    PacketKind, Payload, ProtocolConfig, RealAead, ReplayWindow, ResyncAction, ResyncMessage,
+    ResyncState, Role, SessionKeys, ValidationError, HEADER_LEN, KEY_BYTES, SESSION_SALT_BYTES,
+};
+
+const SESSION_ID: u32 = 0x0D_15_EA_5E;
+const KEY: [u8; KEY_BYTES] = [0x42; KEY_BYTES];
+
+fn session(role: Role) -> SessionKeys {
+    SessionKeys::for_role(SESSION_ID, [0x33; SESSION_SALT_BYTES], role)
+}
+
+fn seal(
+    session: &mut SessionKeys,
+    cfg: &ProtocolConfig,
+    kind: PacketKind,
+    payload: Payload,
+) -> Vec<u8> {
+    let counter = session.next_counter().expect("counter not exhausted");
+    let pkt = Packet {
+        header: PacketHeader {
+            session_id: SESSION_ID,
+            counter,
+            kind,
+            flags: PacketFlags {
+                encrypted: true,
+                needs_ack: false,
+                retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
+            },
+        },
+        payload,
+        mac: vec![0; cfg.security.mac_len],
+    };
+    seal_framed(&pkt, cfg, &RealAead::new(KEY), &session.nonce_for(counter)).expect("seal")
+}
+
+fn open(session: &SessionKeys, cfg: &ProtocolConfig, frame: &[u8]) -> Packet {
+    let header = decode_header(&frame[..HEADER_LEN]).expect("header");
+    open_framed(
+        frame,
+        cfg,
+        &RealAead::new(KEY),
+        &session.rx_nonce_for(header.counter),
+    )
+    .expect("authenticated")
+}
+
+fn key_report() -> Payload {
+    Payload::KeyReport { keys: vec![0x04] }
+}
+
+#[test]
+fn resync_exchange_reanchors_after_counter_jump() {
+    let cfg = proto::demo_config();
+    let mut keyboard = session(Role::Keyboard);
+    let mut dongle = session(Role::Dongle);
+    let mut window = ReplayWindow::new();
+    let mut resync = ResyncState::new();
+
+    let first = open(
+        &dongle,
+        &cfg,
+        &seal(&mut keyboard, &cfg, PacketKind::KeyReport, key_report()),
+    );
+    validate_packet(&first, &cfg, Some(SESSION_ID), Some(&window)).expect("first report");
+    window.record(first.header.counter);
+
+    // Keyboard restored a counter far past anything the dongle has seen.
+    keyboard.resume_from(1_000);
+    let jumped = open(
+        &dongle,
+        &cfg,
+        &seal(&mut keyboard, &cfg, PacketKind::KeyReport, key_report()),
+    );
+    assert_eq!(
+        validate_packet(&jumped, &cfg, Some(SESSION_ID), Some(&window)),
+        Err(ValidationError::CounterJump)
+    );
+    let ResyncAction::Challenge(request) =
+        resync.on_counter_jump(&jumped, &cfg, &mut window, 0xC0FF_EE00)
+    else {
+        panic!("expected a challenge");
+    };
+    assert!(resync.is_pending());
+
+    // Challenge travels downlink; the keyboard echoes it in its next uplink frame.
+    let request = open(
+        &keyboard,
+        &cfg,
+        &seal(&mut dongle, &cfg, PacketKind::Control, request),
+    );
+    let Some(ResyncMessage::Request { challenge }) = ResyncMessage::from_payload(&request.payload)
+    else {
+        panic!("expected a resync request");
+    };
+    let response = ResyncMessage::Response { challenge }.to_payload();
+    let response = open(
+        &dongle,
+        &cfg,
+        &seal(&mut keyboard, &cfg, PacketKind::Control, response),
+    );
+    assert_eq!(
+        validate_packet(&response, &cfg, Some(SESSION_ID), Some(&window)),
+        Err(ValidationError::CounterJump)
+    );
+    assert_eq!(
+        resync.on_counter_jump(&response, &cfg, &mut window, 0),
+        ResyncAction::Reanchored
+    );
+    assert_eq!(window.highest(), Some(response.header.counter));
+    assert!(!resync.is_pending());
+
+    // Traffic flows from the new anchor; the resync frame itself can't be replayed.
+    let next = open(
+        &dongle,
+        &cfg,
+        &seal(&mut keyboard, &cfg, PacketKind::KeyReport, key_report()),
+    );
+    validate_packet(&next, &cfg, Some(SESSION_ID), Some(&window)).expect("report after resync");
+    assert_eq!(
+        validate_packet(&response, &cfg, Some(SESSION_ID), Some(&window)),
+        Err(ValidationError::ReplayDetected)
+    );
+}
+
+#[test]
+fn resync_ignores_wrong_challenge_and_repeats_request() {
+    let cfg = proto::demo_config();
+    let mut keyboard = session(Role::Keyboard);
+    let dongle = session(Role::Dongle);
+    let mut window = ReplayWindow::new();
+    window.record(1);
+    let mut resync = ResyncState::new();
+
+    keyboard.resume_from(500);
+    let jumped = open(
+        &dongle,
+        &cfg,
+        &seal(&mut keyboard, &cfg, PacketKind::KeyReport, key_report()),
+    );
+    let first = resync.on_counter_jump(&jumped, &cfg, &mut window, 7);
+
+    let stale = ResyncMessage::Response { challenge: 8 }.to_payload();
+    let stale = open(
+        &dongle,
+        &cfg,
+        &seal(&mut keyboard, &cfg, PacketKind::Control, stale),
+    );
+    assert_eq!(resync.on_counter_jump(&stale, &cfg, &mut window, 9), first);
+    assert_eq!(window.highest(), Some(1));
+}
+
+#[test]
+fn counter_policy_sets_jump_limit_and_resync() {
+    let mut cfg = proto::demo_config();
+    let mut keyboard = session(Role::Keyboard);
+    let dongle = session(Role::Dongle);
+    let mut window = ReplayWindow::new();
+    window.record(1);
+
+    keyboard.resume_from(500);
+    let jumped = open(
+        &dongle,
+        &cfg,
+        &seal(&mut keyboard, &cfg, PacketKind::KeyReport, key_report()),
+    );
+
+    cfg.counters.max_jump = 1_000;
+    validate_packet(&jumped, &cfg, Some(SESSION_ID), Some(&window)).expect("within limit");
+
+    cfg.counters.max_jump = 10;
+    cfg.counters.resync = false;
+    assert_eq!(
+        validate_packet(&jumped, &cfg, Some(SESSION_ID), Some(&window)),
+        Err(ValidationError::CounterJump)
+    );
+    let mut resync = ResyncState::new();
+    assert_eq!(
+        resync.on_counter_jump(&jumped, &cfg, &mut window, 1),
+        ResyncAction::Drop
+    );
+    assert!(!resync.is_pending());
+}
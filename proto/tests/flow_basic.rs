@@ -1,7 +1,7 @@
 use proto::{
     open_framed, parse_framed, seal_framed, serialize_framed, validate_packet, CipherSuite,
     DummyAead, HandshakeKind, Packet, PacketFlags, PacketHeader, PacketKind, Payload,
-    ProtocolConfig, SessionKeys, SESSION_SALT_BYTES,
+    ProtocolConfig, ReplayWindow, SessionKeys, SESSION_SALT_BYTES,
 };
 
 #[test]
@@ -23,7 +23,12 @@ fn cold_handshake_then_keyreport_flow() {
             target: std::time::Duration::from_millis(6),
             max: std::time::Duration::from_millis(10),
         },
+        counters: proto::CounterPolicy {
+            max_jump: 50,
+            resync: true,
+        },
         max_payload_bytes: 32,
+        features: proto::CAP_NKRO,
     };
 
     let session_id = 0xCA_FE_BA_BE;
@@ -39,9 +44,12 @@ fn cold_handshake_then_keyreport_flow() {
                 encrypted: false,
                 needs_ack: true,
                 retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
             },
         },
         payload: Payload::HandshakeInit {
+            caps: proto::Capabilities::from_config(&cfg),
             eph_pubkey: [0xAA; proto::KEY_BYTES],
             nonce: session.handshake_nonce(),
         },
@@ -64,6 +72,8 @@ fn cold_handshake_then_keyreport_flow() {
                 encrypted: false,
                 needs_ack: true,
                 retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
             },
         },
         payload: Payload::HandshakeAccept { session_id },
@@ -84,19 +94,23 @@ fn cold_handshake_then_keyreport_flow() {
                 encrypted: true,
                 needs_ack: true,
                 retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
             },
         },
         payload: Payload::KeyReport { keys: vec![0x04] },
         mac: vec![0x03; cfg.security.mac_len],
     };
 
-    validate_packet(
-        &key_report,
-        &cfg,
-        Some(session_id),
-        Some(handshake_accept.header.counter),
-    )
-    .expect("key report validate");
+    // Receiver has seen nothing from this session yet.
+    let mut window = ReplayWindow::new();
+    validate_packet(&key_report, &cfg, Some(session_id), Some(&window))
+        .expect("key report validate");
+    window.record(key_report.header.counter);
+    assert_eq!(
+        validate_packet(&key_report, &cfg, Some(session_id), Some(&window)),
+        Err(proto::ValidationError::ReplayDetected)
+    );
     let kr_framed = serialize_framed(&key_report, &cfg).expect("key report frame");
     let kr_parsed = parse_framed(&kr_framed, &cfg).expect("key report parse");
     assert_eq!(key_report.header, kr_parsed.header);
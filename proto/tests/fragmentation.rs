@@ -0,0 +1,195 @@
+use proto::{
+    fragments, CryptoError, DummyAead, Fragment, FragmentError, PacketFlags, PacketHeader,
+    PacketKind, Payload, Reassembled, Reassembler, Role, SerializationError, Session, SessionError,
+    SessionKeys, MAX_CONTROL_MESSAGE_BYTES, MAX_FRAGMENTS, SESSION_SALT_BYTES,
+};
+
+const CONFIG_BLOB: u8 = 0x20;
+
+fn blob(len: usize) -> Vec<u8> {
+    (0..len).map(|i| i as u8).collect()
+}
+
+/// Header the keyboard's session would put on `fragment` sent under `counter`.
+fn header(counter: u32, fragment: &Fragment) -> PacketHeader {
+    PacketHeader {
+        session_id: 0x0F_0F,
+        counter,
+        kind: PacketKind::Control,
+        flags: PacketFlags {
+            encrypted: true,
+            needs_ack: true,
+            retransmit: false,
+            more_fragments: fragment.more_fragments,
+            fragment_index: fragment.fragment_index,
+        },
+    }
+}
+
+/// Fragments of `data` with the counters they go out under, starting at `first_counter`.
+fn split(first_counter: u32, data: &[u8]) -> Vec<(PacketHeader, Payload)> {
+    fragments(&proto::demo_config(), CONFIG_BLOB, data)
+        .expect("fits")
+        .zip(first_counter..)
+        .map(|(fragment, counter)| (header(counter, &fragment), fragment.payload))
+        .collect()
+}
+
+#[test]
+fn large_control_message_survives_reordering() {
+    let cfg = proto::demo_config();
+    let salt = [0x0F; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(cfg, SessionKeys::new(0x0F_0F, salt), DummyAead);
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0x0F_0F, salt, Role::Dongle),
+        DummyAead,
+    );
+    let mut reassembler = Reassembler::new(&cfg);
+
+    let message = blob(100);
+    let frames = keyboard
+        .send_fragmented(CONFIG_BLOB, &message)
+        .expect("send");
+    assert_eq!(frames.len(), 4);
+
+    for (i, &n) in [3, 0, 2].iter().enumerate() {
+        let (header, payload) = dongle.receive_with_header(&frames[n]).expect("delivered");
+        assert!(header.flags.is_fragment() && header.flags.needs_ack);
+        assert_eq!(header.flags.more_fragments, n != 3);
+        assert_eq!(reassembler.push(&header, &payload, i as u64), Ok(None));
+    }
+    assert_eq!(reassembler.in_progress(), 1);
+
+    let (header, payload) = dongle.receive_with_header(&frames[1]).expect("delivered");
+    assert_eq!(
+        reassembler.push(&header, &payload, 3),
+        Ok(Some(Reassembled {
+            code: CONFIG_BLOB,
+            data: &message,
+        }))
+    );
+    assert_eq!(reassembler.in_progress(), 0);
+
+    // A message that fits goes out as one plain Control frame.
+    let frames = keyboard
+        .send_fragmented(CONFIG_BLOB, &[1, 2, 3])
+        .expect("send");
+    assert_eq!(frames.len(), 1);
+    let (header, payload) = dongle.receive_with_header(&frames[0]).expect("delivered");
+    assert!(!header.flags.is_fragment());
+    assert_eq!(
+        payload,
+        Payload::Control {
+            code: CONFIG_BLOB,
+            data: vec![1, 2, 3]
+        }
+    );
+    assert_eq!(
+        reassembler.push(&header, &payload, 4),
+        Err(FragmentError::NotFragment)
+    );
+}
+
+#[test]
+fn message_size_is_bounded() {
+    let cfg = proto::demo_config();
+    let largest = (cfg.max_payload_bytes as usize - 1) * MAX_FRAGMENTS;
+    assert!(largest <= MAX_CONTROL_MESSAGE_BYTES);
+    assert_eq!(
+        fragments(&cfg, CONFIG_BLOB, &blob(largest))
+            .expect("fits")
+            .count(),
+        MAX_FRAGMENTS
+    );
+    assert!(matches!(
+        fragments(&cfg, CONFIG_BLOB, &blob(largest + 1)),
+        Err(FragmentError::TooLarge)
+    ));
+
+    let mut keyboard = Session::new(cfg, SessionKeys::new(1, [0; SESSION_SALT_BYTES]), DummyAead);
+    assert_eq!(
+        keyboard.send_fragmented(CONFIG_BLOB, &blob(largest + 1)),
+        Err(SessionError::Crypto(CryptoError::Serialize(
+            SerializationError::PayloadTooLarge
+        )))
+    );
+}
+
+#[test]
+fn duplicates_and_conflicting_fragments() {
+    let cfg = proto::demo_config();
+    let mut reassembler = Reassembler::new(&cfg);
+    let message = split(10, &blob(70));
+
+    let (header, payload) = &message[0];
+    assert_eq!(reassembler.push(header, payload, 0), Ok(None));
+    // A retransmitted copy is ignored.
+    assert_eq!(reassembler.push(header, payload, 0), Ok(None));
+
+    // Another message claiming counter 10 is not spliced in.
+    let other = split(9, &blob(40));
+    assert_eq!(
+        reassembler.push(&other[1].0, &other[1].1, 0),
+        Err(FragmentError::Overlap)
+    );
+
+    // Same index, different bytes: the message is dropped.
+    let changed = Payload::Control {
+        code: CONFIG_BLOB,
+        data: vec![0xEE; 31],
+    };
+    assert_eq!(
+        reassembler.push(header, &changed, 0),
+        Err(FragmentError::Overlap)
+    );
+    assert_eq!(reassembler.in_progress(), 0);
+
+    // Nothing may follow the final fragment.
+    let (last_header, last) = &message[2];
+    assert_eq!(reassembler.push(last_header, last, 0), Ok(None));
+    let mut past = *header;
+    past.counter = 13;
+    past.flags.fragment_index = 3;
+    assert_eq!(
+        reassembler.push(&past, payload, 0),
+        Err(FragmentError::Malformed)
+    );
+
+    // A non-final fragment must carry a full chunk.
+    let mut short = *header;
+    short.counter = 20;
+    assert_eq!(
+        reassembler.push(&short, last, 0),
+        Err(FragmentError::Malformed)
+    );
+}
+
+#[test]
+fn reassembly_buffer_is_bounded_in_space_and_time() {
+    let cfg = proto::demo_config();
+    let mut reassembler = Reassembler::new(&cfg);
+    let first = split(1, &blob(40));
+    let second = split(100, &blob(40));
+    let third = split(200, &blob(40));
+
+    assert_eq!(reassembler.push(&first[0].0, &first[0].1, 0), Ok(None));
+    assert_eq!(reassembler.push(&second[0].0, &second[0].1, 0), Ok(None));
+    assert_eq!(
+        reassembler.push(&third[0].0, &third[0].1, 0),
+        Err(FragmentError::Busy)
+    );
+
+    // Each fragment gets a full retransmit cycle at the latency budget, then the message goes.
+    let timeout_ms = 10 * 2 * MAX_FRAGMENTS as u64;
+    assert_eq!(reassembler.expire(timeout_ms), 0);
+    assert_eq!(reassembler.expire(timeout_ms + 1), 2);
+    assert_eq!(
+        reassembler.push(&third[0].0, &third[0].1, timeout_ms + 1),
+        Ok(None)
+    );
+    assert!(reassembler
+        .push(&third[1].0, &third[1].1, timeout_ms + 2)
+        .expect("completes")
+        .is_some());
+}
@@ -0,0 +1,90 @@
+use proto::{
+    open_framed, open_framed_in_place, seal_framed, seal_framed_into, CryptoError, DefaultAead,
+    DummyAead, Packet, PacketFlags, PacketHeader, PacketKind, Payload, PayloadRef,
+    SerializationError, SessionKeys, HEADER_LEN, KEY_BYTES, MAX_MAC_BYTES, SESSION_SALT_BYTES,
+};
+
+fn control_packet(session: &mut SessionKeys) -> Packet {
+    Packet {
+        header: PacketHeader {
+            session_id: session.session_id,
+            counter: session.next_counter().expect("counter not exhausted"),
+            kind: PacketKind::Control,
+            flags: PacketFlags {
+                encrypted: true,
+                needs_ack: true,
+                retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
+            },
+        },
+        payload: Payload::Control {
+            code: 0x01,
+            data: vec![0x5C, 0x0F],
+        },
+        mac: vec![0; proto::demo_config().security.mac_len],
+    }
+}
+
+fn default_aead() -> DefaultAead {
+    #[cfg(feature = "crypto")]
+    {
+        DefaultAead::new([0x42; KEY_BYTES])
+    }
+    #[cfg(not(feature = "crypto"))]
+    {
+        let _ = KEY_BYTES;
+        DefaultAead::new()
+    }
+}
+
+#[test]
+fn in_place_path_matches_allocating_path() {
+    let cfg = proto::demo_config();
+    let mut session = SessionKeys::new(0x1A_2B_3C_4D, [0x61; SESSION_SALT_BYTES]);
+    let pkt = control_packet(&mut session);
+    let nonce = session.nonce_for(pkt.header.counter);
+
+    for aead in [&default_aead() as &dyn proto::Aead, &DummyAead] {
+        let expected = seal_framed(&pkt, &cfg, aead, &nonce).expect("seal");
+        let mut buf = [0u8; 64];
+        let len = seal_framed_into(&pkt, &cfg, aead, &nonce, &mut buf).expect("seal into");
+        assert_eq!(&buf[..len], expected.as_slice());
+
+        let owned = open_framed(&expected, &cfg, aead, &nonce).expect("open");
+        let opened = open_framed_in_place(&mut buf[..len], &cfg, aead, &nonce).expect("open");
+        assert_eq!(opened.header, owned.header);
+        assert_eq!(
+            opened.payload,
+            PayloadRef::Control {
+                code: 0x01,
+                data: &[0x5C, 0x0F]
+            }
+        );
+        assert_eq!(opened.payload.to_owned(), owned.payload);
+        assert_eq!(opened.mac, owned.mac.as_slice());
+    }
+}
+
+#[test]
+fn in_place_path_rejects_small_buffers_and_tampering() {
+    let cfg = proto::demo_config();
+    let mut session = SessionKeys::new(0x1A_2B_3C_4D, [0x61; SESSION_SALT_BYTES]);
+    let pkt = control_packet(&mut session);
+    let nonce = session.nonce_for(pkt.header.counter);
+    let aead = default_aead();
+
+    let mut small = [0u8; HEADER_LEN + 2 + MAX_MAC_BYTES];
+    assert_eq!(
+        seal_framed_into(&pkt, &cfg, &aead, &nonce, &mut small),
+        Err(CryptoError::Serialize(SerializationError::BufferTooSmall))
+    );
+
+    let mut buf = [0u8; 64];
+    let len = seal_framed_into(&pkt, &cfg, &aead, &nonce, &mut buf).expect("seal into");
+    buf[HEADER_LEN + 2] ^= 0x80;
+    assert!(matches!(
+        open_framed_in_place(&mut buf[..len], &cfg, &aead, &nonce),
+        Err(CryptoError::AuthFailed { .. })
+    ));
+}
@@ -0,0 +1,98 @@
+use proto::{
+    decode_payload, encode_payload, ControlMessage, DummyAead, HostIndicators, Indicators,
+    KeyReport, PacketKind, ParseError, Payload, Role, Session, SessionKeys, LED_CAPS_LOCK,
+    LED_NUM_LOCK, SESSION_SALT_BYTES,
+};
+
+#[test]
+fn ack_carries_optional_indicator_byte() {
+    let plain = Payload::Ack {
+        ack_counter: 9,
+        sack: 0,
+        indicators: None,
+    };
+    assert_eq!(encode_payload(&plain), vec![9, 0, 0, 0]);
+    assert_eq!(decode_payload(PacketKind::Ack, &[9, 0, 0, 0]), Ok(plain));
+
+    let with_leds = Payload::Ack {
+        ack_counter: 9,
+        sack: 0,
+        indicators: Some(LED_CAPS_LOCK),
+    };
+    assert_eq!(encode_payload(&with_leds), vec![9, 0, 0, 0, 0x02]);
+    assert_eq!(
+        decode_payload(PacketKind::Ack, &[9, 0, 0, 0, 0x02]),
+        Ok(with_leds)
+    );
+
+    assert_eq!(
+        decode_payload(PacketKind::Ack, &[9, 0, 0, 0, 0x02, 0x00]),
+        Err(ParseError::UnexpectedLength)
+    );
+}
+
+#[test]
+fn dongle_acks_only_carry_leds_once_the_host_set_them() {
+    let mut host = HostIndicators::new();
+    assert_eq!(
+        host.ack(3),
+        Payload::Ack {
+            ack_counter: 3,
+            sack: 0,
+            indicators: None
+        }
+    );
+    host.set_output_report(LED_NUM_LOCK | LED_CAPS_LOCK);
+    assert_eq!(
+        host.ack(4),
+        Payload::Ack {
+            ack_counter: 4,
+            sack: 0,
+            indicators: Some(0x03)
+        }
+    );
+}
+
+#[test]
+fn keyboard_reports_only_changes() {
+    let mut leds = Indicators::new();
+    let ack = |indicators| Payload::Ack {
+        ack_counter: 1,
+        sack: 0,
+        indicators,
+    };
+    assert_eq!(leds.apply(&ack(None)), None);
+    assert_eq!(leds.apply(&ack(Some(LED_CAPS_LOCK))), Some(LED_CAPS_LOCK));
+    assert!(leds.is_on(LED_CAPS_LOCK));
+    assert_eq!(leds.apply(&ack(Some(LED_CAPS_LOCK))), None);
+    assert_eq!(
+        leds.apply(&ControlMessage::LedState { leds: 0 }.to_payload()),
+        Some(0)
+    );
+    assert!(!leds.is_on(LED_CAPS_LOCK));
+    assert_eq!(leds.apply(&Payload::KeepAlive), None);
+}
+
+#[test]
+fn caps_lock_reaches_keyboard_on_the_next_ack() {
+    let cfg = proto::demo_config();
+    let salt = [0x1E; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(cfg, SessionKeys::new(0x4C_45_44_53, salt), DummyAead);
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0x4C_45_44_53, salt, Role::Dongle),
+        DummyAead,
+    );
+    let mut host = HostIndicators::new();
+    let mut leds = Indicators::new();
+
+    // Caps Lock pressed: the host toggles the LED while the report is in flight.
+    let caps = KeyReport::boot(0, &[0x39]).expect("caps lock");
+    let frame = keyboard.send(caps.to_payload()).expect("send");
+    let (header, _) = dongle.receive_with_header(&frame).expect("receive");
+    host.set_output_report(LED_CAPS_LOCK);
+
+    let ack = dongle.send(host.ack(header.counter)).expect("ack");
+    let payload = keyboard.receive(&ack).expect("ack receive");
+    assert_eq!(leds.apply(&payload), Some(LED_CAPS_LOCK));
+}
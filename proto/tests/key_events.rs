@@ -0,0 +1,190 @@
+use proto::{
+    decode_header, encode_payload, DummyAead, KeyEventBatch, KeyEventError, KeyEventQueue,
+    KeyEventReceiver, KeyReport, KeyReportError, Payload, Role, Session, SessionKeys, CAP_PADDING,
+    HEADER_LEN, KEY_REPORT_EVENTS, MAX_KEY_EVENTS, PADDED_RECORD_HEADER_BYTES, SESSION_SALT_BYTES,
+};
+
+const KEY_A: u8 = 0x04;
+const KEY_B: u8 = 0x05;
+const LEFT_SHIFT: u8 = 0xE1;
+
+#[test]
+fn press_release_pair_encodes_in_one_small_payload() {
+    let cfg = proto::demo_config();
+    let mut queue = KeyEventQueue::new();
+    assert_eq!(queue.push(KEY_A, true), Ok(0));
+    assert_eq!(queue.push(KEY_A, false), Ok(1));
+
+    let batch = queue.batch(&cfg);
+    let payload = batch.to_payload();
+    assert_eq!(
+        encode_payload(&payload),
+        vec![KEY_REPORT_EVENTS, 0x00, 0x00, 2, 0b01, KEY_A, KEY_A]
+    );
+    assert_eq!(KeyEventBatch::from_payload(&payload), Ok(batch));
+    // Snapshot decoding refuses the event format instead of misreading it.
+    assert_eq!(
+        KeyReport::from_payload(&payload),
+        Err(KeyReportError::UnknownFormat(KEY_REPORT_EVENTS))
+    );
+}
+
+#[test]
+fn receiver_replays_every_event_across_a_lost_frame() {
+    let cfg = proto::demo_config();
+    let salt = [0x5A; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(cfg, SessionKeys::new(0x0E_7E_17_00, salt), DummyAead);
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0x0E_7E_17_00, salt, Role::Dongle),
+        DummyAead,
+    );
+    let mut queue = KeyEventQueue::new();
+    let mut receiver = KeyEventReceiver::new();
+
+    // Shift+a tapped faster than a report interval; the frame never arrives.
+    queue.push(LEFT_SHIFT, true).unwrap();
+    queue.push(KEY_A, true).unwrap();
+    queue.push(KEY_A, false).unwrap();
+    let _lost = keyboard.send(queue.batch(&cfg).to_payload()).unwrap();
+
+    // Next scan adds b; the batch still carries everything unacked.
+    queue.push(KEY_B, true).unwrap();
+    let batch = queue.batch(&cfg);
+    let frame = keyboard.send(batch.to_payload()).unwrap();
+
+    let payload = dongle.receive(&frame).unwrap();
+    let mut host_reports = Vec::new();
+    let applied = receiver
+        .apply(&KeyEventBatch::from_payload(&payload).unwrap(), |state| {
+            host_reports.push(state.to_boot_report())
+        })
+        .unwrap();
+    assert_eq!(applied, 4);
+    assert_eq!(
+        host_reports,
+        vec![
+            [0x02, 0, 0, 0, 0, 0, 0, 0],
+            [0x02, 0, KEY_A, 0, 0, 0, 0, 0],
+            [0x02, 0, 0, 0, 0, 0, 0, 0],
+            [0x02, 0, KEY_B, 0, 0, 0, 0, 0],
+        ],
+        "the short a press survives as its own pair of host reports"
+    );
+
+    // A retransmit of the same batch is trimmed to nothing.
+    assert_eq!(receiver.apply(&batch, |_| panic!("duplicate")), Ok(0));
+
+    let ack = dongle
+        .send(Payload::Ack {
+            ack_counter: decode_header(&frame[..HEADER_LEN]).unwrap().counter,
+            sack: 0,
+            indicators: None,
+        })
+        .unwrap();
+    assert!(matches!(keyboard.receive(&ack), Ok(Payload::Ack { .. })));
+    queue.acknowledge(batch.last_seq().unwrap());
+    assert!(queue.is_empty());
+    assert_eq!(receiver.next_expected(), Some(4));
+}
+
+#[test]
+fn receiver_reports_gap_and_reanchors_from_snapshot() {
+    let cfg = proto::demo_config();
+    let mut queue = KeyEventQueue::new();
+    let mut receiver = KeyEventReceiver::new();
+
+    queue.push(KEY_A, true).unwrap();
+    let first = queue.batch(&cfg);
+    receiver.apply(&first, |_| {}).unwrap();
+    queue.acknowledge(first.last_seq().unwrap());
+
+    // Sender overflows, falls back to a snapshot (lost), and clears its queue.
+    for _ in 0..MAX_KEY_EVENTS {
+        queue.push(KEY_B, true).unwrap();
+    }
+    assert_eq!(queue.push(KEY_B, false), Err(KeyEventError::QueueFull));
+    let stale = queue.batch(&cfg);
+    let snapshot = KeyReport::boot(0, &[KEY_A, KEY_B]).unwrap();
+    let snapshot_seq = queue.next_seq();
+    queue.clear();
+
+    queue.push(KEY_A, false).unwrap();
+    let after = queue.batch(&cfg);
+    assert_eq!(
+        receiver.apply(&after, |_| panic!("must not apply across a gap")),
+        Err(KeyEventError::Gap {
+            expected: 1,
+            received: after.first_seq(),
+        })
+    );
+    assert!(receiver.state().is_pressed(KEY_A));
+
+    receiver.apply_snapshot(&snapshot, snapshot_seq);
+    assert_eq!(receiver.next_expected(), Some(snapshot_seq));
+
+    // A pre-snapshot batch reordered behind the snapshot is a repeat, not a new anchor.
+    assert_eq!(
+        receiver.apply(&stale, |_| panic!("stale events must be trimmed")),
+        Ok(0)
+    );
+    assert_eq!(receiver.next_expected(), Some(snapshot_seq));
+
+    assert_eq!(receiver.apply(&after, |_| {}), Ok(1));
+    assert_eq!(receiver.state().pressed().collect::<Vec<_>>(), vec![KEY_B]);
+}
+
+#[test]
+fn batch_respects_payload_limit_and_sequence_wraps() {
+    let mut cfg = proto::demo_config();
+    cfg.max_payload_bytes = 16;
+    let mut queue = KeyEventQueue::new();
+    for _ in 0..12 {
+        queue.push(KEY_A, true).unwrap();
+    }
+    let batch = queue.batch(&cfg);
+    assert_eq!(batch.events().len(), 10);
+    assert!(batch.encoded_len() <= 16);
+
+    let wrapped =
+        KeyEventBatch::decode(&[KEY_REPORT_EVENTS, 0xFF, 0xFF, 2, 0b11, KEY_A, KEY_B]).unwrap();
+    assert_eq!(wrapped.events()[1].seq, 0);
+    let mut receiver = KeyEventReceiver::new();
+    assert_eq!(receiver.apply(&wrapped, |_| {}), Ok(2));
+    assert_eq!(receiver.next_expected(), Some(1));
+
+    assert_eq!(
+        KeyEventBatch::decode(&[KEY_REPORT_EVENTS, 0, 0, 2, 0, KEY_A]),
+        Err(KeyEventError::UnexpectedLength)
+    );
+    assert_eq!(
+        KeyEventBatch::decode(&[KEY_REPORT_EVENTS, 0, 0, 1, 0, 0x02]),
+        Err(KeyEventError::InvalidUsage(0x02))
+    );
+}
+
+#[test]
+fn full_queue_batch_fits_a_padded_frame() {
+    let mut cfg = proto::demo_config();
+    cfg.features |= CAP_PADDING;
+    let salt = [0x5A; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(cfg, SessionKeys::new(0x0E_7E_17_01, salt), DummyAead);
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0x0E_7E_17_01, salt, Role::Dongle),
+        DummyAead,
+    );
+    let mut queue = KeyEventQueue::new();
+    for _ in 0..MAX_KEY_EVENTS {
+        queue.push(KEY_A, true).unwrap();
+    }
+
+    let batch = queue.batch(&cfg);
+    assert!(batch.encoded_len() <= cfg.max_payload_bytes as usize - PADDED_RECORD_HEADER_BYTES);
+    assert!(batch.events().len() < MAX_KEY_EVENTS);
+    let frame = keyboard
+        .send(batch.to_payload())
+        .expect("batch fits the padded frame");
+    let payload = dongle.receive(&frame).expect("delivered");
+    assert_eq!(KeyEventBatch::from_payload(&payload), Ok(batch));
+}
@@ -0,0 +1,138 @@
+use proto::{
+    encode_payload, DummyAead, KeyReport, KeyReportError, KeyRollover, Payload, Role, Session,
+    SessionKeys, HID_ERROR_ROLL_OVER, KEY_REPORT_MAX_BYTES, MODIFIER_LEFT_CTRL,
+    MODIFIER_LEFT_SHIFT, MODIFIER_RIGHT_GUI, SESSION_SALT_BYTES,
+};
+
+#[test]
+fn boot_report_encodes_compactly_and_converts_to_hid() {
+    let mut report = KeyReport::boot(MODIFIER_LEFT_SHIFT, &[0x04, 0x05]).expect("two keys");
+    let payload = report.to_payload();
+    assert_eq!(encode_payload(&payload), vec![0x00, 0x02, 0x04, 0x05]);
+    assert_eq!(KeyReport::from_payload(&payload), Ok(report));
+    assert_eq!(
+        report.to_boot_report(),
+        [0x02, 0x00, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00]
+    );
+
+    // Modifier usages land in the modifier byte; releasing closes the slot gap.
+    report.press(0xE0).expect("left ctrl");
+    report.release(0x04);
+    assert_eq!(report.modifiers, MODIFIER_LEFT_SHIFT | MODIFIER_LEFT_CTRL);
+    assert_eq!(report.pressed().collect::<Vec<_>>(), vec![0x05]);
+
+    report.release(0x05);
+    report.release(0xE0);
+    report.release(0xE1);
+    assert!(report.is_released());
+    assert_eq!(
+        encode_payload(&report.to_payload()),
+        vec![0x00, 0x00],
+        "all-released report is just the format and modifier bytes"
+    );
+}
+
+#[test]
+fn boot_report_rejects_seventh_key_and_bad_usages() {
+    let mut report = KeyReport::boot(0, &[0x04, 0x05, 0x06, 0x07, 0x08, 0x09]).expect("six keys");
+    assert_eq!(report.press(0x0A), Err(KeyReportError::TooManyKeys));
+    assert_eq!(
+        report.press(0x04),
+        Ok(()),
+        "re-pressing a held key is a no-op"
+    );
+    assert_eq!(report.press(0x01), Err(KeyReportError::InvalidUsage(0x01)));
+    assert_eq!(
+        KeyReport::boot(0, &[0xE8]),
+        Err(KeyReportError::InvalidUsage(0xE8))
+    );
+}
+
+#[test]
+fn nkro_report_round_trips_and_rolls_over_in_boot_form() {
+    let mut report = KeyReport::nkro(MODIFIER_RIGHT_GUI);
+    let held = [0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A];
+    for usage in held {
+        report.press(usage).expect("nkro holds any key");
+    }
+    let payload = report.to_payload();
+    // Usages 0x04..=0x0A sit in the first two bitmap bytes; the rest are trimmed.
+    assert_eq!(encode_payload(&payload), vec![0x01, 0x80, 0xF0, 0x07]);
+    assert_eq!(KeyReport::from_payload(&payload), Ok(report));
+    assert_eq!(report.pressed().collect::<Vec<_>>(), held.to_vec());
+    assert_eq!(
+        report.to_boot_report(),
+        [0x80, 0x00, HID_ERROR_ROLL_OVER, 1, 1, 1, 1, 1]
+    );
+
+    report.release(0x0A);
+    assert_eq!(
+        report.to_boot_report(),
+        [0x80, 0x00, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09]
+    );
+
+    let mut full = KeyReport::nkro(0xFF);
+    for usage in 0x04..0xE0 {
+        full.press(usage).expect("key usage");
+    }
+    assert_eq!(
+        encode_payload(&full.to_payload()).len(),
+        KEY_REPORT_MAX_BYTES
+    );
+    assert!(KEY_REPORT_MAX_BYTES <= usize::from(proto::demo_config().max_payload_bytes));
+}
+
+#[test]
+fn decode_rejects_malformed_reports() {
+    assert_eq!(
+        KeyReport::decode(&[0x00]),
+        Err(KeyReportError::UnexpectedLength)
+    );
+    assert_eq!(
+        KeyReport::decode(&[0x00, 0x00, 4, 5, 6, 7, 8, 9, 10]),
+        Err(KeyReportError::UnexpectedLength)
+    );
+    assert_eq!(
+        KeyReport::decode(&[0x00, 0x00, 0x04, 0x00]),
+        Err(KeyReportError::InvalidUsage(0x00))
+    );
+    assert_eq!(
+        KeyReport::decode(&[0x00, 0x00, 0x04, 0x04]),
+        Err(KeyReportError::DuplicateUsage(0x04))
+    );
+    assert_eq!(
+        KeyReport::decode(&[0x01, 0x00, 0x01]),
+        Err(KeyReportError::InvalidUsage(0x00))
+    );
+    assert_eq!(
+        KeyReport::decode(&[0x07, 0x00]),
+        Err(KeyReportError::UnknownFormat(0x07))
+    );
+    assert_eq!(
+        KeyReport::from_payload(&Payload::KeepAlive),
+        Err(KeyReportError::NotKeyReport)
+    );
+}
+
+#[test]
+fn dongle_turns_received_report_into_hid() {
+    let cfg = proto::demo_config();
+    let salt = [0x3C; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(cfg, SessionKeys::new(0x4B_45_59_53, salt), DummyAead);
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0x4B_45_59_53, salt, Role::Dongle),
+        DummyAead,
+    );
+
+    let report = KeyReport::boot(MODIFIER_LEFT_CTRL, &[0x06]).expect("ctrl+c");
+    let frame = keyboard.send(report.to_payload()).expect("send");
+    let payload = dongle.receive(&frame).expect("receive");
+
+    let decoded = KeyReport::from_payload(&payload).expect("typed report");
+    assert!(matches!(decoded.keys, KeyRollover::Boot(_)));
+    assert_eq!(
+        decoded.to_boot_report(),
+        [0x01, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00]
+    );
+}
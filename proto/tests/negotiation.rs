@@ -0,0 +1,215 @@
+use proto::{
+    negotiate, Capabilities, DummyAead, HandshakeError, HandshakeKind, Incompatibility, KeyReport,
+    Payload, PskInitiator, PskResponder, Session, CAP_KEY_EVENTS, CAP_NKRO, CAP_PADDING,
+    CAP_POINTER, HEADER_LEN, KEY_BYTES, MIN_NEGOTIATED_PAYLOAD_BYTES, MIN_PROTOCOL_VERSION,
+    NONCE_BYTES, PADDED_RECORD_HEADER_BYTES, PROTOCOL_VERSION,
+};
+
+const PSK: [u8; KEY_BYTES] = [0x3C; KEY_BYTES];
+
+fn psk_config() -> proto::ProtocolConfig {
+    let mut cfg = proto::demo_config();
+    cfg.security.handshake = HandshakeKind::PreShared;
+    cfg.security.forward_secure = false;
+    cfg
+}
+
+#[test]
+fn capabilities_round_trip() {
+    let caps = Capabilities {
+        version: 3,
+        min_version: 2,
+        cipher_suite: 0,
+        mac_len: 12,
+        max_payload_bytes: 0x0140,
+        features: CAP_NKRO | CAP_POINTER,
+    };
+    let bytes = caps.to_bytes();
+    assert_eq!(bytes, [3, 2, 0, 12, 0x40, 0x01, 0x09, 0x00, 0x00, 0x00]);
+    assert_eq!(Capabilities::from_bytes(&bytes), caps);
+}
+
+#[test]
+fn negotiation_settles_on_common_ground() {
+    let keyboard_cfg = psk_config();
+    let mut dongle_cfg = psk_config();
+    dongle_cfg.max_payload_bytes = 30;
+    dongle_cfg.security.mac_len = 8;
+    dongle_cfg.features = CAP_NKRO | CAP_KEY_EVENTS;
+
+    let mut keyboard = PskInitiator::new(&keyboard_cfg, PSK, [0x01; NONCE_BYTES]);
+    let mut dongle = PskResponder::new(&dongle_cfg, PSK, [0x02; NONCE_BYTES]);
+    let init = keyboard.start().expect("init frame");
+    let (reply, dongle_session) = dongle.respond(&init).expect("responder accepts init");
+    let keyboard_session = keyboard.finish(&reply).expect("initiator accepts reply");
+
+    for established in [&keyboard_session, &dongle_session] {
+        assert_eq!(established.version, PROTOCOL_VERSION);
+        assert_eq!(established.config.max_payload_bytes, 30);
+        assert_eq!(established.config.security.mac_len, 16);
+        assert_eq!(established.config.features, CAP_NKRO | CAP_KEY_EVENTS);
+    }
+
+    // Both ends frame data with the negotiated config.
+    let mut tx = Session::new(keyboard_session.config, keyboard_session.session, DummyAead);
+    let mut rx = Session::new(dongle_session.config, dongle_session.session, DummyAead);
+    let frame = tx.send(Payload::KeepAlive).expect("send");
+    assert_eq!(rx.receive(&frame), Ok(Payload::KeepAlive));
+}
+
+#[test]
+fn version_ranges_must_overlap() {
+    let cfg = proto::demo_config();
+    let local = Capabilities::from_config(&cfg);
+
+    let newer = Capabilities {
+        version: PROTOCOL_VERSION + 1,
+        ..local
+    };
+    let (version, _) = negotiate(&cfg, &newer).expect("newer peer still speaks ours");
+    assert_eq!(version, PROTOCOL_VERSION);
+
+    let newer_only = Capabilities {
+        version: PROTOCOL_VERSION + 1,
+        min_version: PROTOCOL_VERSION + 1,
+        ..local
+    };
+    assert_eq!(
+        negotiate(&cfg, &newer_only).map(|_| ()),
+        Err(Incompatibility::Version {
+            local: PROTOCOL_VERSION,
+            peer: PROTOCOL_VERSION + 1
+        })
+    );
+
+    let older = Capabilities {
+        version: MIN_PROTOCOL_VERSION - 1,
+        min_version: 0,
+        ..local
+    };
+    assert!(matches!(
+        negotiate(&cfg, &older),
+        Err(Incompatibility::Version { .. })
+    ));
+}
+
+#[test]
+fn unusable_offers_are_named() {
+    let cfg = proto::demo_config();
+    let local = Capabilities::from_config(&cfg);
+
+    let other_suite = Capabilities {
+        cipher_suite: 0x7F,
+        ..local
+    };
+    assert_eq!(
+        negotiate(&cfg, &other_suite).map(|_| ()),
+        Err(Incompatibility::CipherSuite {
+            local: local.cipher_suite,
+            peer: 0x7F
+        })
+    );
+    let huge_mac = Capabilities {
+        mac_len: 32,
+        ..local
+    };
+    assert_eq!(
+        negotiate(&cfg, &huge_mac).map(|_| ()),
+        Err(Incompatibility::MacLength(32))
+    );
+    let mut odd_mac = cfg;
+    odd_mac.security.mac_len = 10;
+    assert_eq!(
+        negotiate(&odd_mac, &local).map(|_| ()),
+        Err(Incompatibility::MacLength(10))
+    );
+}
+
+#[test]
+fn padding_needs_room_for_a_padded_key_report() {
+    let mut cfg = proto::demo_config();
+    cfg.features |= CAP_PADDING;
+    let small = Capabilities {
+        max_payload_bytes: MIN_NEGOTIATED_PAYLOAD_BYTES,
+        ..Capabilities::from_config(&cfg)
+    };
+    assert_eq!(
+        negotiate(&cfg, &small).map(|_| ()),
+        Err(Incompatibility::PayloadSize(MIN_NEGOTIATED_PAYLOAD_BYTES))
+    );
+
+    // A peer that does not pad gets the plain minimum.
+    let plain = Capabilities {
+        features: small.features & !CAP_PADDING,
+        ..small
+    };
+    let (_, negotiated) = negotiate(&cfg, &plain).expect("fits unpadded");
+    assert_eq!(negotiated.max_payload_bytes, MIN_NEGOTIATED_PAYLOAD_BYTES);
+
+    let padded = Capabilities {
+        max_payload_bytes: MIN_NEGOTIATED_PAYLOAD_BYTES + PADDED_RECORD_HEADER_BYTES as u16,
+        ..small
+    };
+    let (_, negotiated) = negotiate(&cfg, &padded).expect("fits padded");
+    let report = KeyReport::nkro(0).to_payload();
+    assert!(proto::pad_payload(&negotiated, &report).is_ok());
+}
+
+#[test]
+fn non_negotiated_policy_must_match() {
+    let keyboard_cfg = psk_config();
+    let mismatches: [fn(&mut proto::ProtocolConfig); 4] = [
+        |cfg| cfg.security.replay_protection = !cfg.security.replay_protection,
+        |cfg| cfg.latency.max += std::time::Duration::from_millis(5),
+        |cfg| cfg.counters.max_jump += 1,
+        |cfg| cfg.counters.resync = !cfg.counters.resync,
+    ];
+    for mismatch in mismatches {
+        let mut dongle_cfg = psk_config();
+        mismatch(&mut dongle_cfg);
+        let mut keyboard = PskInitiator::new(&keyboard_cfg, PSK, [0x01; NONCE_BYTES]);
+        let mut dongle = PskResponder::new(&dongle_cfg, PSK, [0x02; NONCE_BYTES]);
+        let init = keyboard.start().expect("init frame");
+        assert_eq!(
+            dongle.respond(&init).map(|_| ()),
+            Err(HandshakeError::AuthFailed)
+        );
+    }
+}
+
+#[test]
+fn incompatible_initiator_gets_authenticated_reject() {
+    let keyboard_cfg = psk_config();
+    let mut dongle_cfg = psk_config();
+    dongle_cfg.max_payload_bytes = 16;
+
+    let mut keyboard = PskInitiator::new(&keyboard_cfg, PSK, [0x01; NONCE_BYTES]);
+    let mut dongle = PskResponder::new(&dongle_cfg, PSK, [0x02; NONCE_BYTES]);
+    let init = keyboard.start().expect("init frame");
+    let reply = match dongle.respond(&init) {
+        Err(HandshakeError::Rejected { reason, reply }) => {
+            assert_eq!(reason, Incompatibility::PayloadSize(16));
+            reply
+        }
+        other => panic!("expected rejection, got {:?}", other.map(|_| ())),
+    };
+
+    // A tampered reject is ignored like any forged reply.
+    let mut forged = reply.clone();
+    forged[HEADER_LEN + 2 + 5] ^= 0x01;
+    assert_eq!(
+        keyboard.finish(&forged).map(|_| ()),
+        Err(HandshakeError::AuthFailed)
+    );
+
+    assert_eq!(
+        keyboard.finish(&reply).map(|_| ()),
+        Err(HandshakeError::Incompatible(Incompatibility::PayloadSize(
+            16
+        )))
+    );
+    assert_eq!(
+        keyboard.finish(&reply).map(|_| ()),
+        Err(HandshakeError::UnexpectedMessage)
+    );
+}
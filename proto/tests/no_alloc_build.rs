@@ -0,0 +1,56 @@
+//! CI-style check: `proto` must build as plain `no_std` with neither `std` nor `alloc`, with and
+//! without `crypto`, so radio firmware can link it, real ciphers included, without a global
+//! allocator.
+
+use std::path::Path;
+use std::process::Command;
+
+/// Host triple from `rustc -vV`; the workspace default target (thumbv7em) may not be installed.
+fn host_triple() -> String {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let out = Command::new(rustc)
+        .arg("-vV")
+        .output()
+        .expect("run rustc -vV");
+    String::from_utf8(out.stdout)
+        .expect("utf-8")
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .expect("host line")
+        .to_string()
+}
+
+/// `cargo check` proto with only `features` enabled and warnings denied, in its own target dir.
+fn check_no_default_features(features: &str, target_dir: &str) -> bool {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(target_dir);
+    Command::new(env!("CARGO"))
+        .env("RUSTFLAGS", "-D warnings")
+        .args(["check", "--quiet", "-p", "proto", "--no-default-features"])
+        .args(["--features", features])
+        .arg("--manifest-path")
+        .arg(&manifest)
+        .arg("--target")
+        .arg(host_triple())
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("spawn cargo")
+        .success()
+}
+
+#[test]
+fn proto_builds_without_std_or_alloc() {
+    assert!(
+        check_no_default_features("", "no-alloc"),
+        "proto must build with --no-default-features (no std, no alloc)"
+    );
+}
+
+#[test]
+fn crypto_builds_without_std_or_alloc() {
+    assert!(
+        check_no_default_features("crypto", "no-alloc-crypto"),
+        "proto must build with --no-default-features --features crypto"
+    );
+}
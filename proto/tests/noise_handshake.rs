@@ -0,0 +1,137 @@
+#![cfg(feature = "crypto")]
+
+use proto::{
+    noise_public_key, open_framed, seal_framed, HandshakeError, Incompatibility, NoiseInitiator,
+    NoiseResponder, Packet, PacketFlags, PacketHeader, PacketKind, Payload, RealAead, HEADER_LEN,
+    KEY_BYTES, NONCE_BYTES,
+};
+
+const DONGLE_STATIC: [u8; KEY_BYTES] = [0x21; KEY_BYTES];
+const KEYBOARD_EPH: [u8; KEY_BYTES] = [0x34; KEY_BYTES];
+const DONGLE_EPH: [u8; KEY_BYTES] = [0x56; KEY_BYTES];
+
+#[test]
+fn noise_handshake_derives_matching_session() {
+    let cfg = proto::demo_config();
+    let session_id = 0x0B_AD_CA_FE;
+    let mut keyboard = NoiseInitiator::new(
+        &cfg,
+        noise_public_key(&DONGLE_STATIC),
+        KEYBOARD_EPH,
+        [0x07; NONCE_BYTES],
+    );
+    let mut dongle = NoiseResponder::new(&cfg, DONGLE_STATIC, DONGLE_EPH, session_id);
+
+    let init = keyboard.start().expect("init frame");
+    let (reply, dongle_session) = dongle.respond(&init).expect("responder accepts init");
+    let keyboard_session = keyboard.finish(&reply).expect("initiator accepts reply");
+
+    assert_eq!(keyboard_session.key, dongle_session.key);
+    assert_eq!(keyboard_session.session.salt, dongle_session.session.salt);
+    assert_eq!(keyboard_session.session.session_id, session_id);
+    assert_eq!(
+        keyboard_session.handshake_hash,
+        dongle_session.handshake_hash
+    );
+
+    // Derived material is ready for the data path.
+    let mut session = keyboard_session.session;
+    let counter = session.next_counter().expect("counter not exhausted");
+    let pkt = Packet {
+        header: PacketHeader {
+            session_id,
+            counter,
+            kind: PacketKind::KeyReport,
+            flags: PacketFlags {
+                encrypted: true,
+                needs_ack: true,
+                retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
+            },
+        },
+        payload: Payload::KeyReport { keys: vec![0x04] },
+        mac: vec![0xAA; cfg.security.mac_len],
+    };
+    let nonce = session.nonce_for(counter);
+    let sealed = seal_framed(&pkt, &cfg, &RealAead::new(keyboard_session.key), &nonce)
+        .expect("seal with handshake key");
+    let rx_nonce = dongle_session.session.rx_nonce_for(counter);
+    let opened = open_framed(&sealed, &cfg, &RealAead::new(dongle_session.key), &rx_nonce)
+        .expect("open with handshake key");
+    assert_eq!(opened.payload, pkt.payload);
+}
+
+#[test]
+fn noise_handshake_rejects_incompatible_config() {
+    let cfg = proto::demo_config();
+    let mut dongle_cfg = cfg;
+    dongle_cfg.max_payload_bytes = 16;
+
+    let mut keyboard = NoiseInitiator::new(
+        &cfg,
+        noise_public_key(&DONGLE_STATIC),
+        KEYBOARD_EPH,
+        [0x07; NONCE_BYTES],
+    );
+    let mut dongle = NoiseResponder::new(&dongle_cfg, DONGLE_STATIC, DONGLE_EPH, 1);
+
+    let init = keyboard.start().expect("init frame");
+    let reply = match dongle.respond(&init) {
+        Err(HandshakeError::Rejected { reason, reply }) => {
+            assert_eq!(reason, Incompatibility::PayloadSize(16));
+            reply
+        }
+        other => panic!("expected rejection, got {:?}", other.map(|_| ())),
+    };
+    assert_eq!(
+        keyboard.finish(&reply).map(|_| ()),
+        Err(HandshakeError::Incompatible(Incompatibility::PayloadSize(
+            16
+        )))
+    );
+}
+
+#[test]
+fn noise_handshake_rejects_unpaired_dongle() {
+    let cfg = proto::demo_config();
+    let mut keyboard = NoiseInitiator::new(
+        &cfg,
+        noise_public_key(&DONGLE_STATIC),
+        KEYBOARD_EPH,
+        [0x07; NONCE_BYTES],
+    );
+    let mut impostor = NoiseResponder::new(&cfg, [0x99; KEY_BYTES], DONGLE_EPH, 1);
+
+    let init = keyboard.start().expect("init frame");
+    assert!(matches!(
+        impostor.respond(&init),
+        Err(HandshakeError::AuthFailed)
+    ));
+}
+
+#[test]
+fn noise_handshake_survives_forged_reply() {
+    let cfg = proto::demo_config();
+    let mut keyboard = NoiseInitiator::new(
+        &cfg,
+        noise_public_key(&DONGLE_STATIC),
+        KEYBOARD_EPH,
+        [0x07; NONCE_BYTES],
+    );
+    let mut dongle = NoiseResponder::new(&cfg, DONGLE_STATIC, DONGLE_EPH, 0x42);
+
+    let init = keyboard.start().expect("init frame");
+    let (reply, _) = dongle.respond(&init).expect("responder accepts init");
+
+    let mut forged = reply.clone();
+    forged[HEADER_LEN + 2 + 1] ^= 0x01; // flip a bit of the responder ephemeral key
+    assert!(keyboard.finish(&forged).is_err());
+
+    // The genuine reply still completes the handshake.
+    assert!(keyboard.finish(&reply).is_ok());
+    assert!(matches!(
+        keyboard.finish(&reply),
+        Err(HandshakeError::UnexpectedMessage)
+    ));
+}
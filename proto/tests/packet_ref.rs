@@ -0,0 +1,62 @@
+use proto::{
+    parse_framed, parse_framed_ref, sample_packets, serialize_framed, ParseError, PayloadRef,
+    HEADER_LEN,
+};
+
+#[test]
+fn parse_framed_ref_matches_owned_parse_for_samples() {
+    let cfg = proto::demo_config();
+    for pkt in sample_packets(&cfg) {
+        let frame = serialize_framed(&pkt, &cfg).expect("frame");
+        let borrowed = parse_framed_ref(&frame, &cfg).expect("parse ref");
+        assert_eq!(
+            borrowed.to_owned(),
+            parse_framed(&frame, &cfg).expect("parse")
+        );
+        assert_eq!(borrowed.to_owned(), pkt);
+    }
+}
+
+#[test]
+fn dongle_forwards_key_bytes_straight_from_the_frame() {
+    let cfg = proto::demo_config();
+    let report = sample_packets(&cfg)
+        .into_iter()
+        .find(|p| matches!(p.payload, proto::Payload::KeyReport { .. }))
+        .expect("sample key report");
+    let frame = serialize_framed(&report, &cfg).expect("frame");
+
+    let parsed = parse_framed_ref(&frame, &cfg).expect("parse ref");
+    let PayloadRef::KeyReport { keys } = parsed.payload else {
+        panic!("expected a key report");
+    };
+    // Borrowed, not copied: the key bytes live right after header and length.
+    assert!(core::ptr::eq(
+        keys.as_ptr(),
+        frame[HEADER_LEN + 2..].as_ptr()
+    ));
+    assert!(core::ptr::eq(
+        parsed.mac.as_ptr(),
+        frame[frame.len() - cfg.security.mac_len..].as_ptr()
+    ));
+}
+
+#[test]
+fn parse_framed_ref_reports_the_same_errors() {
+    let cfg = proto::demo_config();
+    let pkt = &sample_packets(&cfg)[1];
+    let frame = serialize_framed(pkt, &cfg).expect("frame");
+
+    for bad in [&frame[..HEADER_LEN], &frame[..frame.len() - 1]] {
+        let owned = parse_framed(bad, &cfg).map(|_| ());
+        assert!(owned.is_err());
+        assert_eq!(parse_framed_ref(bad, &cfg).map(|_| ()), owned);
+    }
+
+    let mut unknown_kind = frame.clone();
+    unknown_kind[8] = 0x7F;
+    assert_eq!(
+        parse_framed_ref(&unknown_kind, &cfg).map(|_| ()),
+        Err(ParseError::UnknownKind(0x7F))
+    );
+}
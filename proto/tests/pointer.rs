@@ -0,0 +1,186 @@
+use proto::{
+    decode_payload, encode_payload, DummyAead, EncoderAccumulator, EncoderError, HidReport,
+    PacketKind, ParseError, Payload, PointerAccumulator, Role, Session, SessionKeys,
+    HID_REPORT_ID_MOUSE, MAX_BUTTON_TRANSITIONS, MAX_ENCODERS, MOUSE_BUTTON_LEFT,
+    MOUSE_BUTTON_RIGHT, SESSION_SALT_BYTES,
+};
+
+fn pointer(buttons: u8, dx: i16, dy: i16, wheel: i8, pan: i8) -> Payload {
+    Payload::Pointer {
+        buttons,
+        dx,
+        dy,
+        wheel,
+        pan,
+    }
+}
+
+#[test]
+fn pointer_and_encoder_payloads_round_trip() {
+    let motion = pointer(MOUSE_BUTTON_LEFT, -300, 2, -1, 1);
+    assert_eq!(motion.kind(), PacketKind::Pointer);
+    let bytes = encode_payload(&motion);
+    assert_eq!(bytes, vec![0x01, 0xD4, 0xFE, 0x02, 0x00, 0xFF, 0x01]);
+    assert_eq!(decode_payload(PacketKind::Pointer, &bytes), Ok(motion));
+
+    let turn = Payload::Encoder {
+        encoder: 1,
+        detents: -2,
+    };
+    assert_eq!(turn.kind(), PacketKind::Encoder);
+    assert_eq!(encode_payload(&turn), vec![0x01, 0xFE]);
+    assert_eq!(decode_payload(PacketKind::Encoder, &[0x01, 0xFE]), Ok(turn));
+
+    assert_eq!(
+        decode_payload(PacketKind::Pointer, &bytes[..6]),
+        Err(ParseError::UnexpectedLength)
+    );
+    assert_eq!(
+        decode_payload(PacketKind::Encoder, &[0x01]),
+        Err(ParseError::UnexpectedLength)
+    );
+}
+
+#[test]
+fn pointer_samples_coalesce_into_one_frame() {
+    let mut acc = PointerAccumulator::new();
+    assert_eq!(acc.take(), None);
+
+    for _ in 0..4 {
+        acc.push_motion(3, -1);
+    }
+    acc.push_scroll(1, 0);
+    acc.push_scroll(1, -1);
+    assert_eq!(acc.take(), Some(pointer(0, 12, -4, 2, -1)));
+    assert!(!acc.is_pending());
+    assert_eq!(acc.take(), None);
+}
+
+#[test]
+fn motion_beyond_wire_range_carries_over() {
+    let mut acc = PointerAccumulator::new();
+    for _ in 0..3 {
+        acc.push_motion(i16::MAX, 0);
+    }
+    for _ in 0..3 {
+        acc.push_scroll(-100, 0);
+    }
+    assert_eq!(acc.take(), Some(pointer(0, i16::MAX, 0, i8::MIN, 0)));
+    assert_eq!(acc.take(), Some(pointer(0, i16::MAX, 0, i8::MIN, 0)));
+    assert_eq!(acc.take(), Some(pointer(0, i16::MAX, 0, -44, 0)));
+    assert_eq!(acc.take(), None);
+}
+
+#[test]
+fn short_click_spans_two_frames() {
+    let mut acc = PointerAccumulator::new();
+    acc.push_motion(1, 0);
+    acc.set_buttons(MOUSE_BUTTON_LEFT);
+    acc.set_buttons(0);
+    acc.push_motion(1, 0);
+    assert_eq!(acc.take(), Some(pointer(MOUSE_BUTTON_LEFT, 2, 0, 0, 0)));
+    assert_eq!(acc.take(), Some(pointer(0, 0, 0, 0, 0)));
+    assert_eq!(acc.take(), None);
+
+    // Held buttons only go out when they change.
+    acc.set_buttons(MOUSE_BUTTON_RIGHT);
+    assert_eq!(acc.take(), Some(pointer(MOUSE_BUTTON_RIGHT, 0, 0, 0, 0)));
+    acc.set_buttons(MOUSE_BUTTON_RIGHT);
+    acc.push_motion(0, 5);
+    assert_eq!(acc.take(), Some(pointer(MOUSE_BUTTON_RIGHT, 0, 5, 0, 0)));
+
+    // Brief release of a held button is reported too.
+    acc.set_buttons(0);
+    acc.set_buttons(MOUSE_BUTTON_RIGHT);
+    assert_eq!(acc.take(), Some(pointer(0, 0, 0, 0, 0)));
+    assert_eq!(acc.take(), Some(pointer(MOUSE_BUTTON_RIGHT, 0, 0, 0, 0)));
+    assert_eq!(acc.take(), None);
+}
+
+#[test]
+fn double_click_within_one_frame_is_kept() {
+    let mut acc = PointerAccumulator::new();
+    acc.set_buttons(MOUSE_BUTTON_LEFT);
+    acc.set_buttons(0);
+    acc.set_buttons(MOUSE_BUTTON_LEFT);
+    acc.set_buttons(MOUSE_BUTTON_LEFT | MOUSE_BUTTON_RIGHT);
+    acc.set_buttons(MOUSE_BUTTON_RIGHT);
+    acc.push_motion(3, 0);
+
+    // Independent buttons share a frame; a second edge of the same button waits for the next.
+    assert_eq!(acc.take(), Some(pointer(MOUSE_BUTTON_LEFT, 3, 0, 0, 0)));
+    assert_eq!(acc.take(), Some(pointer(0, 0, 0, 0, 0)));
+    assert_eq!(
+        acc.take(),
+        Some(pointer(MOUSE_BUTTON_LEFT | MOUSE_BUTTON_RIGHT, 0, 0, 0, 0))
+    );
+    assert_eq!(acc.take(), Some(pointer(MOUSE_BUTTON_RIGHT, 0, 0, 0, 0)));
+    assert_eq!(acc.take(), None);
+
+    // A full queue keeps the final state instead of dropping it.
+    let mut acc = PointerAccumulator::new();
+    for click in 0..MAX_BUTTON_TRANSITIONS {
+        acc.set_buttons(if click % 2 == 0 { MOUSE_BUTTON_LEFT } else { 0 });
+    }
+    acc.set_buttons(MOUSE_BUTTON_LEFT);
+    let last = std::iter::from_fn(|| acc.take()).last();
+    assert_eq!(last, Some(pointer(MOUSE_BUTTON_LEFT, 0, 0, 0, 0)));
+}
+
+#[test]
+fn encoder_detents_sum_per_encoder() {
+    let mut acc = EncoderAccumulator::new();
+    acc.push(1, 1).expect("encoder 1");
+    acc.push(0, -1).expect("encoder 0");
+    acc.push(1, 1).expect("encoder 1");
+    acc.push(0, 1).expect("encoder 0");
+    assert_eq!(
+        acc.push(MAX_ENCODERS as u8, 1),
+        Err(EncoderError::InvalidEncoder(MAX_ENCODERS as u8))
+    );
+
+    // Encoder 0 turned back and forth: nothing to send for it.
+    assert_eq!(
+        acc.take(),
+        Some(Payload::Encoder {
+            encoder: 1,
+            detents: 2
+        })
+    );
+    assert!(!acc.is_pending());
+    assert_eq!(acc.take(), None);
+}
+
+#[test]
+fn dongle_forwards_pointer_as_mouse_report() {
+    let cfg = proto::demo_config();
+    let salt = [0x70; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(cfg, SessionKeys::new(0x50_4F_49_4E, salt), DummyAead);
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0x50_4F_49_4E, salt, Role::Dongle),
+        DummyAead,
+    );
+
+    let mut acc = PointerAccumulator::new();
+    acc.set_buttons(MOUSE_BUTTON_LEFT);
+    acc.push_motion(-2, 7);
+    acc.push_scroll(-1, 0);
+    let frame = keyboard.send(acc.take().expect("pending")).expect("send");
+    let payload = dongle.receive(&frame).expect("receive");
+
+    let report = HidReport::from_payload(&payload).expect("mouse report");
+    assert_eq!(
+        report.as_bytes(),
+        &[
+            HID_REPORT_ID_MOUSE,
+            0x01,
+            0xFE,
+            0xFF,
+            0x07,
+            0x00,
+            0xFF,
+            0x00
+        ]
+    );
+}
@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use proto::{
+    decode_payload, encode_payload, negotiate, pad_payload, padded_payload, Batcher, Capabilities,
+    CoverTraffic, DummyAead, KeyReport, PacketKind, PaddingError, ParseError, Payload, Reassembler,
+    Role, Session, SessionError, SessionKeys, ValidationError, CAP_PADDING,
+    PADDED_RECORD_HEADER_BYTES, SESSION_SALT_BYTES,
+};
+
+const SALT: [u8; SESSION_SALT_BYTES] = [0x9A; SESSION_SALT_BYTES];
+
+fn private_config() -> proto::ProtocolConfig {
+    let mut cfg = proto::demo_config();
+    cfg.features |= CAP_PADDING;
+    cfg
+}
+
+fn sessions(cfg: proto::ProtocolConfig) -> (Session<DummyAead>, Session<DummyAead>) {
+    (
+        Session::new(cfg, SessionKeys::new(0x9A9A, SALT), DummyAead),
+        Session::new(
+            cfg,
+            SessionKeys::for_role(0x9A9A, SALT, Role::Dongle),
+            DummyAead,
+        ),
+    )
+}
+
+fn reports() -> Vec<Payload> {
+    vec![
+        KeyReport::boot(0, &[]).expect("empty").to_payload(),
+        KeyReport::boot(0, &[0x04]).expect("one key").to_payload(),
+        KeyReport::boot(0, &[0x04, 0x05, 0x06, 0x07, 0x08, 0x09])
+            .expect("six keys")
+            .to_payload(),
+        Payload::ConsumerControl {
+            usage: proto::CONSUMER_VOLUME_UP,
+        },
+        Payload::Control {
+            code: 0x20,
+            data: vec![0x55; 12],
+        },
+        Payload::KeepAlive,
+    ]
+}
+
+#[test]
+fn padded_frames_all_look_alike() {
+    let (mut keyboard, mut dongle) = sessions(private_config());
+    let mut lengths = Vec::new();
+    for payload in reports() {
+        let frame = keyboard.send(payload.clone()).expect("send");
+        lengths.push(frame.len());
+        let (header, received) = dongle.receive_with_header(&frame).expect("delivered");
+        assert_eq!(
+            proto::decode_header(&frame[..proto::HEADER_LEN])
+                .unwrap()
+                .kind,
+            PacketKind::Padded
+        );
+        assert!(header.flags.needs_ack);
+        assert_eq!(header.kind, payload.kind());
+        assert_eq!(received, payload);
+    }
+    assert!(lengths.iter().all(|&len| len == lengths[0]));
+
+    // Without the capability, lengths and kinds give the reports away.
+    let (mut keyboard, _) = sessions(proto::demo_config());
+    let lengths: Vec<usize> = reports()
+        .into_iter()
+        .map(|p| keyboard.send(p).expect("send").len())
+        .collect();
+    assert!(lengths.iter().any(|&len| len != lengths[0]));
+
+    // Padding is only used when both sides offered it.
+    let cfg = private_config();
+    let plain = Capabilities::from_config(&proto::demo_config());
+    let (_, negotiated) = negotiate(&cfg, &plain).expect("compatible");
+    assert_eq!(negotiated.features & CAP_PADDING, 0);
+}
+
+#[test]
+fn padding_shrinks_the_room_for_data() {
+    let cfg = private_config();
+    let room = cfg.max_payload_bytes as usize - PADDED_RECORD_HEADER_BYTES;
+    let fits = Payload::Control {
+        code: 0x20,
+        data: vec![0; room - 1],
+    };
+    let padded = pad_payload(&cfg, &fits).expect("fits");
+    assert_eq!(
+        encode_payload(&padded).len(),
+        cfg.max_payload_bytes as usize
+    );
+    let too_big = Payload::Control {
+        code: 0x20,
+        data: vec![0; room],
+    };
+    assert_eq!(pad_payload(&cfg, &too_big), Err(PaddingError::TooLarge));
+    assert_eq!(pad_payload(&cfg, &padded), Err(PaddingError::NotPaddable));
+
+    // Fragments and batches are sized for the smaller room and still go through.
+    let (mut keyboard, mut dongle) = sessions(cfg);
+    let message: Vec<u8> = (0..100).collect();
+    let frames = keyboard.send_fragmented(0x20, &message).expect("send");
+    assert_eq!(frames.len(), 4);
+    let mut reassembler = Reassembler::new(&cfg);
+    let mut done = None;
+    for frame in &frames {
+        let (header, payload) = dongle.receive_with_header(frame).expect("delivered");
+        assert!(header.flags.is_fragment());
+        if let Some(message) = reassembler.push(&header, &payload, 0).expect("fragment") {
+            done = Some((message.code, message.data.to_vec()));
+        }
+    }
+    assert_eq!(done, Some((0x20, message.clone())));
+
+    let mut batcher = Batcher::new(&cfg, cfg.latency.target);
+    let volume = Payload::ConsumerControl {
+        usage: proto::CONSUMER_VOLUME_UP,
+    };
+    let batch = (0..8)
+        .find_map(|t| batcher.push(&volume, t).expect("batchable"))
+        .expect("frame fills up");
+    let frame = keyboard
+        .send(batch.clone())
+        .expect("batch fits the padded frame");
+    assert_eq!(dongle.receive(&frame), Ok(batch));
+}
+
+#[test]
+fn padding_fills_frames_larger_than_the_default() {
+    let mut cfg = private_config();
+    cfg.max_payload_bytes = 64;
+    let (mut keyboard, mut dongle) = sessions(cfg);
+    let message: Vec<u8> = (0..150).collect();
+    let frames = keyboard.send_fragmented(0x20, &message).expect("send");
+    assert_eq!(frames.len(), 3);
+    assert!(frames.iter().all(|f| f.len() == frames[0].len()));
+    let mut reassembler = Reassembler::new(&cfg);
+    let mut done = None;
+    for frame in &frames {
+        let (header, payload) = dongle.receive_with_header(frame).expect("delivered");
+        if let Some(message) = reassembler.push(&header, &payload, 0).expect("fragment") {
+            done = Some(message.data.to_vec());
+        }
+    }
+    assert_eq!(done, Some(message));
+}
+
+#[test]
+fn counter_resync_completes_with_padding() {
+    let (mut keyboard, mut dongle) = sessions(private_config());
+    let first = keyboard.send(reports()[1].clone()).expect("send");
+    dongle.receive(&first).expect("first");
+
+    keyboard.keys_mut().resume_from(5_000);
+    let jumped = keyboard.send(reports()[2].clone()).expect("send");
+    assert_eq!(
+        dongle.receive(&jumped),
+        Err(SessionError::Invalid(ValidationError::CounterJump))
+    );
+
+    // Request and response both travel padded; the response still re-anchors the dongle.
+    let request = dongle.poll_transmit().expect("resync request");
+    keyboard.receive(&request).expect("request opens");
+    let response = keyboard.poll_transmit().expect("resync response");
+    dongle.receive(&response).expect("response re-anchors");
+    assert!(dongle.poll_transmit().is_none());
+
+    let next = keyboard.send(reports()[0].clone()).expect("send");
+    assert_eq!(dongle.receive(&next), Ok(reports()[0].clone()));
+}
+
+#[test]
+fn malformed_padding_is_rejected() {
+    let cfg = private_config();
+    let Payload::Padded { record } = pad_payload(&cfg, &Payload::KeepAlive).expect("pad") else {
+        panic!("expected a padded payload");
+    };
+    assert_eq!(padded_payload(&record), Ok(proto::PayloadRef::KeepAlive));
+
+    let mut dirty = record.clone();
+    *dirty.last_mut().unwrap() = 1;
+    assert_eq!(
+        decode_payload(PacketKind::Padded, &dirty),
+        Err(ParseError::UnexpectedLength)
+    );
+    for kind in [PacketKind::Ack, PacketKind::Handshake, PacketKind::Padded] {
+        let mut bytes = record.clone();
+        bytes[0] = kind as u8;
+        assert_eq!(
+            decode_payload(PacketKind::Padded, &bytes),
+            Err(ParseError::NotPaddable(kind as u8))
+        );
+    }
+    assert_eq!(
+        decode_payload(PacketKind::Padded, &[PacketKind::Encoder as u8, 4, 0, 1]),
+        Err(ParseError::UnexpectedLength)
+    );
+    assert_eq!(
+        decode_payload(PacketKind::Padded, &[]),
+        Err(ParseError::UnexpectedLength)
+    );
+}
+
+#[test]
+fn cover_traffic_fills_every_slot() {
+    let cfg = private_config();
+    let target_ms = cfg.latency.target.as_millis() as u64;
+    let mut cover = CoverTraffic::new(&cfg, Duration::from_millis(100), 1000);
+    assert_eq!(cover.deadline_ms(), 1000 + target_ms);
+
+    let mut batcher = Batcher::new(&cfg, cfg.latency.target);
+    let key = KeyReport::boot(0, &[0x04]).expect("report").to_payload();
+    assert_eq!(cover.poll(1000 + target_ms - 1, || batcher.flush()), None);
+    assert_eq!(
+        cover.poll(1000 + target_ms, || batcher.flush()),
+        Some(Payload::KeepAlive)
+    );
+
+    // A report waits for the next slot and takes its place.
+    assert_eq!(batcher.push(&key, 1000 + target_ms + 1), Ok(None));
+    assert_eq!(cover.poll(1000 + target_ms + 2, || batcher.flush()), None);
+    assert_eq!(
+        cover.poll(1000 + 2 * target_ms, || batcher.flush()),
+        Some(key)
+    );
+
+    // Slots nobody polled for are skipped.
+    assert_eq!(
+        cover.poll(1000 + 5 * target_ms + 1, || batcher.flush()),
+        Some(Payload::KeepAlive)
+    );
+    assert_eq!(cover.deadline_ms(), 1000 + 6 * target_ms);
+}
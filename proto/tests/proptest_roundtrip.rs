@@ -2,8 +2,9 @@
 
 use proptest::prelude::*;
 use proto::{
-    decode_header, decode_payload, encode_header, encode_payload, parse_packet, serialize_packet,
-    Packet, PacketFlags, PacketHeader, PacketKind, Payload, ProtocolConfig, KEY_BYTES,
+    decode_header, decode_payload, encode_header, encode_payload, pad_payload, parse_framed,
+    parse_framed_ref, parse_packet, serialize_packet, Batcher, Capabilities, Packet, PacketFlags,
+    PacketHeader, PacketKind, Payload, ProtocolConfig, CAPABILITIES_BYTES, KEY_BYTES,
     MAX_MAC_BYTES, NONCE_BYTES,
 };
 
@@ -21,19 +22,34 @@ fn arb_header() -> impl Strategy<Value = PacketHeader> {
             Just(PacketKind::KeyReport),
             Just(PacketKind::Ack),
             Just(PacketKind::KeepAlive),
+            Just(PacketKind::ConsumerControl),
+            Just(PacketKind::SystemControl),
+            Just(PacketKind::Pointer),
+            Just(PacketKind::Encoder),
+            Just(PacketKind::Batch),
+            Just(PacketKind::Padded),
         ],
-        any::<(bool, bool, bool)>(),
+        any::<(bool, bool, bool, bool)>(),
+        0u8..16,
     )
-        .prop_map(|(session_id, counter, kind, (enc, ack, rt))| PacketHeader {
-            session_id,
-            counter,
-            kind,
-            flags: PacketFlags {
-                encrypted: enc,
-                needs_ack: ack,
-                retransmit: rt,
+        .prop_map(
+            |(session_id, counter, kind, (enc, ack, rt, more), index)| PacketHeader {
+                session_id,
+                counter,
+                kind,
+                flags: PacketFlags {
+                    encrypted: enc,
+                    needs_ack: ack,
+                    retransmit: rt,
+                    more_fragments: more,
+                    fragment_index: index,
+                },
             },
-        })
+        )
+}
+
+fn arb_caps() -> impl Strategy<Value = Capabilities> {
+    any::<[u8; CAPABILITIES_BYTES]>().prop_map(|bytes| Capabilities::from_bytes(&bytes))
 }
 
 fn arb_payload() -> impl Strategy<Value = Payload> {
@@ -45,11 +61,72 @@ fn arb_payload() -> impl Strategy<Value = Payload> {
             code: data[0],
             data: data.into_iter().skip(1).collect()
         }),
-        Just(Payload::Ack { ack_counter: 1 }),
-        Just(Payload::HandshakeInit {
+        Just(Payload::Ack {
+            ack_counter: 1,
+            sack: 0,
+            indicators: None
+        }),
+        any::<(u32, u32, u8)>().prop_map(|(ack_counter, sack, leds)| Payload::Ack {
+            ack_counter,
+            sack,
+            indicators: Some(leds)
+        }),
+        any::<(u32, u32)>().prop_map(|(ack_counter, sack)| Payload::Ack {
+            ack_counter,
+            sack,
+            indicators: None
+        }),
+        arb_caps().prop_map(|caps| Payload::HandshakeInit {
+            caps,
             eph_pubkey: [0u8; KEY_BYTES],
             nonce: [0u8; NONCE_BYTES],
         }),
+        (arb_caps(), any::<u32>()).prop_map(|(caps, session_id)| Payload::HandshakeResponse {
+            caps,
+            eph_pubkey: [0x5A; KEY_BYTES],
+            session_id,
+        }),
+        arb_caps().prop_map(|caps| Payload::PskInit {
+            caps,
+            nonce: [0x11; NONCE_BYTES]
+        }),
+        arb_caps().prop_map(|caps| Payload::PskResponse {
+            caps,
+            nonce: [0x22; NONCE_BYTES]
+        }),
+        arb_caps().prop_map(|caps| Payload::HandshakeReject { caps }),
+        any::<u16>().prop_map(|usage| Payload::ConsumerControl { usage }),
+        any::<u8>().prop_map(|usage| Payload::SystemControl { usage }),
+        any::<(u8, i16, i16, i8, i8)>().prop_map(|(buttons, dx, dy, wheel, pan)| {
+            Payload::Pointer {
+                buttons,
+                dx,
+                dy,
+                wheel,
+                pan,
+            }
+        }),
+        any::<(u8, i8)>().prop_map(|(encoder, detents)| Payload::Encoder { encoder, detents }),
+        prop::collection::vec(arb_batchable(), 2..6).prop_map(|reports| {
+            let mut batcher = Batcher::new(&cfg(), cfg().latency.target);
+            for report in &reports {
+                assert_eq!(batcher.push(report, 0), Ok(None));
+            }
+            batcher.flush().expect("held reports")
+        }),
+        arb_batchable().prop_map(|report| {
+            let mut cfg = cfg();
+            cfg.features |= proto::CAP_PADDING;
+            pad_payload(&cfg, &report).expect("fits")
+        }),
+    ]
+}
+
+fn arb_batchable() -> impl Strategy<Value = Payload> {
+    prop_oneof![
+        any::<u16>().prop_map(|usage| Payload::ConsumerControl { usage }),
+        any::<u8>().prop_map(|usage| Payload::SystemControl { usage }),
+        any::<(u8, i8)>().prop_map(|(encoder, detents)| Payload::Encoder { encoder, detents }),
     ]
 }
 
@@ -87,14 +164,34 @@ proptest! {
     }
 }
 
+proptest! {
+    #[test]
+    fn borrowed_parse_agrees_with_owned(bytes in prop::collection::vec(any::<u8>(), 0..96)) {
+        let cfg = cfg();
+        let owned = parse_framed(&bytes, &cfg);
+        let borrowed = parse_framed_ref(&bytes, &cfg).map(|p| p.to_owned());
+        prop_assert_eq!(borrowed, owned);
+    }
+}
+
 fn matches_valid_kind_payload(kind: PacketKind, p: &Payload) -> bool {
     matches!(
         (kind, p),
         (PacketKind::Handshake, Payload::HandshakeInit { .. })
             | (PacketKind::Handshake, Payload::HandshakeAccept { .. })
+            | (PacketKind::Handshake, Payload::HandshakeResponse { .. })
+            | (PacketKind::Handshake, Payload::PskInit { .. })
+            | (PacketKind::Handshake, Payload::PskResponse { .. })
+            | (PacketKind::Handshake, Payload::HandshakeReject { .. })
             | (PacketKind::Control, Payload::Control { .. })
             | (PacketKind::KeyReport, Payload::KeyReport { .. })
             | (PacketKind::Ack, Payload::Ack { .. })
             | (PacketKind::KeepAlive, Payload::KeepAlive)
+            | (PacketKind::ConsumerControl, Payload::ConsumerControl { .. })
+            | (PacketKind::SystemControl, Payload::SystemControl { .. })
+            | (PacketKind::Pointer, Payload::Pointer { .. })
+            | (PacketKind::Encoder, Payload::Encoder { .. })
+            | (PacketKind::Batch, Payload::Batch { .. })
+            | (PacketKind::Padded, Payload::Padded { .. })
     )
 }
@@ -0,0 +1,128 @@
+use proto::{
+    open_framed, seal_framed, DummyAead, HandshakeError, HandshakeKind, Packet, PacketFlags,
+    PacketHeader, PacketKind, Payload, PskInitiator, PskResponder, KEY_BYTES, NONCE_BYTES,
+};
+
+fn psk_config() -> proto::ProtocolConfig {
+    let mut cfg = proto::demo_config();
+    cfg.security.handshake = HandshakeKind::PreShared;
+    cfg.security.forward_secure = false;
+    cfg
+}
+
+#[test]
+fn psk_handshake_derives_session_from_provisioned_key() {
+    let cfg = psk_config();
+    let psk = [0x3C; KEY_BYTES];
+    let mut keyboard = PskInitiator::new(&cfg, psk, [0x01; NONCE_BYTES]);
+    let mut dongle = PskResponder::new(&cfg, psk, [0x02; NONCE_BYTES]);
+
+    let init = keyboard.start().expect("init frame");
+    let (reply, dongle_session) = dongle.respond(&init).expect("responder accepts init");
+    let keyboard_session = keyboard.finish(&reply).expect("initiator accepts reply");
+
+    assert_eq!(keyboard_session.key, dongle_session.key);
+    assert_eq!(keyboard_session.session.salt, dongle_session.session.salt);
+    assert_eq!(
+        keyboard_session.session.session_id,
+        dongle_session.session.session_id
+    );
+
+    let mut session = keyboard_session.session;
+    let session_id = session.session_id;
+    let counter = session.next_counter().expect("counter not exhausted");
+    let pkt = Packet {
+        header: PacketHeader {
+            session_id,
+            counter,
+            kind: PacketKind::KeyReport,
+            flags: PacketFlags {
+                encrypted: true,
+                needs_ack: true,
+                retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
+            },
+        },
+        payload: Payload::KeyReport { keys: vec![0x04] },
+        mac: vec![0xAA; cfg.security.mac_len],
+    };
+    let nonce = session.nonce_for(counter);
+    let sealed = seal_framed(&pkt, &cfg, &DummyAead, &nonce).expect("seal");
+    let rx_nonce = dongle_session.session.rx_nonce_for(counter);
+    let opened = open_framed(&sealed, &cfg, &DummyAead, &rx_nonce).expect("open");
+    assert_eq!(opened.payload, pkt.payload);
+}
+
+#[test]
+fn psk_handshake_fresh_nonces_give_fresh_sessions() {
+    let cfg = psk_config();
+    let psk = [0x3C; KEY_BYTES];
+
+    let mut first = PskInitiator::new(&cfg, psk, [0x01; NONCE_BYTES]);
+    let mut dongle = PskResponder::new(&cfg, psk, [0x02; NONCE_BYTES]);
+    let (reply, _) = dongle.respond(&first.start().unwrap()).unwrap();
+    let first = first.finish(&reply).unwrap();
+
+    let mut second = PskInitiator::new(&cfg, psk, [0x03; NONCE_BYTES]);
+    let mut dongle = PskResponder::new(&cfg, psk, [0x02; NONCE_BYTES]);
+    let (reply, _) = dongle.respond(&second.start().unwrap()).unwrap();
+    let second = second.finish(&reply).unwrap();
+
+    assert_ne!(first.key, second.key);
+    assert_ne!(first.session.salt, second.session.salt);
+    assert_ne!(first.session.session_id, second.session.session_id);
+}
+
+#[test]
+fn psk_handshake_rejects_wrong_key() {
+    let cfg = psk_config();
+    let mut keyboard = PskInitiator::new(&cfg, [0x3C; KEY_BYTES], [0x01; NONCE_BYTES]);
+    let mut dongle = PskResponder::new(&cfg, [0x3D; KEY_BYTES], [0x02; NONCE_BYTES]);
+
+    let init = keyboard.start().expect("init frame");
+    assert!(matches!(
+        dongle.respond(&init),
+        Err(HandshakeError::AuthFailed)
+    ));
+}
+
+#[test]
+fn psk_handshake_rejects_tampered_reply() {
+    let cfg = psk_config();
+    let psk = [0x3C; KEY_BYTES];
+    let mut keyboard = PskInitiator::new(&cfg, psk, [0x01; NONCE_BYTES]);
+    let mut dongle = PskResponder::new(&cfg, psk, [0x02; NONCE_BYTES]);
+
+    let init = keyboard.start().expect("init frame");
+    let (mut reply, _) = dongle.respond(&init).expect("responder accepts init");
+    let last = reply.len() - 1;
+    reply[last] ^= 0x80; // corrupt the tag
+
+    assert!(matches!(
+        keyboard.finish(&reply),
+        Err(HandshakeError::AuthFailed)
+    ));
+}
+
+#[test]
+fn forward_secure_config_cannot_use_psk() {
+    let psk = [0x3C; KEY_BYTES];
+    let mut strict = psk_config();
+    strict.security.forward_secure = true;
+
+    let mut keyboard = PskInitiator::new(&strict, psk, [0x01; NONCE_BYTES]);
+    assert!(matches!(
+        keyboard.start(),
+        Err(HandshakeError::NotForwardSecure)
+    ));
+
+    // A lenient keyboard's init is still refused by a forward-secure dongle.
+    let mut lenient = PskInitiator::new(&psk_config(), psk, [0x01; NONCE_BYTES]);
+    let init = lenient.start().expect("init frame");
+    let mut dongle = PskResponder::new(&strict, psk, [0x02; NONCE_BYTES]);
+    assert!(matches!(
+        dongle.respond(&init),
+        Err(HandshakeError::NotForwardSecure)
+    ));
+}
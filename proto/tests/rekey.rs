@@ -0,0 +1,305 @@
+#![cfg(feature = "crypto")]
+
+use proto::{
+    decode_header, open_framed, seal_framed, Packet, PacketFlags, PacketHeader, PacketKind,
+    Payload, RealAead, RekeyError, RekeyMessage, RekeyState, Role, Session, SessionError,
+    SessionKeys, COUNTER_REKEY_SOFT_LIMIT, HEADER_LEN, KEY_BYTES, SESSION_SALT_BYTES,
+};
+
+struct Side {
+    session: SessionKeys,
+    rekey: RekeyState,
+}
+
+impl Side {
+    fn new(role: Role) -> Self {
+        let cfg = proto::demo_config();
+        let session = SessionKeys::for_role(0x5E_55_10_4E, [0x77; SESSION_SALT_BYTES], role);
+        let rekey = RekeyState::new(&cfg, [0x42; KEY_BYTES], &session);
+        Self { session, rekey }
+    }
+
+    fn send(&mut self, kind: PacketKind, payload: Payload) -> Vec<u8> {
+        let cfg = proto::demo_config();
+        let counter = self.session.next_counter().expect("counter not exhausted");
+        let pkt = Packet {
+            header: PacketHeader {
+                session_id: self.session.session_id,
+                counter,
+                kind,
+                flags: PacketFlags {
+                    encrypted: true,
+                    needs_ack: false,
+                    retransmit: false,
+                    more_fragments: false,
+                    fragment_index: 0,
+                },
+            },
+            payload,
+            mac: vec![0; cfg.security.mac_len],
+        };
+        let keys = self.rekey.current();
+        let aead = RealAead::new(keys.key);
+        seal_framed(&pkt, &cfg, &aead, &self.session.nonce_for(counter)).expect("seal")
+    }
+
+    /// Open a peer frame under whichever epoch it belongs to; returns the packet and epoch.
+    fn receive(&self, frame: &[u8], now_ms: u64) -> Option<(Packet, u16)> {
+        let cfg = proto::demo_config();
+        let header = decode_header(&frame[..HEADER_LEN]).expect("header");
+        let rx = self.session.role().rx_direction();
+        self.rekey.rx_candidates(now_ms).find_map(|keys| {
+            let nonce = keys.nonce(rx, header.counter);
+            open_framed(frame, &cfg, &RealAead::new(keys.key), &nonce)
+                .ok()
+                .map(|pkt| (pkt, keys.epoch))
+        })
+    }
+
+    /// Open a peer frame, check it against that epoch's replay window, then record it.
+    fn accept(&mut self, frame: &[u8], now_ms: u64) -> Option<(Packet, u16)> {
+        let (pkt, epoch) = self.receive(frame, now_ms)?;
+        let counter = pkt.header.counter;
+        self.session
+            .replay_window_for(epoch)
+            .map(|window| window.check(counter))?
+            .ok()?;
+        self.session.record_rx_in(epoch, counter);
+        self.rekey
+            .on_authenticated(epoch, &mut self.session, now_ms);
+        Some((pkt, epoch))
+    }
+
+    /// Open any peer frame and feed it to the rekey state; returns the rekey reply to send.
+    fn handle(&mut self, frame: &[u8], now_ms: u64) -> Result<Option<Payload>, RekeyError> {
+        let (pkt, epoch) = self.receive(frame, now_ms).expect("frame opens");
+        let reply = self
+            .rekey
+            .on_authenticated(epoch, &mut self.session, now_ms);
+        match RekeyMessage::from_payload(&pkt.payload) {
+            Some(msg) => Ok(self
+                .rekey
+                .on_message(msg, epoch, &mut self.session, now_ms)?
+                .or(reply)),
+            None => Ok(reply),
+        }
+    }
+}
+
+fn key_report() -> Payload {
+    Payload::KeyReport { keys: vec![0x04] }
+}
+
+#[test]
+fn rekey_ratchets_both_sides_and_keeps_in_flight_frames() {
+    let mut keyboard = Side::new(Role::Keyboard);
+    let mut dongle = Side::new(Role::Dongle);
+
+    // Keyboard is close to the hard limit and starts a rekey.
+    keyboard.session.resume_from(COUNTER_REKEY_SOFT_LIMIT);
+    assert!(keyboard.session.needs_rekey());
+    let in_flight = keyboard.send(PacketKind::KeyReport, key_report());
+    let request = keyboard.rekey.begin().expect("begin rekey");
+    let request = keyboard.send(PacketKind::Control, request);
+
+    // Dongle switches and confirms under the new epoch.
+    let confirm = dongle
+        .handle(&request, 100)
+        .expect("request accepted")
+        .expect("confirm to send");
+    assert_eq!(dongle.rekey.current().epoch, 1);
+    assert_eq!(dongle.session.epoch(), 1);
+    let confirm = dongle.send(PacketKind::Control, confirm);
+
+    // The old-epoch report that was still in flight opens while the keyboard has not switched.
+    let (pkt, epoch) = dongle
+        .receive(&in_flight, 110)
+        .expect("old epoch still open");
+    assert_eq!((pkt.payload, epoch), (key_report(), 0));
+
+    // Keyboard sees the confirm under its pending epoch, switches too and confirms back.
+    let back = keyboard
+        .handle(&confirm, 105)
+        .expect("confirm accepted")
+        .expect("confirm back");
+    assert_eq!(
+        RekeyMessage::from_payload(&back),
+        Some(RekeyMessage::Confirm { epoch: 1 })
+    );
+    assert_eq!(keyboard.rekey.current(), dongle.rekey.current());
+    assert_ne!(keyboard.rekey.current().key, [0x42; KEY_BYTES]);
+    assert!(!keyboard.session.needs_rekey());
+    let back = keyboard.send(PacketKind::Control, back);
+    assert_eq!(dongle.handle(&back, 120), Ok(None));
+
+    // From here the retired epoch only lasts the grace window.
+    assert!(dongle.receive(&in_flight, 130).is_some());
+    assert!(dongle.receive(&in_flight, 10_000).is_none());
+
+    // New-epoch traffic flows with fresh counters.
+    let report = keyboard.send(PacketKind::KeyReport, key_report());
+    let (pkt, epoch) = dongle.receive(&report, 10_000).expect("new epoch");
+    assert_eq!((pkt.header.counter, epoch), (2, 1));
+}
+
+#[test]
+fn lost_confirms_do_not_strand_the_link() {
+    let mut keyboard = Side::new(Role::Keyboard);
+    let mut dongle = Side::new(Role::Dongle);
+
+    let request = keyboard.rekey.begin().expect("begin rekey");
+    let first = keyboard.send(PacketKind::Control, request.clone());
+    assert!(dongle.handle(&first, 0).expect("request").is_some());
+
+    // Every confirm is lost. Long past the grace window the retransmitted request still opens
+    // under the old epoch and is confirmed again.
+    let retry = keyboard.send(PacketKind::Control, request);
+    let again = dongle
+        .handle(&retry, 60_000)
+        .expect("retry")
+        .expect("confirm");
+    assert_eq!(
+        RekeyMessage::from_payload(&again),
+        Some(RekeyMessage::Confirm { epoch: 1 })
+    );
+    let old = keyboard.send(PacketKind::KeyReport, key_report());
+    assert!(dongle.receive(&old, 60_000).is_some());
+
+    // Any dongle frame under the new epoch moves the keyboard over, and it confirms back.
+    let report = dongle.send(PacketKind::KeyReport, key_report());
+    let back = keyboard
+        .handle(&report, 60_010)
+        .expect("new-epoch frame")
+        .expect("confirm back");
+    assert_eq!(keyboard.rekey.current().epoch, 1);
+    assert!(!keyboard.rekey.is_pending());
+    let back = keyboard.send(PacketKind::Control, back);
+    assert_eq!(dongle.handle(&back, 60_020), Ok(None));
+    assert!(dongle.receive(&old, 120_000).is_none());
+}
+
+#[test]
+fn rekey_request_retransmit_is_confirmed_again() {
+    let mut keyboard = Side::new(Role::Keyboard);
+    let mut dongle = Side::new(Role::Dongle);
+
+    let request = keyboard.rekey.begin().expect("begin rekey");
+    let first = keyboard.send(PacketKind::Control, request.clone());
+    let retry = keyboard.send(PacketKind::Control, request);
+
+    assert!(dongle.handle(&first, 0).expect("first").is_some());
+    // Confirm was lost; the retransmitted request arrives under the retired epoch.
+    let again = dongle.handle(&retry, 5).expect("retry").expect("confirm");
+    assert_eq!(
+        RekeyMessage::from_payload(&again),
+        Some(RekeyMessage::Confirm { epoch: 1 })
+    );
+    assert_eq!(dongle.rekey.current().epoch, 1);
+}
+
+#[test]
+fn rekey_rejects_skipped_epoch() {
+    let mut keyboard = Side::new(Role::Keyboard);
+    let mut dongle = Side::new(Role::Dongle);
+
+    let bogus = keyboard.send(
+        PacketKind::Control,
+        RekeyMessage::Request { epoch: 7 }.to_payload(),
+    );
+    assert_eq!(dongle.handle(&bogus, 0), Err(RekeyError::UnexpectedEpoch));
+    assert_eq!(dongle.rekey.current().epoch, 0);
+    assert_eq!(keyboard.rekey.begin().map(|_| ()), Ok(()));
+    assert_eq!(
+        keyboard.rekey.begin().map(|_| ()),
+        Err(RekeyError::AlreadyPending)
+    );
+}
+
+#[test]
+fn old_epoch_frames_keep_their_own_replay_window_during_grace() {
+    let mut keyboard = Side::new(Role::Keyboard);
+    let mut dongle = Side::new(Role::Dongle);
+
+    let in_flight = keyboard.send(PacketKind::KeyReport, key_report());
+    let request = keyboard.rekey.begin().expect("begin rekey");
+    let request = keyboard.send(PacketKind::Control, request);
+    let confirm = dongle
+        .handle(&request, 0)
+        .expect("request")
+        .expect("confirm");
+    let confirm = dongle.send(PacketKind::Control, confirm);
+    assert!(keyboard.handle(&confirm, 5).expect("confirm").is_some());
+
+    // New epoch counter 1 and old epoch counter 1 are different frames; both are accepted.
+    let report = keyboard.send(PacketKind::KeyReport, key_report());
+    let (pkt, epoch) = dongle.accept(&report, 10).expect("new epoch");
+    assert_eq!((pkt.header.counter, epoch), (1, 1));
+    let (pkt, epoch) = dongle.accept(&in_flight, 15).expect("grace window");
+    assert_eq!((pkt.header.counter, epoch), (1, 0));
+
+    // Replaying either frame inside the grace window is refused.
+    assert!(dongle.accept(&in_flight, 20).is_none());
+    assert!(dongle.accept(&report, 20).is_none());
+    assert_eq!(dongle.session.replay_window_for(2), None);
+}
+
+#[test]
+fn sessions_ratchet_keys_and_aead_in_band() {
+    let cfg = proto::demo_config();
+    let key = [0x42; KEY_BYTES];
+    let salt = [0x77; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(
+        cfg,
+        SessionKeys::new(0x5E_55_10_4F, salt),
+        RealAead::new(key),
+    );
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0x5E_55_10_4F, salt, Role::Dongle),
+        RealAead::new(key),
+    );
+    assert_eq!(
+        keyboard.begin_rekey().map(|_| ()),
+        Err(SessionError::Rekey(RekeyError::NotEnabled))
+    );
+    keyboard.enable_rekey(key).expect("rekeyable");
+    dongle.enable_rekey(key).expect("rekeyable");
+
+    let in_flight = keyboard.send(key_report()).expect("send");
+    let late = keyboard.send(key_report()).expect("send");
+    let request = keyboard.begin_rekey().expect("request");
+
+    // Dongle switches on the request and confirms under the new key.
+    assert!(dongle.receive(&request).is_ok());
+    let confirm = dongle.poll_transmit().expect("confirm");
+    assert_eq!(dongle.keys().epoch(), 1);
+
+    // Keyboard switches on the confirm and confirms back; the old report still opens.
+    assert!(keyboard.receive(&confirm).is_ok());
+    let back = keyboard.poll_transmit().expect("confirm back");
+    assert_eq!(keyboard.keys().epoch(), 1);
+    assert_eq!(
+        keyboard.rekey_state().map(|r| r.current()),
+        dongle.rekey_state().map(|r| r.current())
+    );
+    assert_eq!(dongle.receive(&in_flight), Ok(key_report()));
+    assert!(dongle.receive(&back).is_ok());
+    assert!(dongle.poll_transmit().is_none());
+
+    // New-epoch frames are sealed under the ratcheted key, not just a new nonce.
+    let report = keyboard.send(key_report()).expect("send");
+    let counter = decode_header(&report[..HEADER_LEN])
+        .expect("header")
+        .counter;
+    let old_key = RealAead::new(key);
+    let nonce = dongle.keys().rx_nonce_for(counter);
+    assert!(open_framed(&report, &cfg, &old_key, &nonce).is_err());
+    assert_eq!(dongle.receive(&report), Ok(key_report()));
+
+    // The retired epoch closes once the grace window after the switch has passed.
+    dongle.set_time(60_000);
+    assert!(matches!(
+        dongle.receive(&late),
+        Err(SessionError::Crypto(_))
+    ));
+}
@@ -0,0 +1,149 @@
+use proto::{
+    check_security_policy, seal_framed, validate_packet, Capabilities, DummyAead, KeyReport,
+    Packet, PacketFlags, PacketHeader, PacketKind, Payload, ReplayWindow, Role, Session,
+    SessionError, SessionKeys, ValidationError, NONCE_BYTES, SESSION_SALT_BYTES,
+};
+
+const SESSION_ID: u32 = 0x0D_0D;
+const SALT: [u8; SESSION_SALT_BYTES] = [0x4D; SESSION_SALT_BYTES];
+
+fn packet(kind: PacketKind, counter: u32, encrypted: bool, payload: Payload) -> Packet {
+    Packet {
+        header: PacketHeader {
+            session_id: SESSION_ID,
+            counter,
+            kind,
+            flags: PacketFlags {
+                encrypted,
+                needs_ack: kind.needs_ack(),
+                retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
+            },
+        },
+        payload,
+        mac: vec![0x5A; proto::MAX_MAC_BYTES],
+    }
+}
+
+fn key_a() -> Payload {
+    KeyReport::boot(0, &[0x04])
+        .expect("boot report")
+        .to_payload()
+}
+
+#[test]
+fn cleartext_frames_are_refused() {
+    let cfg = proto::demo_config();
+    let window = ReplayWindow::new();
+    let volume = Payload::ConsumerControl {
+        usage: proto::CONSUMER_VOLUME_UP,
+    };
+    for (kind, payload) in [
+        (PacketKind::KeyReport, key_a()),
+        (PacketKind::ConsumerControl, volume),
+    ] {
+        let injected = packet(kind, 1, false, payload);
+        assert_eq!(
+            validate_packet(&injected, &cfg, Some(SESSION_ID), Some(&window)),
+            Err(ValidationError::Unencrypted)
+        );
+    }
+    // Acks steer LEDs and retransmission, so they need encryption too.
+    let ack = packet(
+        PacketKind::Ack,
+        1,
+        false,
+        Payload::Ack {
+            ack_counter: 9,
+            sack: u32::MAX,
+            indicators: Some(proto::LED_CAPS_LOCK),
+        },
+    );
+    assert_eq!(
+        validate_packet(&ack, &cfg, Some(SESSION_ID), Some(&window)),
+        Err(ValidationError::Unencrypted)
+    );
+    let keep_alive = packet(PacketKind::KeepAlive, 1, false, Payload::KeepAlive);
+    assert_eq!(
+        validate_packet(&keep_alive, &cfg, Some(SESSION_ID), Some(&window)),
+        Err(ValidationError::Unencrypted)
+    );
+    let keep_alive = packet(PacketKind::KeepAlive, 1, true, Payload::KeepAlive);
+    assert_eq!(
+        validate_packet(&keep_alive, &cfg, Some(SESSION_ID), Some(&window)),
+        Ok(())
+    );
+
+    // Even a frame that authenticates is dropped when its header claims cleartext.
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(SESSION_ID, SALT, Role::Dongle),
+        DummyAead,
+    );
+    let nonce = SessionKeys::new(SESSION_ID, SALT).nonce_for(1);
+    let frame = seal_framed(
+        &packet(PacketKind::KeyReport, 1, false, key_a()),
+        &cfg,
+        &DummyAead,
+        &nonce,
+    )
+    .expect("seal");
+    assert_eq!(
+        dongle.receive(&frame),
+        Err(SessionError::Invalid(ValidationError::Unencrypted))
+    );
+    assert_eq!(dongle.keys().replay_window().highest(), None);
+}
+
+#[test]
+fn replay_protection_flag_is_honored() {
+    let mut cfg = proto::demo_config();
+    let report = packet(PacketKind::KeyReport, 7, true, key_a());
+    assert_eq!(
+        validate_packet(&report, &cfg, Some(SESSION_ID), None),
+        Err(ValidationError::NoReplayWindow)
+    );
+    assert_eq!(
+        check_security_policy(&report, &cfg, None),
+        Err(ValidationError::NoReplayWindow)
+    );
+
+    let mut window = ReplayWindow::new();
+    window.record(7);
+    assert_eq!(
+        validate_packet(&report, &cfg, Some(SESSION_ID), Some(&window)),
+        Err(ValidationError::ReplayDetected)
+    );
+
+    cfg.security.replay_protection = false;
+    assert_eq!(
+        validate_packet(&report, &cfg, Some(SESSION_ID), Some(&window)),
+        Ok(())
+    );
+    assert_eq!(
+        validate_packet(&report, &cfg, Some(SESSION_ID), None),
+        Ok(())
+    );
+}
+
+#[test]
+fn forward_secure_refuses_the_psk_handshake() {
+    let mut cfg = proto::demo_config();
+    let psk_init = packet(
+        PacketKind::Handshake,
+        0,
+        false,
+        Payload::PskInit {
+            caps: Capabilities::from_config(&cfg),
+            nonce: [0x01; NONCE_BYTES],
+        },
+    );
+    assert_eq!(
+        validate_packet(&psk_init, &cfg, None, None),
+        Err(ValidationError::NotForwardSecure)
+    );
+
+    cfg.security.forward_secure = false;
+    assert_eq!(validate_packet(&psk_init, &cfg, None, None), Ok(()));
+}
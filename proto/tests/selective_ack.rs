@@ -0,0 +1,163 @@
+use proto::{
+    decode_header, decode_payload, encode_payload, DummyAead, PacketKind, Payload, ReplayWindow,
+    RetransmitError, RetransmitQueue, Role, SelectiveAck, Session, SessionError, SessionKeys,
+    ValidationError, HEADER_LEN, LED_CAPS_LOCK, MAX_IN_FLIGHT, SACK_BITS, SESSION_SALT_BYTES,
+};
+
+fn counter_of(frame: &[u8]) -> u32 {
+    decode_header(&frame[..HEADER_LEN]).expect("header").counter
+}
+
+fn window(counters: &[u32]) -> ReplayWindow {
+    let mut window = ReplayWindow::new();
+    for &counter in counters {
+        window.record(counter);
+    }
+    window
+}
+
+#[test]
+fn ack_bitmap_is_optional_on_the_wire() {
+    let sack = SelectiveAck {
+        ack_counter: 9,
+        sack: 0b101,
+    };
+    let bytes = encode_payload(&sack.to_payload(None));
+    assert_eq!(bytes, vec![9, 0, 0, 0, 0b101, 0, 0, 0]);
+    assert_eq!(
+        decode_payload(PacketKind::Ack, &bytes),
+        Ok(sack.to_payload(None))
+    );
+
+    let with_leds = sack.to_payload(Some(LED_CAPS_LOCK));
+    let bytes = encode_payload(&with_leds);
+    assert_eq!(bytes, vec![9, 0, 0, 0, 0b101, 0, 0, 0, 0x02]);
+    assert_eq!(decode_payload(PacketKind::Ack, &bytes), Ok(with_leds));
+
+    // No bitmap: the plain four-byte Ack.
+    let plain = SelectiveAck {
+        ack_counter: 9,
+        sack: 0,
+    };
+    assert_eq!(encode_payload(&plain.to_payload(None)), vec![9, 0, 0, 0]);
+}
+
+#[test]
+fn ack_covers_cumulative_run_and_bitmap() {
+    assert_eq!(SelectiveAck::from_window(&ReplayWindow::new()), None);
+
+    let ack = SelectiveAck::from_window(&window(&[1, 2, 6, 4])).expect("ack");
+    assert_eq!(
+        ack,
+        SelectiveAck {
+            ack_counter: 2,
+            sack: 0b1010
+        }
+    );
+    assert_eq!(ack.highest(), 6);
+    assert!(ack.acks(1) && ack.acks(4) && ack.acks(6));
+    assert!(!ack.acks(3) && !ack.acks(5) && !ack.acks(7));
+}
+
+#[test]
+fn old_holes_do_not_pin_the_cumulative_counter() {
+    // Counter 1 never arrived; once it is SACK_BITS behind it counts as settled.
+    let counters: Vec<u32> = (2..=SACK_BITS + 8).collect();
+    let ack = SelectiveAck::from_window(&window(&counters)).expect("ack");
+    assert_eq!(
+        ack,
+        SelectiveAck {
+            ack_counter: SACK_BITS + 8,
+            sack: 0
+        }
+    );
+}
+
+#[test]
+fn only_missing_frames_are_resent() {
+    let cfg = proto::demo_config();
+    let salt = [0x5A; SESSION_SALT_BYTES];
+    let mut keyboard = Session::new(cfg, SessionKeys::new(0x5A_C4, salt), DummyAead);
+    let mut dongle = Session::new(
+        cfg,
+        SessionKeys::for_role(0x5A_C4, salt, Role::Dongle),
+        DummyAead,
+    );
+
+    let mut queue = RetransmitQueue::new();
+    let mut frames = Vec::new();
+    for key in 0x04..0x08 {
+        let frame = keyboard
+            .send(Payload::KeyReport { keys: vec![key] })
+            .expect("send");
+        queue
+            .push(counter_of(&frame), frame.clone())
+            .expect("room in flight");
+        frames.push(frame);
+    }
+
+    // The second frame is lost on air.
+    for frame in [&frames[0], &frames[2], &frames[3]] {
+        dongle.receive(frame).expect("delivered");
+    }
+    let ack_frame = dongle
+        .send(dongle.selective_ack().expect("ack").to_payload(None))
+        .expect("ack send");
+    let ack = SelectiveAck::from_payload(&keyboard.receive(&ack_frame).expect("ack receive"))
+        .expect("selective ack");
+
+    assert_eq!(queue.acknowledge(&ack), 3);
+    let resent: Vec<Vec<u8>> = queue.missing(&ack).cloned().collect();
+    assert_eq!(resent, vec![frames[1].clone()]);
+
+    dongle.receive(&resent[0]).expect("retransmit delivered");
+    let ack = dongle.selective_ack().expect("ack");
+    assert_eq!(queue.acknowledge(&ack), 1);
+    assert!(queue.is_empty());
+    assert_eq!(queue.lost(), 0);
+
+    // A duplicate is still re-acked from the window.
+    assert_eq!(
+        dongle.receive(&frames[0]),
+        Err(SessionError::Invalid(ValidationError::ReplayDetected))
+    );
+    assert_eq!(dongle.selective_ack(), Some(ack));
+}
+
+#[test]
+fn frames_in_flight_after_the_ack_are_not_resent() {
+    let mut queue = RetransmitQueue::new();
+    for counter in 1..=3 {
+        queue.push(counter, counter).expect("room");
+    }
+    let ack = SelectiveAck::from_window(&window(&[1])).expect("ack");
+    assert_eq!(queue.acknowledge(&ack), 1);
+    assert_eq!(queue.missing(&ack).count(), 0);
+    assert_eq!(queue.pending().collect::<Vec<_>>(), vec![2, 3]);
+
+    // Without any Ack, a timeout resends everything once, then gives up.
+    assert_eq!(queue.timed_out().copied().collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(queue.timed_out().count(), 0);
+    assert!(queue.is_empty());
+    assert_eq!(queue.lost(), 2);
+}
+
+#[test]
+fn queue_bounds_frames_in_flight() {
+    let mut queue = RetransmitQueue::new();
+    for counter in 1..=MAX_IN_FLIGHT as u32 {
+        queue.push(counter, ()).expect("room");
+    }
+    assert_eq!(
+        queue.push(MAX_IN_FLIGHT as u32 + 1, ()),
+        Err(RetransmitError::QueueFull)
+    );
+
+    // Frames SACK_BITS behind the newest are settled on the dongle side; stop waiting for them.
+    let mut queue = RetransmitQueue::new();
+    queue.push(1, ()).expect("room");
+    queue.push(2, ()).expect("room");
+    queue.push(1 + SACK_BITS, ()).expect("room");
+    assert_eq!(queue.pending().collect::<Vec<_>>(), vec![2, 1 + SACK_BITS]);
+    assert_eq!(queue.lost(), 1);
+}
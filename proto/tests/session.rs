@@ -0,0 +1,159 @@
+use proto::{
+    decode_header, CryptoError, DummyAead, Payload, Role, Session, SessionError, SessionKeys,
+    ValidationError, HEADER_LEN, SESSION_SALT_BYTES,
+};
+
+const SESSION_ID: u32 = 0x5E_55_10_11;
+
+fn link() -> (Session<DummyAead>, Session<DummyAead>) {
+    let cfg = proto::demo_config();
+    let salt = [0x29; SESSION_SALT_BYTES];
+    (
+        Session::new(cfg, SessionKeys::new(SESSION_ID, salt), DummyAead),
+        Session::new(
+            cfg,
+            SessionKeys::for_role(SESSION_ID, salt, Role::Dongle),
+            DummyAead,
+        ),
+    )
+}
+
+#[test]
+fn session_round_trips_both_directions() {
+    let (mut keyboard, mut dongle) = link();
+
+    let report = Payload::KeyReport {
+        keys: vec![0x04, 0x05],
+    };
+    let frame = keyboard.send(report.clone()).expect("send");
+    let (header, payload) = dongle.receive_with_header(&frame).expect("receive");
+    assert_eq!(payload, report);
+    assert_eq!(header.counter, 1);
+    assert!(header.flags.needs_ack);
+
+    let ack = dongle
+        .send(Payload::Ack {
+            ack_counter: header.counter,
+            sack: 0,
+            indicators: None,
+        })
+        .expect("ack");
+    assert_eq!(
+        keyboard.receive(&ack),
+        Ok(Payload::Ack {
+            ack_counter: 1,
+            sack: 0,
+            indicators: None
+        })
+    );
+
+    // Both ends count independently; the peer window tracks what it accepted.
+    assert_eq!(keyboard.keys().replay_window().highest(), Some(1));
+    assert_eq!(dongle.keys().replay_window().highest(), Some(1));
+}
+
+#[test]
+fn session_rejects_tampered_and_foreign_frames_without_recording() {
+    let (mut keyboard, mut dongle) = link();
+
+    let frame = keyboard
+        .send(Payload::KeyReport { keys: vec![0x04] })
+        .expect("send");
+    let mut tampered = frame.clone();
+    tampered[HEADER_LEN + 2] ^= 0x01;
+    assert!(matches!(
+        dongle.receive(&tampered),
+        Err(SessionError::Crypto(CryptoError::AuthFailed { .. }))
+    ));
+    assert_eq!(dongle.keys().replay_window().highest(), None);
+
+    let mut foreign = frame.clone();
+    foreign[0] ^= 0xFF;
+    assert_eq!(
+        dongle.receive(&foreign),
+        Err(SessionError::Invalid(ValidationError::SessionMismatch))
+    );
+
+    // Our own frame reflected back must not open: the direction is part of the nonce.
+    assert!(keyboard.receive(&frame).is_err());
+    assert!(dongle.receive(&frame).is_ok());
+}
+
+#[test]
+fn session_resyncs_after_counter_jump() {
+    let (mut keyboard, mut dongle) = link();
+
+    let first = keyboard
+        .send(Payload::KeyReport { keys: vec![0x04] })
+        .expect("send");
+    dongle.receive(&first).expect("first");
+
+    // Keyboard restored a stale counter from flash, far ahead of the dongle's window.
+    keyboard.keys_mut().resume_from(5_000);
+    let jumped = keyboard
+        .send(Payload::KeyReport { keys: vec![0x05] })
+        .expect("send");
+    assert_eq!(
+        dongle.receive(&jumped),
+        Err(SessionError::Invalid(ValidationError::CounterJump))
+    );
+
+    // Dongle challenges, keyboard answers on its own, dongle re-anchors.
+    let request = dongle.poll_transmit().expect("resync request");
+    keyboard.receive(&request).expect("request opens");
+    let response = keyboard.poll_transmit().expect("resync response");
+    dongle.receive(&response).expect("response re-anchors");
+    let anchor = decode_header(&response[..HEADER_LEN])
+        .expect("header")
+        .counter;
+    assert_eq!(dongle.keys().replay_window().highest(), Some(anchor));
+
+    let next = keyboard
+        .send(Payload::KeyReport { keys: vec![0x06] })
+        .expect("send");
+    assert_eq!(
+        dongle.receive(&next),
+        Ok(Payload::KeyReport { keys: vec![0x06] })
+    );
+    assert!(dongle.poll_transmit().is_none());
+}
+
+#[test]
+fn session_queues_every_frame_it_generates() {
+    let (mut keyboard, mut dongle) = link();
+    let report = Payload::KeyReport { keys: vec![0x04] };
+    dongle
+        .receive(&keyboard.send(report.clone()).expect("send"))
+        .expect("keyboard frame");
+    keyboard
+        .receive(&dongle.send(Payload::KeepAlive).expect("send"))
+        .expect("dongle frame");
+
+    // Both sides restored stale counters; each challenges the other.
+    dongle.keys_mut().resume_from(5_000);
+    let jumped = dongle.send(Payload::KeepAlive).expect("send");
+    assert!(keyboard.receive(&jumped).is_err());
+    let keyboard_request = keyboard.poll_transmit().expect("keyboard challenge");
+
+    keyboard.keys_mut().resume_from(9_000);
+    let jumped = keyboard.send(report.clone()).expect("send");
+    assert!(dongle.receive(&jumped).is_err());
+
+    // The dongle answers the keyboard's challenge before its own went out: both stay queued.
+    dongle.receive(&keyboard_request).expect("request opens");
+    let dongle_request = dongle.poll_transmit().expect("dongle challenge");
+    let dongle_response = dongle.poll_transmit().expect("dongle response");
+    assert!(dongle.poll_transmit().is_none());
+
+    keyboard
+        .receive(&dongle_response)
+        .expect("response re-anchors");
+    keyboard.receive(&dongle_request).expect("request opens");
+    let keyboard_response = keyboard.poll_transmit().expect("keyboard response");
+    dongle
+        .receive(&keyboard_response)
+        .expect("response re-anchors");
+
+    let next = keyboard.send(report.clone()).expect("send");
+    assert_eq!(dongle.receive(&next), Ok(report));
+}
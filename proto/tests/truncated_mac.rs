@@ -0,0 +1,123 @@
+#![cfg(feature = "crypto")]
+
+use proto::{
+    negotiate, open_framed, open_framed_in_place, Capabilities, CryptoError, Incompatibility,
+    Payload, RealAead, Role, SerializationError, Session, SessionError, SessionKeys, HEADER_LEN,
+    KEY_BYTES, MAX_MAC_BYTES, SESSION_SALT_BYTES,
+};
+
+const KEY: [u8; KEY_BYTES] = [0x7A; KEY_BYTES];
+const SALT: [u8; SESSION_SALT_BYTES] = [0x3D; SESSION_SALT_BYTES];
+
+fn config(mac_len: usize) -> proto::ProtocolConfig {
+    let mut cfg = proto::demo_config();
+    cfg.security.mac_len = mac_len;
+    cfg
+}
+
+fn keyboard(mac_len: usize) -> Session<RealAead> {
+    Session::new(
+        config(mac_len),
+        SessionKeys::new(0x7A61, SALT),
+        RealAead::new(KEY),
+    )
+}
+
+fn volume_up() -> Payload {
+    Payload::ConsumerControl {
+        usage: proto::CONSUMER_VOLUME_UP,
+    }
+}
+
+#[test]
+fn truncated_tag_is_a_prefix_of_the_full_tag() {
+    let full = keyboard(MAX_MAC_BYTES).send(volume_up()).expect("send");
+    let full_tag = &full[full.len() - MAX_MAC_BYTES..];
+
+    for mac_len in [8, 12] {
+        let frame = keyboard(mac_len).send(volume_up()).expect("send");
+        assert_eq!(frame.len(), full.len() - (MAX_MAC_BYTES - mac_len));
+        assert_eq!(
+            frame[..frame.len() - mac_len],
+            full[..full.len() - MAX_MAC_BYTES]
+        );
+        assert_eq!(&frame[frame.len() - mac_len..], &full_tag[..mac_len]);
+
+        let mut dongle = Session::new(
+            config(mac_len),
+            SessionKeys::for_role(0x7A61, SALT, Role::Dongle),
+            RealAead::new(KEY),
+        );
+        assert_eq!(dongle.receive(&frame), Ok(volume_up()));
+    }
+}
+
+#[test]
+fn truncated_tag_rejects_tampering() {
+    let aead = RealAead::new(KEY);
+    for mac_len in [8, 12] {
+        let cfg = config(mac_len);
+        let mut tx = keyboard(mac_len);
+        let frame = tx.send(volume_up()).expect("send");
+        let nonce = tx.keys().nonce_for(1);
+        assert!(open_framed(&frame, &cfg, &aead, &nonce).is_ok());
+
+        // Every bit of the header, ciphertext and tag is covered; the length field is
+        // checked against the frame before the AEAD runs.
+        for i in (0..frame.len()).filter(|i| !(HEADER_LEN..HEADER_LEN + 2).contains(i)) {
+            for bit in 0..8 {
+                let mut forged = frame.clone();
+                forged[i] ^= 1 << bit;
+                assert!(
+                    open_framed(&forged, &cfg, &aead, &nonce).is_err(),
+                    "mac_len {mac_len}: flip of byte {i} bit {bit} accepted"
+                );
+            }
+        }
+
+        // A rejected in-place open does not leave decrypted bytes behind.
+        let mut forged = frame.clone();
+        *forged.last_mut().unwrap() ^= 0x01;
+        let before = forged.clone();
+        assert_eq!(
+            open_framed_in_place(&mut forged, &cfg, &aead, &nonce).map(|_| ()),
+            Err(CryptoError::AuthFailed {
+                context: "RealAead::open"
+            })
+        );
+        assert_eq!(forged, before);
+    }
+}
+
+#[test]
+fn unsupported_tag_lengths_are_refused() {
+    assert_eq!(
+        keyboard(10).send(volume_up()),
+        Err(SessionError::Crypto(CryptoError::Serialize(
+            SerializationError::MacLengthMismatch
+        )))
+    );
+
+    let cfg = config(8);
+    let local = Capabilities::from_config(&cfg);
+    let (_, negotiated) = negotiate(
+        &cfg,
+        &Capabilities {
+            mac_len: 12,
+            ..local
+        },
+    )
+    .expect("both lengths are supported");
+    assert_eq!(negotiated.security.mac_len, 12);
+    assert_eq!(
+        negotiate(
+            &cfg,
+            &Capabilities {
+                mac_len: 10,
+                ..local
+            }
+        )
+        .map(|_| ()),
+        Err(Incompatibility::MacLength(10))
+    );
+}
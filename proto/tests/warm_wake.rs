@@ -1,6 +1,6 @@
 use proto::{
     open_framed, seal_framed, validate_packet, DummyAead, Packet, PacketFlags, PacketHeader,
-    PacketKind, Payload, SessionKeys, SESSION_SALT_BYTES,
+    PacketKind, Payload, ReplayWindow, SessionKeys, SESSION_SALT_BYTES,
 };
 
 #[test]
@@ -23,14 +23,22 @@ fn warm_wake_uses_cached_session() {
                 encrypted: true,
                 needs_ack: true,
                 retransmit: false,
+                more_fragments: false,
+                fragment_index: 0,
             },
         },
         payload: Payload::KeyReport { keys: vec![0x04] },
         mac: vec![0xAA; cfg.security.mac_len],
     };
 
-    // Last seen counter was 4 in the cached session; ensure validation accepts cached session.
-    validate_packet(&key_report, &cfg, Some(session_id), Some(counter - 1))
+    // Receiver persisted its replay window (last seen counter 4) before sleeping.
+    let mut before_sleep = ReplayWindow::new();
+    for seen in 1..=4 {
+        before_sleep.record(seen);
+    }
+    let persisted = before_sleep.to_bytes();
+    let window = ReplayWindow::from_bytes(&persisted);
+    validate_packet(&key_report, &cfg, Some(session_id), Some(&window))
         .expect("warm wake validate");
 
     let nonce = session.nonce_for(counter);